@@ -27,7 +27,7 @@ fn main() -> ! {
     // Configure I2C1
     let scl = gpiob.pb8.into_alternate_open_drain::<4>();
     let sda = gpiob.pb7.into_alternate_open_drain::<4>();
-    let mut i2c = hal::i2c::BlockingI2c::i2c1(
+    let mut i2c = hal::i2c::BlockingI2c::new(
         dp.I2C1,
         (scl, sda),
         hal::i2c::Mode::fast(100_000.Hz()),
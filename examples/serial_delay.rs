@@ -43,6 +43,7 @@ fn main() -> ! {
             baud_rate: 115_200.bps(),
             oversampling: serial::Oversampling::By16,
             character_match: None,
+            ..Default::default()
         },
     );
     let (mut tx, _) = serial.split();
@@ -50,6 +50,7 @@ fn main() -> ! {
             baud_rate: 115_200.bps(),
             oversampling: serial::Oversampling::By16,
             character_match: None,
+            ..Default::default()
         },
     );
     let (mut tx, mut rx) = serial.split();
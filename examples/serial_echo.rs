@@ -39,6 +39,7 @@ fn main() -> ! {
             baud_rate: 115_200.bps(),
             oversampling: serial::Oversampling::By16,
             character_match: None,
+            ..Default::default()
         },
     );
 
@@ -107,7 +107,7 @@ impl Mt25q {
         len: usize,
         dma: &Handle<DMA2, state::Enabled>,
         stream: Stream7<DMA2>,
-    ) -> TransferResources<RxTx<QUADSPI>, B>
+    ) -> TransferResources<RxTx<QUADSPI>, Stream7<DMA2>, B>
     where
         B: Deref + 'static,
         B::Target: AsSlice<Element = u8>,
@@ -146,7 +146,7 @@ impl Mt25q {
         len: usize,
         dma: &Handle<DMA2, state::Enabled>,
         stream: Stream7<DMA2>,
-    ) -> TransferResources<RxTx<QUADSPI>, B>
+    ) -> TransferResources<RxTx<QUADSPI>, Stream7<DMA2>, B>
     where
         B: Deref + 'static,
         B::Target: AsSlice<Element = u8>,
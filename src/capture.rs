@@ -0,0 +1,630 @@
+//! Input capture
+//!
+//! Channels 1 to 4 of the general-purpose and advanced-control timers can
+//! latch the value of the counter when an edge is detected on their input.
+//! [`InputCapture`] exposes these timestamps, extended in software to 64 bits
+//! so that they don't wrap when the counter overflows.
+//!
+//! ```rust
+//! let gpioa = dp.GPIOA.split();
+//! let pin = gpioa.pa0.into_alternate();
+//!
+//! let mut capture =
+//!     InputCapture::tim2(dp.TIM2, pin, 1.MHz(), Config::default(), clocks, &mut rcc.apb1).unwrap();
+//!
+//! let t0 = block!(capture.read(Channel::C1)).unwrap();
+//! let t1 = block!(capture.read(Channel::C1)).unwrap();
+//! let period_us = t1 - t0;
+//! ```
+//!
+//! [`PwmInput`] uses channels 1 and 2 together on the channel 1 pin, with the
+//! slave mode controller resetting the counter on every rising edge, to
+//! measure the period and the duty cycle of an external PWM signal. It is
+//! available on the timers with a slave mode controller (TIM1-TIM5, TIM8,
+//! TIM9 and TIM12).
+//!
+//! See chapters 23.3.6 and 23.3.7 in the STM32F746 Reference Manual.
+
+use core::marker::PhantomData;
+
+use crate::pac::{
+    TIM1, TIM10, TIM11, TIM12, TIM13, TIM14, TIM2, TIM3, TIM4, TIM5, TIM8, TIM9,
+};
+use crate::pwm::{Channel, PinC1, Pins};
+use crate::rcc::{Clocks, Enable, RccBus, Reset};
+use cast::u16;
+use fugit::HertzU32 as Hertz;
+
+pub use crate::pwm::{C1, C2, C3, C4};
+
+/// Edge of the input signal that triggers a capture (CCxP/CCxNP)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// Input prescaler (ICxPSC): number of edges per capture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Prescaler {
+    /// Capture on every edge
+    Div1 = 0b00,
+    /// Capture once every 2 edges
+    Div2 = 0b01,
+    /// Capture once every 4 edges
+    Div4 = 0b10,
+    /// Capture once every 8 edges
+    Div8 = 0b11,
+}
+
+/// Input filter (ICxF)
+///
+/// An edge is only validated once the input has been sampled `N` consecutive
+/// times at the new level. The sampling frequency is either the timer clock
+/// (`CkInt`) or a division of it (`Dts`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    NoFilter = 0b0000,
+    CkIntN2 = 0b0001,
+    CkIntN4 = 0b0010,
+    CkIntN8 = 0b0011,
+    DtsDiv2N6 = 0b0100,
+    DtsDiv2N8 = 0b0101,
+    DtsDiv4N6 = 0b0110,
+    DtsDiv4N8 = 0b0111,
+    DtsDiv8N6 = 0b1000,
+    DtsDiv8N8 = 0b1001,
+    DtsDiv16N5 = 0b1010,
+    DtsDiv16N6 = 0b1011,
+    DtsDiv16N8 = 0b1100,
+    DtsDiv32N5 = 0b1101,
+    DtsDiv32N6 = 0b1110,
+    DtsDiv32N8 = 0b1111,
+}
+
+/// Input channel configuration
+pub struct Config {
+    pub edge: Edge,
+    pub prescaler: Prescaler,
+    pub filter: Filter,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            edge: Edge::Rising,
+            prescaler: Prescaler::Div1,
+            filter: Filter::NoFilter,
+        }
+    }
+}
+
+/// Interrupt events
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A value has been captured on the channel
+    Capture(Channel),
+    /// The counter overflowed
+    Overflow,
+}
+
+/// Input capture errors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A capture happened before the previous one was read, so at least one
+    /// capture has been lost
+    Overcapture,
+    /// The counter overflowed before a full period was measured. The input
+    /// signal is either slower than the timer range or not toggling at all
+    Overflow,
+    /// The requested tick is zero, faster than the timer clock, or too slow
+    /// to reach with the 16-bit prescaler
+    InvalidTick,
+}
+
+/// A timer capturing edges on its input channels
+pub struct InputCapture<TIM, P, PINS> {
+    tim: TIM,
+    pins: PINS,
+    tick: Hertz,
+    overflows: u32,
+    _channels: PhantomData<P>,
+}
+
+/// A timer measuring the period and the duty cycle of a PWM signal on its
+/// channel 1 pin
+pub struct PwmInput<TIM, PIN> {
+    tim: TIM,
+    pin: PIN,
+    tick: Hertz,
+    valid: bool,
+}
+
+/// A measurement of a PWM signal, in timer ticks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PwmReading {
+    /// Time between two rising edges
+    pub period: u32,
+    /// Time between a rising edge and the next falling edge
+    pub duty: u32,
+}
+
+impl PwmReading {
+    /// Returns the frequency of the signal for a timer running at `tick`
+    pub fn frequency(&self, tick: Hertz) -> Hertz {
+        Hertz::from_raw(tick.raw() / self.period.max(1))
+    }
+}
+
+/// Computes the prescaler needed to count at `tick`
+fn compute_psc(clock: Hertz, tick: Hertz) -> Result<u16, Error> {
+    if tick.raw() == 0 || tick > clock {
+        return Err(Error::InvalidTick);
+    }
+
+    u16(clock.raw() / tick.raw() - 1).map_err(|_| Error::InvalidTick)
+}
+
+/// Extends a captured counter value to 64 bits
+///
+/// `overflows` is the number of counter overflows that have been accounted for
+/// so far, and `pending` tells whether a further overflow has been flagged but
+/// not accounted for yet. In that case, a small captured value was latched
+/// after the overflow and a large one before it.
+fn extend(value: u32, overflows: u32, pending: bool, range: u64) -> u64 {
+    let overflows = if pending && u64::from(value) < range / 2 {
+        u64::from(overflows) + 1
+    } else {
+        u64::from(overflows)
+    };
+
+    overflows * range + u64::from(value)
+}
+
+/// CCMRx bits configuring the input channel at `offset` in the register
+fn ccmr_bits(ccxs: u32, config: &Config, offset: u32) -> u32 {
+    (ccxs | ((config.prescaler as u32) << 2) | ((config.filter as u32) << 4)) << offset
+}
+
+/// CCER bits selecting the active edge of channel `ch`
+fn ccer_bits(edge: Edge, ch: u32) -> u32 {
+    let bits = match edge {
+        Edge::Rising => 0b0000,
+        Edge::Falling => 0b0010,
+        Edge::Both => 0b1010,
+    };
+
+    bits << (4 * ch)
+}
+
+macro_rules! capture {
+    ($(
+        $TIM:ident: ($tim:ident, $timclk:ident, $bits:ty, [
+            $($C:ident: ($ch:expr, $ccmrx:ident, $ccr:ident),)+
+        ]),
+    )+) => {
+        $(
+            impl<P, PINS> InputCapture<$TIM, P, PINS>
+            where
+                PINS: Pins<$TIM, P>,
+            {
+                /// Configures a TIM peripheral to capture the edges on `pins`
+                ///
+                /// The counter runs freely at a frequency as close as possible
+                /// to `tick`, and all channels use the same `config`. Returns
+                /// [`Error::InvalidTick`], if the timer can't count at `tick`.
+                pub fn $tim<T>(
+                    tim: $TIM,
+                    pins: PINS,
+                    tick: T,
+                    config: Config,
+                    clocks: Clocks,
+                    apb: &mut <$TIM as RccBus>::Bus,
+                ) -> Result<Self, Error>
+                where
+                    T: Into<Hertz>,
+                {
+                    let clock = clocks.$timclk();
+                    let psc = compute_psc(clock, tick.into())?;
+
+                    // enable and reset peripheral to a clean slate state
+                    <$TIM>::enable(apb);
+                    <$TIM>::reset(apb);
+
+                    #[allow(unused_unsafe)]
+                    tim.psc.write(|w| unsafe { w.bits(u32::from(psc)) });
+                    #[allow(unused_unsafe)]
+                    tim.arr.write(|w| unsafe { w.bits(<$bits>::MAX as u32) });
+
+                    // Only counter overflows set the update flag (URS)
+                    tim.cr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << 2)) });
+
+                    // Trigger an update event to load the prescaler value to the clock
+                    tim.egr.write(|w| w.ug().set_bit());
+
+                    let mut capture = InputCapture {
+                        tim,
+                        pins,
+                        tick: clock / (u32::from(psc) + 1),
+                        overflows: 0,
+                        _channels: PhantomData,
+                    };
+
+                    $(
+                        if PINS::$C {
+                            capture.configure(Channel::$C, &config);
+                        }
+                    )+
+
+                    capture.tim.sr.write(|w| unsafe { w.bits(0) });
+                    capture.tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    Ok(capture)
+                }
+
+                /// Changes the configuration of a single channel
+                pub fn configure(&mut self, channel: Channel, config: &Config) {
+                    let ch = Self::channel_index(channel);
+                    let offset = 8 * (ch % 2);
+
+                    // Disable the capture while reconfiguring the channel
+                    self.tim.ccer.modify(|r, w| unsafe { w.bits(r.bits() & !(0b1111 << (4 * ch))) });
+
+                    // CCxS = 01: ICx is mapped on TIx
+                    match channel {
+                        $(
+                            Channel::$C => self.tim.$ccmrx().modify(|r, w| unsafe {
+                                w.bits((r.bits() & !(0xff << offset)) | ccmr_bits(0b01, config, offset))
+                            }),
+                        )+
+                        #[allow(unreachable_patterns)]
+                        _ => unreachable!(),
+                    }
+
+                    self.tim.ccer.modify(|r, w| unsafe {
+                        w.bits(r.bits() | ccer_bits(config.edge, ch) | (1 << (4 * ch)))
+                    });
+                }
+
+                /// Returns the frequency at which the counter runs
+                pub fn tick(&self) -> Hertz {
+                    self.tick
+                }
+
+                /// Returns the latest captured timestamp of `channel`, in ticks
+                ///
+                /// Counter overflows are accounted for as long as they are
+                /// noticed at least once per counter period, either by calling
+                /// this method or [`Self::handle_overflow`].
+                ///
+                /// # Panics
+                ///
+                /// Panics if no pin has been provided for `channel`.
+                pub fn read(&mut self, channel: Channel) -> nb::Result<u64, Error> {
+                    let ch = Self::channel_index(channel);
+                    let sr = self.tim.sr.read().bits();
+
+                    if sr & (1 << (ch + 1)) == 0 {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    // NOTE reading CCRx clears CCxIF
+                    let value = match channel {
+                        $(Channel::$C => self.tim.$ccr.read().bits(),)+
+                        #[allow(unreachable_patterns)]
+                        _ => unreachable!(),
+                    };
+
+                    let pending = sr & 1 != 0;
+                    let timestamp = extend(value, self.overflows, pending, u64::from(<$bits>::MAX) + 1);
+                    if pending {
+                        self.handle_overflow();
+                    }
+
+                    if sr & (1 << (ch + 9)) != 0 {
+                        self.tim.sr.write(|w| unsafe { w.bits(!(1 << (ch + 9))) });
+                        return Err(nb::Error::Other(Error::Overcapture));
+                    }
+
+                    Ok(timestamp)
+                }
+
+                /// Accounts for a counter overflow
+                ///
+                /// Call this from the timer interrupt handler when listening for
+                /// [`Event::Overflow`] if the captures can be further apart than
+                /// the counter period.
+                pub fn handle_overflow(&mut self) {
+                    if self.tim.sr.read().bits() & 1 != 0 {
+                        self.tim.sr.write(|w| unsafe { w.bits(!1) });
+                        self.overflows = self.overflows.wrapping_add(1);
+                    }
+                }
+
+                /// Starts listening for an `event`
+                pub fn listen(&mut self, event: Event) {
+                    let mask = Self::event_mask(event);
+                    self.tim.dier.modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+                }
+
+                /// Stops listening for an `event`
+                pub fn unlisten(&mut self, event: Event) {
+                    let mask = Self::event_mask(event);
+                    self.tim.dier.modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+                }
+
+                /// Stops the timer and releases the TIM peripheral and pins
+                pub fn free(self) -> ($TIM, PINS) {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+
+                    (self.tim, self.pins)
+                }
+
+                fn channel_index(channel: Channel) -> u32 {
+                    match channel {
+                        $(Channel::$C if PINS::$C => $ch,)+
+                        _ => panic!("channel {:?} has no input pin", channel),
+                    }
+                }
+
+                fn event_mask(event: Event) -> u32 {
+                    match event {
+                        Event::Capture(channel) => 1 << (Self::channel_index(channel) + 1),
+                        Event::Overflow => 1,
+                    }
+                }
+            }
+        )+
+    }
+}
+
+capture! {
+    TIM2: (tim2, timclk1, u32, [
+        C1: (0, ccmr1_input, ccr1),
+        C2: (1, ccmr1_input, ccr2),
+        C3: (2, ccmr2_input, ccr3),
+        C4: (3, ccmr2_input, ccr4),
+    ]),
+    TIM3: (tim3, timclk1, u16, [
+        C1: (0, ccmr1_input, ccr1),
+        C2: (1, ccmr1_input, ccr2),
+        C3: (2, ccmr2_input, ccr3),
+        C4: (3, ccmr2_input, ccr4),
+    ]),
+    TIM4: (tim4, timclk1, u16, [
+        C1: (0, ccmr1_input, ccr1),
+        C2: (1, ccmr1_input, ccr2),
+        C3: (2, ccmr2_input, ccr3),
+        C4: (3, ccmr2_input, ccr4),
+    ]),
+    TIM5: (tim5, timclk1, u32, [
+        C1: (0, ccmr1_input, ccr1),
+        C2: (1, ccmr1_input, ccr2),
+        C3: (2, ccmr2_input, ccr3),
+        C4: (3, ccmr2_input, ccr4),
+    ]),
+    TIM12: (tim12, timclk1, u16, [
+        C1: (0, ccmr1_input, ccr1),
+        C2: (1, ccmr1_input, ccr2),
+    ]),
+    TIM13: (tim13, timclk1, u16, [
+        C1: (0, ccmr1_input, ccr1),
+    ]),
+    TIM14: (tim14, timclk1, u16, [
+        C1: (0, ccmr1_input, ccr1),
+    ]),
+
+    TIM1: (tim1, timclk2, u16, [
+        C1: (0, ccmr1_input, ccr1),
+        C2: (1, ccmr1_input, ccr2),
+        C3: (2, ccmr2_input, ccr3),
+        C4: (3, ccmr2_input, ccr4),
+    ]),
+    TIM8: (tim8, timclk2, u16, [
+        C1: (0, ccmr1_input, ccr1),
+        C2: (1, ccmr1_input, ccr2),
+        C3: (2, ccmr2_input, ccr3),
+        C4: (3, ccmr2_input, ccr4),
+    ]),
+    TIM9: (tim9, timclk2, u16, [
+        C1: (0, ccmr1_input, ccr1),
+        C2: (1, ccmr1_input, ccr2),
+    ]),
+    TIM10: (tim10, timclk2, u16, [
+        C1: (0, ccmr1_input, ccr1),
+    ]),
+    TIM11: (tim11, timclk2, u16, [
+        C1: (0, ccmr1_input, ccr1),
+    ]),
+}
+
+macro_rules! pwm_input {
+    ($($TIM:ident: ($tim:ident, $timclk:ident, $bits:ty),)+) => {
+        $(
+            impl<PIN> PwmInput<$TIM, PIN>
+            where
+                PIN: PinC1<$TIM>,
+            {
+                /// Configures a TIM peripheral to measure the PWM signal on `pin`
+                ///
+                /// The counter runs at a frequency as close as possible to
+                /// `tick`, which bounds both the resolution and the longest
+                /// period that can be measured. The edge and the prescaler of
+                /// `config` are ignored. Returns [`Error::InvalidTick`], if the
+                /// timer can't count at `tick`.
+                pub fn $tim<T>(
+                    tim: $TIM,
+                    pin: PIN,
+                    tick: T,
+                    config: Config,
+                    clocks: Clocks,
+                    apb: &mut <$TIM as RccBus>::Bus,
+                ) -> Result<Self, Error>
+                where
+                    T: Into<Hertz>,
+                {
+                    let clock = clocks.$timclk();
+                    let psc = compute_psc(clock, tick.into())?;
+
+                    // enable and reset peripheral to a clean slate state
+                    <$TIM>::enable(apb);
+                    <$TIM>::reset(apb);
+
+                    #[allow(unused_unsafe)]
+                    tim.psc.write(|w| unsafe { w.bits(u32::from(psc)) });
+                    #[allow(unused_unsafe)]
+                    tim.arr.write(|w| unsafe { w.bits(<$bits>::MAX as u32) });
+
+                    // Only counter overflows, and not the resets from the slave
+                    // mode controller, set the update flag (URS)
+                    tim.cr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << 2)) });
+
+                    // Trigger an update event to load the prescaler value to the clock
+                    tim.egr.write(|w| w.ug().set_bit());
+
+                    // IC1 and IC2 are both mapped on TI1 (CC1S = 01, CC2S = 10)
+                    let config = Config {
+                        prescaler: Prescaler::Div1,
+                        ..config
+                    };
+                    tim.ccmr1_input().write(|w| unsafe {
+                        w.bits(ccmr_bits(0b01, &config, 0) | ccmr_bits(0b10, &config, 8))
+                    });
+
+                    // IC1 captures the rising edges and IC2 the falling ones
+                    tim.ccer.write(|w| unsafe {
+                        w.bits(ccer_bits(Edge::Rising, 0) | ccer_bits(Edge::Falling, 1) | 0b1_0001)
+                    });
+
+                    // Reset the counter on TI1FP1 (TS = 101, SMS = 100)
+                    tim.smcr.write(|w| unsafe { w.bits((0b101 << 4) | 0b100) });
+
+                    tim.sr.write(|w| unsafe { w.bits(0) });
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    Ok(PwmInput {
+                        tim,
+                        pin,
+                        tick: clock / (u32::from(psc) + 1),
+                        valid: false,
+                    })
+                }
+
+                /// Returns the frequency at which the counter runs
+                pub fn tick(&self) -> Hertz {
+                    self.tick
+                }
+
+                /// Returns the latest complete measurement
+                ///
+                /// Returns [`Error::Overflow`] when no rising edge was seen for a
+                /// whole counter period. The first period after an overflow is
+                /// discarded, as it hasn't been measured from its start.
+                pub fn read(&mut self) -> nb::Result<PwmReading, Error> {
+                    let sr = self.tim.sr.read().bits();
+
+                    if sr & 1 != 0 {
+                        // Clear UIF, CC1IF, CC2IF, CC1OF and CC2OF
+                        self.tim.sr.write(|w| unsafe { w.bits(!0b110_0000_0111) });
+                        self.valid = false;
+                        return Err(nb::Error::Other(Error::Overflow));
+                    }
+
+                    if sr & (1 << 1) == 0 {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    // NOTE reading CCR1 clears CC1IF. Missed periods are not
+                    // an error here, only the latest one is of interest
+                    let period = self.tim.ccr1.read().bits();
+                    let duty = self.tim.ccr2.read().bits();
+                    self.tim.sr.write(|w| unsafe { w.bits(!0b110_0000_0000) });
+
+                    if !self.valid {
+                        self.valid = true;
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    Ok(PwmReading { period, duty })
+                }
+
+                /// Starts listening for an `event`
+                ///
+                /// [`Event::Capture`] is only meaningful for [`Channel::C1`],
+                /// which signals a new measurement.
+                pub fn listen(&mut self, event: Event) {
+                    let mask = match event {
+                        Event::Capture(_) => 1 << 1,
+                        Event::Overflow => 1,
+                    };
+                    self.tim.dier.modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+                }
+
+                /// Stops listening for an `event`
+                pub fn unlisten(&mut self, event: Event) {
+                    let mask = match event {
+                        Event::Capture(_) => 1 << 1,
+                        Event::Overflow => 1,
+                    };
+                    self.tim.dier.modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+                }
+
+                /// Stops the timer and releases the TIM peripheral and pin
+                pub fn free(self) -> ($TIM, PIN) {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+
+                    (self.tim, self.pin)
+                }
+            }
+        )+
+    }
+}
+
+pwm_input! {
+    TIM2: (tim2, timclk1, u32),
+    TIM3: (tim3, timclk1, u16),
+    TIM4: (tim4, timclk1, u16),
+    TIM5: (tim5, timclk1, u32),
+    TIM12: (tim12, timclk1, u16),
+
+    TIM1: (tim1, timclk2, u16),
+    TIM8: (tim8, timclk2, u16),
+    TIM9: (tim9, timclk2, u16),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fugit::RateExtU32;
+
+    #[test]
+    fn test_extend() {
+        const RANGE: u64 = 1 << 16;
+
+        assert_eq!(extend(100, 0, false, RANGE), 100);
+        assert_eq!(extend(100, 3, false, RANGE), 3 * RANGE + 100);
+        // Captured right after the pending overflow
+        assert_eq!(extend(100, 3, true, RANGE), 4 * RANGE + 100);
+        // Captured right before the pending overflow
+        assert_eq!(extend(0xfff0, 3, true, RANGE), 3 * RANGE + 0xfff0);
+    }
+
+    #[test]
+    fn test_ccer_bits() {
+        assert_eq!(ccer_bits(Edge::Rising, 0), 0);
+        assert_eq!(ccer_bits(Edge::Falling, 1), 0b0010_0000);
+        assert_eq!(ccer_bits(Edge::Both, 2), 0b1010_0000_0000);
+    }
+
+    #[test]
+    fn test_compute_psc() {
+        assert_eq!(compute_psc(108.MHz(), 1.MHz()), Ok(107));
+        assert_eq!(compute_psc(108.MHz(), 108.MHz()), Ok(0));
+        // The slowest tick reachable with the 16-bit prescaler
+        assert_eq!(compute_psc(65_536.Hz(), 1.Hz()), Ok(0xffff));
+        assert_eq!(compute_psc(108.MHz(), 1.kHz()), Err(Error::InvalidTick));
+        assert_eq!(compute_psc(108.MHz(), 216.MHz()), Err(Error::InvalidTick));
+        assert_eq!(compute_psc(108.MHz(), 0.Hz()), Err(Error::InvalidTick));
+    }
+}
@@ -7,6 +7,9 @@ use cortex_m::peripheral::SYST;
 use crate::hal::blocking::delay::{DelayMs, DelayUs};
 use crate::rcc::Clocks;
 
+#[cfg(feature = "eh1")]
+mod hal_1;
+
 /// System timer (SysTick) as a delay provider
 pub struct Delay {
     clocks: Clocks,
@@ -25,33 +28,12 @@ impl Delay {
     pub fn free(self) -> SYST {
         self.syst
     }
-}
-
-impl DelayMs<u32> for Delay {
-    fn delay_ms(&mut self, ms: u32) {
-        self.delay_us(ms * 1_000);
-    }
-}
 
-impl DelayMs<u16> for Delay {
-    fn delay_ms(&mut self, ms: u16) {
-        self.delay_ms(u32(ms));
-    }
-}
-
-impl DelayMs<u8> for Delay {
-    fn delay_ms(&mut self, ms: u8) {
-        self.delay_ms(u32(ms));
-    }
-}
-
-impl DelayUs<u32> for Delay {
-    fn delay_us(&mut self, us: u32) {
+    /// Busy waits for `total_rvr` SysTick ticks (HCLK / 8)
+    fn delay_ticks(&mut self, mut total_rvr: u32) {
         // The SysTick Reload Value register supports values between 1 and 0x00FFFFFF.
         const MAX_RVR: u32 = 0x00FF_FFFF;
 
-        let mut total_rvr = us * (self.clocks.hclk().raw() / 8_000_000);
-
         while total_rvr != 0 {
             let current_rvr = if total_rvr <= MAX_RVR {
                 total_rvr
@@ -73,6 +55,30 @@ impl DelayUs<u32> for Delay {
     }
 }
 
+impl DelayMs<u32> for Delay {
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay_us(ms * 1_000);
+    }
+}
+
+impl DelayMs<u16> for Delay {
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(u32(ms));
+    }
+}
+
+impl DelayMs<u8> for Delay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(u32(ms));
+    }
+}
+
+impl DelayUs<u32> for Delay {
+    fn delay_us(&mut self, us: u32) {
+        self.delay_ticks(us * (self.clocks.hclk().raw() / 8_000_000));
+    }
+}
+
 impl DelayUs<u16> for Delay {
     fn delay_us(&mut self, us: u16) {
         self.delay_us(u32(us))
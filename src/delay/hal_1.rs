@@ -0,0 +1,24 @@
+use super::Delay;
+
+use embedded_hal_one::delay::DelayNs;
+
+impl DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        // Round up, so that the delay is never shorter than requested
+        let ticks =
+            (u64::from(ns) * u64::from(self.clocks.hclk().raw() / 8) + 999_999_999) / 1_000_000_000;
+
+        // `ns` is below 4.3 s, which fits in a `u32` for HCLK up to 8 GHz
+        self.delay_ticks(ticks as u32);
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        crate::hal::blocking::delay::DelayUs::delay_us(self, us);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        for _ in 0..ms {
+            crate::hal::blocking::delay::DelayUs::delay_us(self, 1_000u32);
+        }
+    }
+}
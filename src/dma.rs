@@ -3,14 +3,19 @@
 use core::{
     fmt,
     marker::PhantomData,
-    ops::Deref,
+    ops::{Deref, DerefMut},
     pin::Pin,
     sync::atomic::{self, Ordering},
 };
 
-use as_slice::AsSlice;
+use as_slice::{AsMutSlice, AsSlice};
+#[cfg(feature = "async")]
+use atomic_waker::AtomicWaker;
+#[cfg(feature = "async")]
+use core::task::Poll;
 
 use crate::{
+    i2c,
     pac::{
         self,
         dma2::{self, st::cr},
@@ -18,7 +23,7 @@ use crate::{
     },
     qspi,
     rcc::{Enable, RccBus, Reset},
-    serial, spi, state,
+    sai, serial, spi, state,
 };
 
 /// Entry point to the DMA API
@@ -81,14 +86,15 @@ where
 ///
 /// Peripheral APIs that support DMA have methods like `write_all` and
 /// `read_all`, which return instances of this struct.
-pub struct Transfer<T: Target, B, State> {
-    res: TransferResources<T, B>,
+pub struct Transfer<T, S: Stream, B, State> {
+    res: TransferResources<T, S, B>,
     _state: State,
 }
 
-impl<T, B> Transfer<T, B, Ready>
+impl<T, S, B> Transfer<T, S, B, Ready>
 where
-    T: Target,
+    T: Target<S>,
+    S: Stream,
     B: 'static,
 {
     /// Internal constructor to create a new `Transfer`
@@ -104,8 +110,8 @@ where
     /// If this method is used to prepare a peripheral-to-memory transfer, the
     /// caller must make sure that the buffer can be written to safely.
     pub(crate) unsafe fn new<Word>(
-        handle: &Handle<T::Instance, state::Enabled>,
-        stream: T::Stream,
+        handle: &Handle<S::Instance, state::Enabled>,
+        stream: S,
         buffer: Pin<B>,
         target: T,
         address: u32,
@@ -118,94 +124,14 @@ where
     {
         assert!(buffer.len() <= u16::max_value() as usize);
 
-        // The following configuration procedure is documented in the reference
-        // manual for STM32F75xxx and STM32F74xxx, section 8.3.18.
-
-        let nr = T::Stream::number();
-
-        // Disable stream
-        handle.dma.st[nr].cr.modify(|_, w| w.en().disabled());
-        while handle.dma.st[nr].cr.read().en().is_enabled() {}
-
-        T::Stream::clear_status_flags(&handle.dma);
-
-        // Set peripheral port register address
-        handle.dma.st[nr].par.write(|w| w.pa().bits(address));
-
-        // Set memory address
-        let memory_address = buffer.as_ptr() as u32;
-        handle.dma.st[nr]
-            .m0ar
-            .write(|w| w.m0a().bits(memory_address));
-
-        // Write number of data items to transfer
-        //
-        // We've asserted that `data.len()` fits into a `u16`, so the cast
-        // should be fine.
-        handle.dma.st[nr]
-            .ndtr
-            .write(|w| w.ndt().bits(buffer.len() as u16));
-
-        // Configure FIFO
-        handle.dma.st[nr].fcr.modify(|_, w| {
-            w
-                // Interrupt disabled
-                .feie()
-                .disabled()
-                // Direct mode enabled (FIFO disabled)
-                .dmdis()
-                .enabled()
-        });
-
-        // Select channel
-        handle.dma.st[nr].cr.write(|w| {
-            let w = T::Channel::select(w);
-
-            let w = match direction {
-                Direction::MemoryToPeripheral => w.dir().memory_to_peripheral(),
-                Direction::PeripheralToMemory => w.dir().peripheral_to_memory(),
-            };
-
-            w
-                // Single transfer
-                .mburst()
-                .single()
-                .pburst()
-                .single()
-                // Double-buffer mode disabled
-                .dbm()
-                .disabled()
-                // Very high priority
-                .pl()
-                .very_high()
-                // Memory data size
-                .msize()
-                .variant(Word::msize())
-                // Peripheral data size
-                .psize()
-                .variant(Word::psize())
-                // Memory increment mode
-                .minc()
-                .incremented()
-                // Peripheral increment mode
-                .pinc()
-                .fixed()
-                // Circular mode disabled
-                .circ()
-                .disabled()
-                // DMA is the flow controller
-                .pfctrl()
-                .dma()
-                // All interrupts disabled
-                .tcie()
-                .disabled()
-                .htie()
-                .disabled()
-                .teie()
-                .disabled()
-                .dmeie()
-                .disabled()
-        });
+        configure::<S, T::Channel, Word>(
+            &handle.dma,
+            address,
+            buffer.as_ptr() as u32,
+            buffer.len(),
+            direction,
+            Mode::Normal,
+        );
 
         Transfer {
             res: TransferResources {
@@ -217,6 +143,14 @@ where
         }
     }
 
+    /// Configures the FIFO and bursts of the DMA stream for this transfer
+    ///
+    /// By default, transfers use direct mode and single transfers. See
+    /// [`Config`] for the supported combinations.
+    pub fn set_config(&mut self, handle: &Handle<S::Instance, state::Enabled>, config: Config) {
+        set_config::<S>(&handle.dma, config);
+    }
+
     /// Enables the given interrupts for this DMA transfer
     ///
     /// These interrupts are only enabled for this transfer. The settings
@@ -224,52 +158,18 @@ where
     /// DMA stream.
     pub fn enable_interrupts(
         &mut self,
-        handle: &Handle<T::Instance, state::Enabled>,
+        handle: &Handle<S::Instance, state::Enabled>,
         interrupts: Interrupts,
     ) {
-        handle.dma.st[T::Stream::number()].cr.modify(|_, w| {
-            let w = if interrupts.transfer_complete {
-                w.tcie().enabled()
-            } else {
-                w
-            };
-
-            let w = if interrupts.half_transfer {
-                w.htie().enabled()
-            } else {
-                w
-            };
-
-            let w = if interrupts.transfer_error {
-                w.teie().enabled()
-            } else {
-                w
-            };
-
-            let w = if interrupts.direct_mode_error {
-                w.dmeie().enabled()
-            } else {
-                w
-            };
-
-            w
-        });
-
-        // Enable interrupt.
-        unsafe { NVIC::unmask(T::INTERRUPT) };
+        enable_interrupts::<S>(&handle.dma, interrupts);
     }
 
     /// Start the DMA transfer
     ///
     /// Consumes this instance of `Transfer` and returns another instance with
     /// its type state set to indicate the transfer has been started.
-    pub fn start(self, handle: &Handle<T::Instance, state::Enabled>) -> Transfer<T, B, Started> {
-        T::Stream::clear_status_flags(&handle.dma);
-        atomic::fence(Ordering::SeqCst);
-
-        handle.dma.st[T::Stream::number()]
-            .cr
-            .modify(|_, w| w.en().enabled());
+    pub fn start(self, handle: &Handle<S::Instance, state::Enabled>) -> Transfer<T, S, B, Started> {
+        start::<S>(&handle.dma);
 
         Transfer {
             res: self.res,
@@ -278,24 +178,23 @@ where
     }
 }
 
-impl<T, B> Transfer<T, B, Started>
+impl<T, S, B> Transfer<T, S, B, Started>
 where
-    T: Target,
+    S: Stream,
 {
     /// Checks whether the transfer is still ongoing
-    pub fn is_active(&self, handle: &Handle<T::Instance, state::Enabled>) -> bool {
-        handle.dma.st[T::Stream::number()]
-            .cr
-            .read()
-            .en()
-            .is_enabled()
+    pub fn is_active(&self, handle: &Handle<S::Instance, state::Enabled>) -> bool {
+        handle.dma.st[S::number()].cr.read().en().is_enabled()
+    }
+
+    /// Returns the number of data items that are still to be transferred
+    pub fn remaining(&self, handle: &Handle<S::Instance, state::Enabled>) -> usize {
+        handle.dma.st[S::number()].ndtr.read().ndt().bits() as usize
     }
 
     /// Try to cancel an in process transfer. Check is_active to verify cancellation
-    pub fn cancel(&self, handle: &Handle<T::Instance, state::Enabled>) {
-        handle.dma.st[T::Stream::number()]
-            .cr
-            .write(|w| w.en().disabled());
+    pub fn cancel(&self, handle: &Handle<S::Instance, state::Enabled>) {
+        handle.dma.st[S::number()].cr.write(|w| w.en().disabled());
     }
 
     /// Waits for the transfer to end
@@ -310,21 +209,21 @@ where
     /// method returns those resources, so they can be used again.
     pub fn wait(
         self,
-        handle: &Handle<T::Instance, state::Enabled>,
-    ) -> Result<TransferResources<T, B>, (TransferResources<T, B>, Error)> {
+        handle: &Handle<S::Instance, state::Enabled>,
+    ) -> Result<TransferResources<T, S, B>, (TransferResources<T, S, B>, Error)> {
         // Disable interrupt.
-        NVIC::mask(T::INTERRUPT);
+        NVIC::mask(S::INTERRUPT);
 
         // Wait for transfer to finish
         while self.is_active(handle) {
-            if let Err(error) = Error::check::<T::Stream>(&handle.dma) {
+            if let Err(error) = Error::check::<S>(&handle.dma) {
                 return Err((self.res, error));
             }
         }
 
         atomic::fence(Ordering::SeqCst);
 
-        if let Err(error) = Error::check::<T::Stream>(&handle.dma) {
+        if let Err(error) = Error::check::<S>(&handle.dma) {
             return Err((self.res, error));
         }
 
@@ -332,9 +231,866 @@ where
     }
 }
 
+#[cfg(feature = "async")]
+impl<T, S, B> Transfer<T, S, B, Started>
+where
+    S: Stream,
+{
+    /// Waits asynchronously for the transfer to end
+    ///
+    /// This is the `async` version of [`Transfer::wait`]. The transfer must
+    /// have been started with the transfer complete and transfer error
+    /// interrupts enabled (see [`Transfer::enable_interrupts`]), and the
+    /// stream interrupt handler must call [`on_interrupt`].
+    ///
+    /// If the returned future is dropped before the transfer ends, the
+    /// transfer is cancelled.
+    pub async fn wait_async(
+        self,
+        handle: &Handle<S::Instance, state::Enabled>,
+    ) -> Result<TransferResources<T, S, B>, (TransferResources<T, S, B>, Error)> {
+        let guard = CancelOnDrop {
+            dma: &handle.dma,
+            nr: S::number(),
+        };
+
+        let result = core::future::poll_fn(|cx| {
+            waker(S::INTERRUPT).register(cx.waker());
+
+            if let Err(error) = Error::check::<S>(&handle.dma) {
+                return Poll::Ready(Err(error));
+            }
+            if !self.is_active(handle) {
+                return Poll::Ready(Ok(()));
+            }
+
+            // Re-enable the interrupt masked by `on_interrupt`
+            unsafe { NVIC::unmask(S::INTERRUPT) };
+            Poll::Pending
+        })
+        .await;
+
+        core::mem::forget(guard);
+        NVIC::mask(S::INTERRUPT);
+        atomic::fence(Ordering::SeqCst);
+
+        match result {
+            Ok(()) => Ok(self.res),
+            Err(error) => Err((self.res, error)),
+        }
+    }
+}
+
+/// Disables a stream when an async transfer is dropped before its end
+#[cfg(feature = "async")]
+struct CancelOnDrop<'a> {
+    dma: &'a dma2::RegisterBlock,
+    nr: usize,
+}
+
+#[cfg(feature = "async")]
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        self.dma.st[self.nr].cr.modify(|_, w| w.en().disabled());
+        while self.dma.st[self.nr].cr.read().en().is_enabled() {}
+    }
+}
+
+#[cfg(feature = "async")]
+static WAKERS: [AtomicWaker; 16] = {
+    const NEW: AtomicWaker = AtomicWaker::new();
+    [NEW; 16]
+};
+
+/// Returns the waker slot of the stream raising `interrupt`
+#[cfg(feature = "async")]
+fn waker(interrupt: Interrupt) -> &'static AtomicWaker {
+    let index = match interrupt {
+        Interrupt::DMA1_STREAM0 => 0,
+        Interrupt::DMA1_STREAM1 => 1,
+        Interrupt::DMA1_STREAM2 => 2,
+        Interrupt::DMA1_STREAM3 => 3,
+        Interrupt::DMA1_STREAM4 => 4,
+        Interrupt::DMA1_STREAM5 => 5,
+        Interrupt::DMA1_STREAM6 => 6,
+        Interrupt::DMA1_STREAM7 => 7,
+        Interrupt::DMA2_STREAM0 => 8,
+        Interrupt::DMA2_STREAM1 => 9,
+        Interrupt::DMA2_STREAM2 => 10,
+        Interrupt::DMA2_STREAM3 => 11,
+        Interrupt::DMA2_STREAM4 => 12,
+        Interrupt::DMA2_STREAM5 => 13,
+        Interrupt::DMA2_STREAM6 => 14,
+        Interrupt::DMA2_STREAM7 => 15,
+        _ => panic!("{:?} is not a DMA stream interrupt", interrupt),
+    };
+
+    &WAKERS[index]
+}
+
+/// Wakes the task waiting for a transfer on the stream raising `interrupt`
+///
+/// Call this from the interrupt handler of every DMA stream used with the
+/// `async` APIs, e.g. `dma::on_interrupt(Interrupt::DMA2_STREAM0)` from
+/// `DMA2_STREAM0`. The interrupt is masked until the waiting task has been
+/// polled again, so the status flags don't need to be cleared here.
+#[cfg(feature = "async")]
+pub fn on_interrupt(interrupt: Interrupt) {
+    NVIC::mask(interrupt);
+    waker(interrupt).wake();
+}
+
+/// How a stream moves through its memory buffers
+#[derive(Clone, Copy)]
+enum Mode {
+    /// Transfer the buffer once
+    Normal,
+    /// Transfer the buffer over and over again
+    Circular,
+    /// Alternate between the buffer and the one at the given address
+    DoubleBuffer(u32),
+}
+
+/// Configures stream `S` for a transfer, leaving it disabled
+///
+/// # Safety
+///
+/// `memory_address` and `len` must define a valid memory region, that can be
+/// accessed as required by `direction` for as long as the stream is enabled.
+unsafe fn configure<S, C, Word>(
+    dma: &dma2::RegisterBlock,
+    address: u32,
+    memory_address: u32,
+    len: usize,
+    direction: Direction,
+    mode: Mode,
+) where
+    S: Stream,
+    C: Channel,
+    Word: SupportedWordSize,
+{
+    // The following configuration procedure is documented in the reference
+    // manual for STM32F75xxx and STM32F74xxx, section 8.3.18.
+
+    let nr = S::number();
+
+    // Disable stream
+    dma.st[nr].cr.modify(|_, w| w.en().disabled());
+    while dma.st[nr].cr.read().en().is_enabled() {}
+
+    S::clear_status_flags(dma);
+
+    // Set peripheral port register address
+    dma.st[nr].par.write(|w| w.pa().bits(address));
+
+    // Set memory address
+    dma.st[nr].m0ar.write(|w| w.m0a().bits(memory_address));
+    if let Mode::DoubleBuffer(memory_address) = mode {
+        dma.st[nr].m1ar.write(|w| w.m1a().bits(memory_address));
+    }
+
+    // Write number of data items to transfer
+    //
+    // The callers assert that `len` fits into a `u16`, so the cast should
+    // be fine.
+    dma.st[nr].ndtr.write(|w| w.ndt().bits(len as u16));
+
+    // Configure FIFO
+    dma.st[nr].fcr.modify(|_, w| {
+        // Interrupt disabled
+        let w = w.feie().disabled();
+
+        match direction {
+            // Direct mode is not allowed for memory-to-memory transfers
+            Direction::MemoryToMemory { .. } => {
+                w.dmdis().disabled().fth().bits(FifoThreshold::Full.bits())
+            }
+            // Direct mode enabled (FIFO disabled)
+            _ => w.dmdis().enabled(),
+        }
+    });
+
+    // Select channel
+    dma.st[nr].cr.write(|w| {
+        let w = C::select(w);
+
+        let w = match direction {
+            Direction::MemoryToPeripheral => w.dir().memory_to_peripheral(),
+            Direction::PeripheralToMemory => w.dir().peripheral_to_memory(),
+            Direction::MemoryToMemory { .. } => w.dir().memory_to_memory(),
+        };
+
+        let w = w
+            // Single transfer
+            .mburst()
+            .single()
+            .pburst()
+            .single()
+            // Double-buffer mode disabled, unless selected below
+            .dbm()
+            .disabled()
+            // Very high priority
+            .pl()
+            .very_high()
+            // Memory data size
+            .msize()
+            .variant(Word::msize())
+            // Peripheral data size
+            .psize()
+            .variant(Word::psize())
+            // Memory increment mode
+            .minc()
+            .incremented()
+            // Peripheral increment mode, unless selected below
+            .pinc()
+            .fixed()
+            // Circular mode disabled, unless selected below
+            .circ()
+            .disabled()
+            // DMA is the flow controller
+            .pfctrl()
+            .dma()
+            // All interrupts disabled
+            .tcie()
+            .disabled()
+            .htie()
+            .disabled()
+            .teie()
+            .disabled()
+            .dmeie()
+            .disabled();
+
+        let w = match mode {
+            Mode::Normal => w,
+            Mode::Circular => w.circ().enabled(),
+            // Double-buffer mode implies circular mode
+            Mode::DoubleBuffer(_) => w.dbm().enabled().circ().enabled(),
+        };
+
+        match direction {
+            // The peripheral port addresses the source of memory-to-memory
+            // transfers
+            Direction::MemoryToMemory {
+                increment_source: true,
+            } => w.pinc().incremented(),
+            _ => w,
+        }
+    });
+}
+
+/// Applies `config` to the disabled stream `S`
+///
+/// # Panics
+///
+/// Panics, if `config` is not supported by the data sizes and direction the
+/// stream is configured for. See [`Config`].
+fn set_config<S: Stream>(dma: &dma2::RegisterBlock, config: Config) {
+    let st = &dma.st[S::number()];
+    let cr = st.cr.read();
+
+    match config.fifo {
+        Some(threshold) => {
+            let msize = 1 << cr.msize().bits();
+            let psize = 1 << cr.psize().bits();
+            assert!(
+                config.memory_burst.fits(threshold, msize)
+                    && config.peripheral_burst.fits(threshold, psize),
+                "Burst does not fit the FIFO threshold."
+            );
+
+            st.fcr
+                .modify(|_, w| w.dmdis().disabled().fth().bits(threshold.bits()));
+        }
+        None => {
+            assert!(
+                !cr.dir().is_memory_to_memory(),
+                "Memory-to-memory transfers require the FIFO."
+            );
+            assert!(
+                config.memory_burst == Burst::Single && config.peripheral_burst == Burst::Single,
+                "Bursts require the FIFO."
+            );
+
+            st.fcr.modify(|_, w| w.dmdis().enabled());
+        }
+    }
+
+    st.cr.modify(|_, w| {
+        w.mburst()
+            .bits(config.memory_burst.bits())
+            .pburst()
+            .bits(config.peripheral_burst.bits())
+    });
+}
+
+/// Enables the given interrupts of stream `S` and unmasks its interrupt line
+fn enable_interrupts<S: Stream>(dma: &dma2::RegisterBlock, interrupts: Interrupts) {
+    dma.st[S::number()].cr.modify(|_, w| {
+        let w = if interrupts.transfer_complete {
+            w.tcie().enabled()
+        } else {
+            w
+        };
+
+        let w = if interrupts.half_transfer {
+            w.htie().enabled()
+        } else {
+            w
+        };
+
+        let w = if interrupts.transfer_error {
+            w.teie().enabled()
+        } else {
+            w
+        };
+
+        let w = if interrupts.direct_mode_error {
+            w.dmeie().enabled()
+        } else {
+            w
+        };
+
+        w
+    });
+
+    // Enable interrupt.
+    unsafe { NVIC::unmask(S::INTERRUPT) };
+}
+
+/// Clears the status flags of stream `S` and enables it
+fn start<S: Stream>(dma: &dma2::RegisterBlock) {
+    S::clear_status_flags(dma);
+    atomic::fence(Ordering::SeqCst);
+
+    dma.st[S::number()].cr.modify(|_, w| w.en().enabled());
+}
+
+/// Disables stream `S` and waits until the current data item is transferred
+fn stop<S: Stream>(dma: &dma2::RegisterBlock) {
+    dma.st[S::number()].cr.modify(|_, w| w.en().disabled());
+    while dma.st[S::number()].cr.read().en().is_enabled() {}
+
+    atomic::fence(Ordering::SeqCst);
+    S::clear_status_flags(dma);
+}
+
+/// One half of the buffer of a [`CircTransfer`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Half {
+    First,
+    Second,
+}
+
+/// Represents an ongoing circular DMA transfer
+///
+/// The DMA stream transfers the buffer over and over again, without any gaps
+/// in between. The buffer is split into two halves: while the stream works on
+/// one half, the other one can be accessed using [`CircTransfer::peek`] or
+/// [`CircTransfer::peek_mut`].
+///
+/// Peripheral APIs that support circular DMA have methods like
+/// `read_circular`, which return instances of this struct.
+pub struct CircTransfer<T, S: Stream, B, State> {
+    res: TransferResources<T, S, B>,
+    _state: State,
+}
+
+impl<T, S, B> CircTransfer<T, S, B, Ready>
+where
+    T: Target<S>,
+    S: Stream,
+    B: 'static,
+{
+    /// Internal constructor to create a new `CircTransfer`
+    ///
+    /// # Safety
+    ///
+    /// See [`Transfer::new`]. The buffer must stay accessible for as long as
+    /// the transfer is running, which is ensured by pinning it.
+    pub(crate) unsafe fn new<Word>(
+        handle: &Handle<S::Instance, state::Enabled>,
+        stream: S,
+        buffer: Pin<B>,
+        target: T,
+        address: u32,
+        direction: Direction,
+    ) -> Self
+    where
+        B: Deref,
+        B::Target: Buffer<Word>,
+        Word: SupportedWordSize,
+    {
+        assert!(buffer.len() <= u16::max_value() as usize);
+        assert!(
+            buffer.len() >= 2 && buffer.len() % 2 == 0,
+            "Circular buffer must have an even, non-zero length."
+        );
+
+        configure::<S, T::Channel, Word>(
+            &handle.dma,
+            address,
+            buffer.as_ptr() as u32,
+            buffer.len(),
+            direction,
+            Mode::Circular,
+        );
+
+        CircTransfer {
+            res: TransferResources {
+                stream,
+                buffer,
+                target,
+            },
+            _state: Ready,
+        }
+    }
+
+    /// Configures the FIFO and bursts of the DMA stream for this transfer
+    ///
+    /// By default, transfers use direct mode and single transfers. See
+    /// [`Config`] for the supported combinations.
+    pub fn set_config(&mut self, handle: &Handle<S::Instance, state::Enabled>, config: Config) {
+        set_config::<S>(&handle.dma, config);
+    }
+
+    /// Enables the given interrupts for this DMA transfer
+    ///
+    /// The half transfer and transfer complete interrupts signal that the
+    /// first and second half of the buffer, respectively, can be accessed.
+    pub fn enable_interrupts(
+        &mut self,
+        handle: &Handle<S::Instance, state::Enabled>,
+        interrupts: Interrupts,
+    ) {
+        enable_interrupts::<S>(&handle.dma, interrupts);
+    }
+
+    /// Start the DMA transfer
+    pub fn start(
+        self,
+        handle: &Handle<S::Instance, state::Enabled>,
+    ) -> CircTransfer<T, S, B, Started> {
+        start::<S>(&handle.dma);
+
+        CircTransfer {
+            res: self.res,
+            _state: Started,
+        }
+    }
+}
+
+impl<T, S, B> CircTransfer<T, S, B, Started>
+where
+    S: Stream,
+{
+    /// Returns the half of the buffer that the DMA stream is done with, if any
+    ///
+    /// Returns [`Error::Overrun`], if the stream finished both halves since
+    /// they were last accessed. The overrun is cleared by that, and the next
+    /// call returns the next half the stream finishes.
+    pub fn readable_half(
+        &self,
+        handle: &Handle<S::Instance, state::Enabled>,
+    ) -> Result<Option<Half>, Error> {
+        Error::check::<S>(&handle.dma)?;
+
+        readable_half(
+            S::is_half_transfer(&handle.dma),
+            S::is_transfer_complete(&handle.dma),
+            || {
+                S::clear_half_transfer(&handle.dma);
+                S::clear_transfer_complete(&handle.dma);
+            },
+        )
+    }
+
+    /// Gives access to the half of the buffer the DMA stream is done with
+    ///
+    /// Returns `Ok(None)` if no half is ready yet. Otherwise `f` is called with
+    /// the finished half, while the stream keeps working on the other one.
+    /// Returns [`Error::Overrun`], if the stream got back to the accessed half
+    /// before `f` returned, in which case its data can't be relied upon.
+    pub fn peek<Word, R, F>(
+        &mut self,
+        handle: &Handle<S::Instance, state::Enabled>,
+        f: F,
+    ) -> Result<Option<R>, Error>
+    where
+        B: Deref,
+        B::Target: AsSlice<Element = Word>,
+        F: FnOnce(&[Word], Half) -> R,
+    {
+        let half = match self.acquire(handle)? {
+            Some(half) => half,
+            None => return Ok(None),
+        };
+
+        let buffer = self.res.buffer.as_slice();
+        let len = buffer.len() / 2;
+        let result = match half {
+            Half::First => f(&buffer[..len], half),
+            Half::Second => f(&buffer[len..], half),
+        };
+
+        self.release(handle, half)?;
+        Ok(Some(result))
+    }
+
+    /// Gives mutable access to the half of the buffer the DMA stream is done
+    /// with
+    ///
+    /// This is the counterpart of [`CircTransfer::peek`] for
+    /// memory-to-peripheral transfers, to fill in the data the stream is going
+    /// to send next.
+    pub fn peek_mut<Word, R, F>(
+        &mut self,
+        handle: &Handle<S::Instance, state::Enabled>,
+        f: F,
+    ) -> Result<Option<R>, Error>
+    where
+        B: DerefMut,
+        B::Target: AsMutSlice<Element = Word>,
+        F: FnOnce(&mut [Word], Half) -> R,
+    {
+        let half = match self.acquire(handle)? {
+            Some(half) => half,
+            None => return Ok(None),
+        };
+
+        // Safe, as the buffer is not moved out of the `Pin`.
+        let buffer = unsafe { self.res.buffer.as_mut().get_unchecked_mut() }.as_mut_slice();
+        let len = buffer.len() / 2;
+        let result = match half {
+            Half::First => f(&mut buffer[..len], half),
+            Half::Second => f(&mut buffer[len..], half),
+        };
+
+        self.release(handle, half)?;
+        Ok(Some(result))
+    }
+
+    /// Stops the transfer and returns the resources it used
+    pub fn stop(self, handle: &Handle<S::Instance, state::Enabled>) -> TransferResources<T, S, B> {
+        stop::<S>(&handle.dma);
+        NVIC::mask(S::INTERRUPT);
+
+        self.res
+    }
+
+    fn acquire(&self, handle: &Handle<S::Instance, state::Enabled>) -> Result<Option<Half>, Error> {
+        let half = self.readable_half(handle)?;
+
+        match half {
+            Some(Half::First) => S::clear_half_transfer(&handle.dma),
+            Some(Half::Second) => S::clear_transfer_complete(&handle.dma),
+            None => {}
+        }
+        atomic::fence(Ordering::SeqCst);
+
+        Ok(half)
+    }
+
+    fn release(
+        &self,
+        handle: &Handle<S::Instance, state::Enabled>,
+        half: Half,
+    ) -> Result<(), Error> {
+        atomic::fence(Ordering::SeqCst);
+
+        // The stream signals the end of the half it is working on now. If that
+        // happened, it has already moved on to the half that was accessed.
+        let overrun = match half {
+            Half::First => S::is_transfer_complete(&handle.dma),
+            Half::Second => S::is_half_transfer(&handle.dma),
+        };
+        if overrun {
+            return Err(Error::Overrun);
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the readable half of a circular buffer from the HT and TC flags
+///
+/// On an overrun, `clear` is called to clear both flags. Otherwise, every
+/// later call would report the same overrun.
+fn readable_half(
+    half_transfer: bool,
+    transfer_complete: bool,
+    clear: impl FnOnce(),
+) -> Result<Option<Half>, Error> {
+    match (half_transfer, transfer_complete) {
+        (true, true) => {
+            clear();
+            Err(Error::Overrun)
+        }
+        (true, false) => Ok(Some(Half::First)),
+        (false, true) => Ok(Some(Half::Second)),
+        (false, false) => Ok(None),
+    }
+}
+
+/// Represents an ongoing DMA transfer in double-buffer mode
+///
+/// The DMA stream alternates between two buffers of the same length, switching
+/// to the other one without any gap every time it reaches the end of the
+/// current one. While the stream works on one buffer, the other one can be
+/// accessed using [`DoubleBufferTransfer::peek`] or
+/// [`DoubleBufferTransfer::peek_mut`], or replaced altogether using
+/// [`DoubleBufferTransfer::replace_inactive`].
+///
+/// Peripheral APIs that support double-buffer mode have methods like
+/// `read_double_buffer`, which return instances of this struct.
+pub struct DoubleBufferTransfer<T, S: Stream, B, State> {
+    res: DoubleBufferResources<T, S, B>,
+    _state: State,
+}
+
+impl<T, S, B> DoubleBufferTransfer<T, S, B, Ready>
+where
+    T: Target<S>,
+    S: Stream,
+    B: 'static,
+{
+    /// Internal constructor to create a new `DoubleBufferTransfer`
+    ///
+    /// # Safety
+    ///
+    /// See [`Transfer::new`]. The requirements apply to both buffers.
+    pub(crate) unsafe fn new<Word>(
+        handle: &Handle<S::Instance, state::Enabled>,
+        stream: S,
+        buffers: [Pin<B>; 2],
+        target: T,
+        address: u32,
+        direction: Direction,
+    ) -> Self
+    where
+        B: Deref,
+        B::Target: Buffer<Word>,
+        Word: SupportedWordSize,
+    {
+        assert!(buffers[0].len() <= u16::max_value() as usize);
+        assert_eq!(
+            buffers[0].len(),
+            buffers[1].len(),
+            "Both buffers must have the same length."
+        );
+
+        configure::<S, T::Channel, Word>(
+            &handle.dma,
+            address,
+            buffers[0].as_ptr() as u32,
+            buffers[0].len(),
+            direction,
+            Mode::DoubleBuffer(buffers[1].as_ptr() as u32),
+        );
+
+        DoubleBufferTransfer {
+            res: DoubleBufferResources {
+                stream,
+                buffers,
+                target,
+            },
+            _state: Ready,
+        }
+    }
+
+    /// Configures the FIFO and bursts of the DMA stream for this transfer
+    ///
+    /// By default, transfers use direct mode and single transfers. See
+    /// [`Config`] for the supported combinations.
+    pub fn set_config(&mut self, handle: &Handle<S::Instance, state::Enabled>, config: Config) {
+        set_config::<S>(&handle.dma, config);
+    }
+
+    /// Enables the given interrupts for this DMA transfer
+    ///
+    /// The transfer complete interrupt signals that the stream switched
+    /// buffers, and that the previous one can be accessed.
+    pub fn enable_interrupts(
+        &mut self,
+        handle: &Handle<S::Instance, state::Enabled>,
+        interrupts: Interrupts,
+    ) {
+        enable_interrupts::<S>(&handle.dma, interrupts);
+    }
+
+    /// Start the DMA transfer
+    pub fn start(
+        self,
+        handle: &Handle<S::Instance, state::Enabled>,
+    ) -> DoubleBufferTransfer<T, S, B, Started> {
+        start::<S>(&handle.dma);
+
+        DoubleBufferTransfer {
+            res: self.res,
+            _state: Started,
+        }
+    }
+}
+
+impl<T, S, B> DoubleBufferTransfer<T, S, B, Started>
+where
+    S: Stream,
+{
+    /// Returns the index of the buffer the DMA stream is working on
+    pub fn active_buffer(&self, handle: &Handle<S::Instance, state::Enabled>) -> usize {
+        handle.dma.st[S::number()].cr.read().ct().bit_is_set() as usize
+    }
+
+    /// Gives access to the buffer the DMA stream is done with
+    ///
+    /// Returns `Ok(None)` if the stream hasn't switched buffers since the
+    /// last call. Otherwise `f` is called with the inactive buffer and its
+    /// index. Returns [`Error::Overrun`], if the stream switched back to the
+    /// accessed buffer before `f` returned.
+    pub fn peek<Word, R, F>(
+        &mut self,
+        handle: &Handle<S::Instance, state::Enabled>,
+        f: F,
+    ) -> Result<Option<R>, Error>
+    where
+        B: Deref,
+        B::Target: AsSlice<Element = Word>,
+        F: FnOnce(&[Word], usize) -> R,
+    {
+        let index = match self.acquire(handle)? {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let result = f(self.res.buffers[index].as_slice(), index);
+
+        self.release(handle)?;
+        Ok(Some(result))
+    }
+
+    /// Gives mutable access to the buffer the DMA stream is done with
+    ///
+    /// This is the counterpart of [`DoubleBufferTransfer::peek`] for
+    /// memory-to-peripheral transfers, to fill in the data the stream is going
+    /// to send next.
+    pub fn peek_mut<Word, R, F>(
+        &mut self,
+        handle: &Handle<S::Instance, state::Enabled>,
+        f: F,
+    ) -> Result<Option<R>, Error>
+    where
+        B: DerefMut,
+        B::Target: AsMutSlice<Element = Word>,
+        F: FnOnce(&mut [Word], usize) -> R,
+    {
+        let index = match self.acquire(handle)? {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        // Safe, as the buffer is not moved out of the `Pin`.
+        let buffer = unsafe { self.res.buffers[index].as_mut().get_unchecked_mut() };
+        let result = f(buffer.as_mut_slice(), index);
+
+        self.release(handle)?;
+        Ok(Some(result))
+    }
+
+    /// Replaces the buffer the DMA stream is done with, returning it
+    ///
+    /// Must be called after the stream switched buffers, and before it
+    /// reaches the end of the active one. Otherwise the stream signals a
+    /// transfer error and stops.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `buffer` doesn't have the length of the replaced one.
+    pub fn replace_inactive<Word>(
+        &mut self,
+        handle: &Handle<S::Instance, state::Enabled>,
+        buffer: Pin<B>,
+    ) -> Pin<B>
+    where
+        B: Deref,
+        B::Target: AsSlice<Element = Word>,
+    {
+        let index = 1 - self.active_buffer(handle);
+        assert_eq!(
+            buffer.as_slice().len(),
+            self.res.buffers[index].as_slice().len()
+        );
+
+        let address = buffer.as_slice().as_ptr() as u32;
+        let st = &handle.dma.st[S::number()];
+        atomic::fence(Ordering::SeqCst);
+        // NOTE(unsafe) the buffer is pinned and has the length of the one it
+        // replaces, and the stream doesn't access the inactive buffer.
+        unsafe {
+            if index == 0 {
+                st.m0ar.write(|w| w.m0a().bits(address));
+            } else {
+                st.m1ar.write(|w| w.m1a().bits(address));
+            }
+        }
+
+        core::mem::replace(&mut self.res.buffers[index], buffer)
+    }
+
+    /// Stops the transfer and returns the resources it used
+    pub fn stop(
+        self,
+        handle: &Handle<S::Instance, state::Enabled>,
+    ) -> DoubleBufferResources<T, S, B> {
+        stop::<S>(&handle.dma);
+        NVIC::mask(S::INTERRUPT);
+
+        self.res
+    }
+
+    fn acquire(
+        &self,
+        handle: &Handle<S::Instance, state::Enabled>,
+    ) -> Result<Option<usize>, Error> {
+        Error::check::<S>(&handle.dma)?;
+
+        if !S::is_transfer_complete(&handle.dma) {
+            return Ok(None);
+        }
+        S::clear_transfer_complete(&handle.dma);
+        atomic::fence(Ordering::SeqCst);
+
+        Ok(Some(1 - self.active_buffer(handle)))
+    }
+
+    fn release(&self, handle: &Handle<S::Instance, state::Enabled>) -> Result<(), Error> {
+        atomic::fence(Ordering::SeqCst);
+
+        // Another transfer complete means the stream switched back to the
+        // accessed buffer.
+        if S::is_transfer_complete(&handle.dma) {
+            return Err(Error::Overrun);
+        }
+
+        Ok(())
+    }
+}
+
+/// The resources that an ongoing double-buffer transfer needs exclusive access
+/// to
+pub struct DoubleBufferResources<T, S, B> {
+    pub stream: S,
+    pub buffers: [Pin<B>; 2],
+    pub target: T,
+}
+
+impl<T, S, B> fmt::Debug for DoubleBufferResources<T, S, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DoubleBufferResources {{ .. }}")
+    }
+}
+
 /// The resources that an ongoing transfer needs exclusive access to
-pub struct TransferResources<T: Target, B> {
-    pub stream: T::Stream,
+pub struct TransferResources<T, S, B> {
+    pub stream: S,
     pub buffer: Pin<B>,
     pub target: T,
 }
@@ -342,30 +1098,126 @@ pub struct TransferResources<T: Target, B> {
 // As `TransferResources` is used in the error variant of `Result`, it needs a
 // `Debug` implementation to enable stuff like `unwrap` and `expect`. This can't
 // be derived without putting requirements on the type arguments.
-impl<T, B> fmt::Debug for TransferResources<T, B>
-where
-    T: Target,
-{
+impl<T, S, B> fmt::Debug for TransferResources<T, S, B> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "TransferResources {{ .. }}")
     }
 }
 
+/// Target of memory-to-memory transfers
+///
+/// Owns the source of the transfer. Only DMA2 supports memory-to-memory
+/// transfers, which can use any of its streams.
+pub struct MemoryToMemory<Src> {
+    source: Pin<Src>,
+}
+
+impl<Src> MemoryToMemory<Src>
+where
+    Src: Deref + 'static,
+{
+    /// Prepares a transfer copying `source` into `destination`
+    ///
+    /// # Panics
+    ///
+    /// Panics, if the buffers don't have the same length, or if they are
+    /// longer than 65535 words.
+    pub fn copy<Word, Dst, S>(
+        source: Pin<Src>,
+        destination: Pin<Dst>,
+        dma: &Handle<DMA2, state::Enabled>,
+        stream: S,
+    ) -> Transfer<Self, S, Dst, Ready>
+    where
+        Self: Target<S>,
+        S: Stream<Instance = DMA2>,
+        Src::Target: AsSlice<Element = Word>,
+        Dst: DerefMut + 'static,
+        Dst::Target: AsMutSlice<Element = Word>,
+        Word: SupportedWordSize,
+    {
+        assert_eq!(source.as_slice().len(), destination.as_slice().len());
+        let address = source.as_slice().as_ptr() as u32;
+
+        // Safe, because the trait bounds on this method guarantee that
+        // `destination` can be written to safely, and the source is kept
+        // alive by the target.
+        unsafe {
+            Transfer::new(
+                dma,
+                stream,
+                destination,
+                MemoryToMemory { source },
+                address,
+                Direction::MemoryToMemory {
+                    increment_source: true,
+                },
+            )
+        }
+    }
+
+    /// Prepares a transfer filling `destination` with `value`
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `destination` is longer than 65535 words.
+    pub fn fill<Word, Dst, S>(
+        value: Pin<Src>,
+        destination: Pin<Dst>,
+        dma: &Handle<DMA2, state::Enabled>,
+        stream: S,
+    ) -> Transfer<Self, S, Dst, Ready>
+    where
+        Self: Target<S>,
+        S: Stream<Instance = DMA2>,
+        Src: Deref<Target = Word>,
+        Dst: DerefMut + 'static,
+        Dst::Target: AsMutSlice<Element = Word>,
+        Word: SupportedWordSize,
+    {
+        let address = &*value as *const Word as u32;
+
+        // Safe, see `copy`.
+        unsafe {
+            Transfer::new(
+                dma,
+                stream,
+                destination,
+                MemoryToMemory { source: value },
+                address,
+                Direction::MemoryToMemory {
+                    increment_source: false,
+                },
+            )
+        }
+    }
+
+    /// Returns the source of the transfer
+    pub fn free(self) -> Pin<Src> {
+        self.source
+    }
+}
+
 pub(crate) enum Direction {
     MemoryToPeripheral,
     PeripheralToMemory,
+    /// DMA2 only. The source is addressed through the peripheral port.
+    MemoryToMemory {
+        increment_source: bool,
+    },
 }
 
 /// Implemented for all peripheral APIs that support DMA transfers
 ///
+/// A peripheral implements this trait once for every stream it can be used
+/// with, and `Channel` names the channel it is mapped to on that stream.
+/// Passing a stream that isn't connected to the peripheral is rejected at
+/// compile time.
+///
 /// This is an internal trait. End users neither need to implement it, nor use
 /// it directly.
-pub trait Target {
-    type Instance: Deref<Target = dma2::RegisterBlock>;
-    type Stream: Stream;
+pub trait Target<S: Stream> {
     type Channel: Channel;
-
-    const INTERRUPT: Interrupt;
 }
 
 macro_rules! impl_target {
@@ -374,80 +1226,90 @@ macro_rules! impl_target {
             $ty:ty,
             $instance:ty,
             $stream:ident,
-            $channel:ty,
-            $interrupt:ident;
+            $channel:ty;
         )*
     ) => {
         $(
-            impl Target for $ty {
-                type Instance = $instance;
-                type Stream   = $stream<$instance>;
-                type Channel  = $channel;
-
-                const INTERRUPT: Interrupt = Interrupt::$interrupt;
+            impl Target<$stream<$instance>> for $ty {
+                type Channel = $channel;
             }
         )*
     }
 }
 
 // See section 8.3.4, tables 25 and 26
-//
-// Some peripherals can be used with multiple stream/channel combinations. To
-// model this, we'd need to implement `Tx` multiple times, which means `Tx`
-// would need a type parameter. When attempting to do this, I ran into errors
-// about unconstrained type parameters when trying to use `Tx`.
-//
-// There's probably a smart way to achieve this, but I decided to declare
-// victory and leave this problem to someone who actually needs this capability.
 impl_target!(
     // SPI receive
-    spi::Rx<pac::SPI1>, DMA2, Stream0, Channel3, DMA2_STREAM0;
-    // SPI1 for DMA2, stream 2, channel 3 is unsupported
-    spi::Rx<pac::SPI2>, DMA1, Stream3, Channel0, DMA1_STREAM3;
-    spi::Rx<pac::SPI3>, DMA1, Stream0, Channel0, DMA1_STREAM0;
-    // SPI3 for DMA1, stream 2, channel 0 is unsupported
-    spi::Rx<pac::SPI4>, DMA2, Stream0, Channel4, DMA2_STREAM0;
-    // SPI4 for DMA2, stream 3, channel 5 is unsupported
-    spi::Rx<pac::SPI5>, DMA2, Stream3, Channel2, DMA2_STREAM3;
-    // SPI5 for DMA2, stream 5, channel 7 is unsupported
+    spi::Rx<pac::SPI1>, DMA2, Stream0, Channel3;
+    spi::Rx<pac::SPI1>, DMA2, Stream2, Channel3;
+    spi::Rx<pac::SPI2>, DMA1, Stream3, Channel0;
+    spi::Rx<pac::SPI3>, DMA1, Stream0, Channel0;
+    spi::Rx<pac::SPI3>, DMA1, Stream2, Channel0;
+    spi::Rx<pac::SPI4>, DMA2, Stream0, Channel4;
+    spi::Rx<pac::SPI4>, DMA2, Stream3, Channel5;
+    spi::Rx<pac::SPI5>, DMA2, Stream3, Channel2;
+    spi::Rx<pac::SPI5>, DMA2, Stream5, Channel7;
 
     // SPI transmit
-    spi::Tx<pac::SPI1>, DMA2, Stream3, Channel3, DMA2_STREAM3;
-    // SPI1 for DMA2, stream 5, channel 3 is unsupported
-    spi::Tx<pac::SPI2>, DMA1, Stream4, Channel0, DMA1_STREAM4;
-    spi::Tx<pac::SPI3>, DMA1, Stream5, Channel0, DMA1_STREAM5;
-    // SPI3 for DMA1, stream 7, channel 0 is unsupported
-    spi::Tx<pac::SPI4>, DMA2, Stream1, Channel4, DMA2_STREAM1;
-    // SPI4 for DMA2, stream 4, channel 5 is unsupported
-    spi::Tx<pac::SPI5>, DMA2, Stream4, Channel2, DMA2_STREAM4;
-    // SPI5 for DMA2, stream 6, channel 7 is unsupported
+    spi::Tx<pac::SPI1>, DMA2, Stream3, Channel3;
+    spi::Tx<pac::SPI1>, DMA2, Stream5, Channel3;
+    spi::Tx<pac::SPI2>, DMA1, Stream4, Channel0;
+    spi::Tx<pac::SPI3>, DMA1, Stream5, Channel0;
+    spi::Tx<pac::SPI3>, DMA1, Stream7, Channel0;
+    spi::Tx<pac::SPI4>, DMA2, Stream1, Channel4;
+    spi::Tx<pac::SPI4>, DMA2, Stream4, Channel5;
+    spi::Tx<pac::SPI5>, DMA2, Stream4, Channel2;
+    spi::Tx<pac::SPI5>, DMA2, Stream6, Channel7;
 
     // USART receive
-    serial::Rx<pac::USART1>, DMA2, Stream2, Channel4, DMA2_STREAM2;
-    // USART1 for DMA2, stream 5, channel 4 is unsupported
-    serial::Rx<pac::USART2>, DMA1, Stream5, Channel4, DMA1_STREAM5;
-    serial::Rx<pac::USART3>, DMA1, Stream1, Channel4, DMA1_STREAM1;
-    serial::Rx<pac::UART4>, DMA1, Stream2, Channel4, DMA1_STREAM2;
-    serial::Rx<pac::UART5>, DMA1, Stream0, Channel4, DMA1_STREAM0;
-    serial::Rx<pac::USART6>, DMA2, Stream1, Channel5, DMA2_STREAM1;
-    // USART6 for DMA2, stream 2, channel 5 is unsupported
-    serial::Rx<pac::UART7>, DMA1, Stream3, Channel5, DMA1_STREAM3;
-    serial::Rx<pac::UART8>, DMA1, Stream6, Channel5, DMA1_STREAM6;
+    serial::Rx<pac::USART1>, DMA2, Stream2, Channel4;
+    serial::Rx<pac::USART1>, DMA2, Stream5, Channel4;
+    serial::Rx<pac::USART2>, DMA1, Stream5, Channel4;
+    serial::Rx<pac::USART3>, DMA1, Stream1, Channel4;
+    serial::Rx<pac::UART4>,  DMA1, Stream2, Channel4;
+    serial::Rx<pac::UART5>,  DMA1, Stream0, Channel4;
+    serial::Rx<pac::USART6>, DMA2, Stream1, Channel5;
+    serial::Rx<pac::USART6>, DMA2, Stream2, Channel5;
+    serial::Rx<pac::UART7>,  DMA1, Stream3, Channel5;
+    serial::Rx<pac::UART8>,  DMA1, Stream6, Channel5;
 
     // USART transmit
-    serial::Tx<pac::USART1>, DMA2, Stream7, Channel4, DMA2_STREAM7;
-    serial::Tx<pac::USART2>, DMA1, Stream6, Channel4, DMA1_STREAM6;
-    serial::Tx<pac::USART3>, DMA1, Stream3, Channel4, DMA1_STREAM3;
-    // USART3 for DMA1, stream 4, channel 7 is unsupported
-    serial::Tx<pac::UART4>,  DMA1, Stream4, Channel4, DMA1_STREAM4;
-    serial::Tx<pac::UART5>,  DMA1, Stream7, Channel4, DMA1_STREAM7;
-    serial::Tx<pac::USART6>, DMA2, Stream6, Channel5, DMA2_STREAM6;
-    // USART6 for DMA2, stream 7, channel 5 is unsupported
-    serial::Tx<pac::UART7>,  DMA1, Stream1, Channel5, DMA1_STREAM1;
-    serial::Tx<pac::UART8>,  DMA1, Stream0, Channel5, DMA1_STREAM0;
+    serial::Tx<pac::USART1>, DMA2, Stream7, Channel4;
+    serial::Tx<pac::USART2>, DMA1, Stream6, Channel4;
+    serial::Tx<pac::USART3>, DMA1, Stream3, Channel4;
+    serial::Tx<pac::USART3>, DMA1, Stream4, Channel7;
+    serial::Tx<pac::UART4>,  DMA1, Stream4, Channel4;
+    serial::Tx<pac::UART5>,  DMA1, Stream7, Channel4;
+    serial::Tx<pac::USART6>, DMA2, Stream6, Channel5;
+    serial::Tx<pac::USART6>, DMA2, Stream7, Channel5;
+    serial::Tx<pac::UART7>,  DMA1, Stream1, Channel5;
+    serial::Tx<pac::UART8>,  DMA1, Stream0, Channel5;
+
+    // I2C receive
+    i2c::Rx<pac::I2C1>, DMA1, Stream0, Channel1;
+    i2c::Rx<pac::I2C1>, DMA1, Stream5, Channel1;
+    i2c::Rx<pac::I2C2>, DMA1, Stream2, Channel7;
+    i2c::Rx<pac::I2C2>, DMA1, Stream3, Channel7;
+    i2c::Rx<pac::I2C3>, DMA1, Stream1, Channel1;
+    i2c::Rx<pac::I2C3>, DMA1, Stream2, Channel3;
+
+    // I2C transmit
+    i2c::Tx<pac::I2C1>, DMA1, Stream6, Channel1;
+    i2c::Tx<pac::I2C1>, DMA1, Stream7, Channel1;
+    i2c::Tx<pac::I2C2>, DMA1, Stream7, Channel7;
+    i2c::Tx<pac::I2C3>, DMA1, Stream4, Channel3;
 
     // QUADSPI is half-duplex, uses one channel for both send/receive
-    qspi::RxTx<pac::QUADSPI>, DMA2, Stream7, Channel3, DMA2_STREAM7;
+    qspi::RxTx<pac::QUADSPI>, DMA2, Stream7, Channel3;
+
+    // An SAI block is either a transmitter or a receiver, with one request
+    sai::RxTx<pac::SAI1, sai::A>, DMA2, Stream1, Channel0;
+    sai::RxTx<pac::SAI1, sai::A>, DMA2, Stream3, Channel0;
+    sai::RxTx<pac::SAI1, sai::B>, DMA2, Stream4, Channel1;
+    sai::RxTx<pac::SAI1, sai::B>, DMA2, Stream5, Channel0;
+    sai::RxTx<pac::SAI2, sai::A>, DMA2, Stream4, Channel3;
+    sai::RxTx<pac::SAI2, sai::B>, DMA2, Stream6, Channel3;
+    sai::RxTx<pac::SAI2, sai::B>, DMA2, Stream7, Channel0;
 );
 
 #[cfg(any(
@@ -462,18 +1324,40 @@ impl_target!(
     feature = "stm32f779",
 ))]
 impl_target!(
-    spi::Rx<pac::SPI6>, DMA2, Stream6, Channel1, DMA2_STREAM6;
-    spi::Tx<pac::SPI6>, DMA2, Stream5, Channel1, DMA2_STREAM5;
+    spi::Rx<pac::SPI6>, DMA2, Stream6, Channel1;
+    spi::Tx<pac::SPI6>, DMA2, Stream5, Channel1;
+    i2c::Rx<pac::I2C4>, DMA1, Stream2, Channel2;
+    i2c::Tx<pac::I2C4>, DMA1, Stream5, Channel2;
 );
 
+// Memory-to-memory transfers don't depend on a request, so the channel doesn't
+// matter.
+macro_rules! impl_memory_to_memory {
+    ($($stream:ident,)*) => {
+        $(
+            impl<Src> Target<$stream<DMA2>> for MemoryToMemory<Src> {
+                type Channel = Channel0;
+            }
+        )*
+    }
+}
+
+impl_memory_to_memory!(Stream0, Stream1, Stream2, Stream3, Stream4, Stream5, Stream6, Stream7,);
+
 /// Implemented for all types that represent DMA streams
 ///
 /// This is an internal trait. End users neither need to implement it, nor use
 /// it directly.
 pub trait Stream {
+    type Instance: Deref<Target = dma2::RegisterBlock>;
+
+    const INTERRUPT: Interrupt;
+
     fn number() -> usize;
 
     fn clear_status_flags(dma: &dma2::RegisterBlock);
+    fn clear_half_transfer(dma: &dma2::RegisterBlock);
+    fn clear_transfer_complete(dma: &dma2::RegisterBlock);
 
     fn is_transfer_complete(dma: &dma2::RegisterBlock) -> bool;
     fn is_half_transfer(dma: &dma2::RegisterBlock) -> bool;
@@ -495,7 +1379,8 @@ macro_rules! impl_stream {
             $htif:ident,
             $tcif:ident,
             $flag_clear_reg:ident,
-            ($($flag_clear_field:ident,)*);
+            ($cfeif:ident, $cdmeif:ident, $cteif:ident, $chtif:ident, $ctcif:ident,),
+            [$($instance:ident: $interrupt:ident,)*];
         )*
     ) => {
         pub struct Streams<I> {
@@ -514,32 +1399,48 @@ macro_rules! impl_stream {
         $(
             pub struct $name<I>(PhantomData<I>);
 
-            impl<I> Stream for $name<I> {
-                fn number() -> usize { $number }
-
-                fn clear_status_flags(dma: &dma2::RegisterBlock) {
-                    dma.$flag_clear_reg.write(|w|
-                        w
-                            $(.$flag_clear_field().clear())*
-                    );
+            $(
+                impl Stream for $name<$instance> {
+                    type Instance = $instance;
+
+                    const INTERRUPT: Interrupt = Interrupt::$interrupt;
+
+                    fn number() -> usize { $number }
+
+                    fn clear_status_flags(dma: &dma2::RegisterBlock) {
+                        dma.$flag_clear_reg.write(|w|
+                            w
+                                .$cfeif().clear()
+                                .$cdmeif().clear()
+                                .$cteif().clear()
+                                .$chtif().clear()
+                                .$ctcif().clear()
+                        );
+                    }
+                    fn clear_half_transfer(dma: &dma2::RegisterBlock) {
+                        dma.$flag_clear_reg.write(|w| w.$chtif().clear());
+                    }
+                    fn clear_transfer_complete(dma: &dma2::RegisterBlock) {
+                        dma.$flag_clear_reg.write(|w| w.$ctcif().clear());
+                    }
+
+                    fn is_transfer_complete(dma: &dma2::RegisterBlock) -> bool {
+                        dma.$flag_reg.read().$tcif().is_complete()
+                    }
+                    fn is_half_transfer(dma: &dma2::RegisterBlock) -> bool {
+                        dma.$flag_reg.read().$htif().is_half()
+                    }
+                    fn is_transfer_error(dma: &dma2::RegisterBlock) -> bool {
+                        dma.$flag_reg.read().$teif().is_error()
+                    }
+                    fn is_direct_mode_error(dma: &dma2::RegisterBlock) -> bool {
+                        dma.$flag_reg.read().$dmeif().is_error()
+                    }
+                    fn is_fifo_error(dma: &dma2::RegisterBlock) -> bool {
+                        dma.$flag_reg.read().$feif().is_error()
+                    }
                 }
-
-                fn is_transfer_complete(dma: &dma2::RegisterBlock) -> bool {
-                    dma.$flag_reg.read().$tcif().is_complete()
-                }
-                fn is_half_transfer(dma: &dma2::RegisterBlock) -> bool {
-                    dma.$flag_reg.read().$htif().is_half()
-                }
-                fn is_transfer_error(dma: &dma2::RegisterBlock) -> bool {
-                    dma.$flag_reg.read().$teif().is_error()
-                }
-                fn is_direct_mode_error(dma: &dma2::RegisterBlock) -> bool {
-                    dma.$flag_reg.read().$dmeif().is_error()
-                }
-                fn is_fifo_error(dma: &dma2::RegisterBlock) -> bool {
-                    dma.$flag_reg.read().$feif().is_error()
-                }
-            }
+            )*
         )*
     }
 }
@@ -547,28 +1448,36 @@ macro_rules! impl_stream {
 impl_stream!(
     Stream0, stream0, 0,
         lisr, feif0, dmeif0, teif0, htif0, tcif0,
-        lifcr, (cfeif0, cdmeif0, cteif0, chtif0, ctcif0,);
+        lifcr, (cfeif0, cdmeif0, cteif0, chtif0, ctcif0,),
+        [DMA1: DMA1_STREAM0, DMA2: DMA2_STREAM0,];
     Stream1, stream1, 1,
         lisr, feif1, dmeif1, teif1, htif1, tcif1,
-        lifcr, (cfeif1, cdmeif1, cteif1, chtif1, ctcif1,);
+        lifcr, (cfeif1, cdmeif1, cteif1, chtif1, ctcif1,),
+        [DMA1: DMA1_STREAM1, DMA2: DMA2_STREAM1,];
     Stream2, stream2, 2,
         lisr, feif2, dmeif2, teif2, htif2, tcif2,
-        lifcr, (cfeif2, cdmeif2, cteif2, chtif2, ctcif2,);
+        lifcr, (cfeif2, cdmeif2, cteif2, chtif2, ctcif2,),
+        [DMA1: DMA1_STREAM2, DMA2: DMA2_STREAM2,];
     Stream3, stream3, 3,
         lisr, feif3, dmeif3, teif3, htif3, tcif3,
-        lifcr, (cfeif3, cdmeif3, cteif3, chtif3, ctcif3,);
+        lifcr, (cfeif3, cdmeif3, cteif3, chtif3, ctcif3,),
+        [DMA1: DMA1_STREAM3, DMA2: DMA2_STREAM3,];
     Stream4, stream4, 4,
         hisr, feif4, dmeif4, teif4, htif4, tcif4,
-        hifcr, (cfeif4, cdmeif4, cteif4, chtif4, ctcif4,);
+        hifcr, (cfeif4, cdmeif4, cteif4, chtif4, ctcif4,),
+        [DMA1: DMA1_STREAM4, DMA2: DMA2_STREAM4,];
     Stream5, stream5, 5,
         hisr, feif5, dmeif5, teif5, htif5, tcif5,
-        hifcr, (cfeif5, cdmeif5, cteif5, chtif5, ctcif5,);
+        hifcr, (cfeif5, cdmeif5, cteif5, chtif5, ctcif5,),
+        [DMA1: DMA1_STREAM5, DMA2: DMA2_STREAM5,];
     Stream6, stream6, 6,
         hisr, feif6, dmeif6, teif6, htif6, tcif6,
-        hifcr, (cfeif6, cdmeif6, cteif6, chtif6, ctcif6,);
+        hifcr, (cfeif6, cdmeif6, cteif6, chtif6, ctcif6,),
+        [DMA1: DMA1_STREAM6, DMA2: DMA2_STREAM6,];
     Stream7, stream7, 7,
         hisr, feif7, dmeif7, teif7, htif7, tcif7,
-        hifcr, (cfeif7, cdmeif7, cteif7, chtif7, ctcif7,);
+        hifcr, (cfeif7, cdmeif7, cteif7, chtif7, ctcif7,),
+        [DMA1: DMA1_STREAM7, DMA2: DMA2_STREAM7,];
 );
 
 /// Implemented for all types that represent DMA channels
@@ -648,11 +1557,107 @@ impl Default for Interrupts {
     }
 }
 
+impl Interrupts {
+    /// Interrupts waking the tasks waiting with [`Transfer::wait_async`]
+    #[cfg(feature = "async")]
+    pub(crate) const ASYNC: Self = Self {
+        transfer_complete: true,
+        half_transfer: false,
+        transfer_error: true,
+        direct_mode_error: true,
+    };
+}
+
+/// FIFO and burst configuration of a DMA stream
+///
+/// Bursts are only available in FIFO mode, and a burst must fit evenly into
+/// the FIFO threshold. For example, with 16-bit words a burst of 4 requires the
+/// `Half` or `Full` threshold, while bursts of 16 are only possible with bytes
+/// and the `Full` threshold. See section 8.3.13 of the reference manual.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// FIFO threshold, or `None` for direct mode
+    pub fifo: Option<FifoThreshold>,
+    pub memory_burst: Burst,
+    pub peripheral_burst: Burst,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fifo: None,
+            memory_burst: Burst::Single,
+            peripheral_burst: Burst::Single,
+        }
+    }
+}
+
+/// FIFO threshold level, in quarters of the 4-word FIFO
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FifoThreshold {
+    Quarter,
+    Half,
+    ThreeQuarters,
+    Full,
+}
+
+impl FifoThreshold {
+    fn bits(self) -> u8 {
+        match self {
+            FifoThreshold::Quarter => 0b00,
+            FifoThreshold::Half => 0b01,
+            FifoThreshold::ThreeQuarters => 0b10,
+            FifoThreshold::Full => 0b11,
+        }
+    }
+
+    fn bytes(self) -> u32 {
+        (self.bits() as u32 + 1) * 4
+    }
+}
+
+/// Number of beats of a burst transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Burst {
+    Single,
+    Incr4,
+    Incr8,
+    Incr16,
+}
+
+impl Burst {
+    fn bits(self) -> u8 {
+        match self {
+            Burst::Single => 0b00,
+            Burst::Incr4 => 0b01,
+            Burst::Incr8 => 0b10,
+            Burst::Incr16 => 0b11,
+        }
+    }
+
+    fn beats(self) -> u32 {
+        match self {
+            Burst::Single => 1,
+            Burst::Incr4 => 4,
+            Burst::Incr8 => 8,
+            Burst::Incr16 => 16,
+        }
+    }
+
+    /// Whether bursts of words of `size` bytes fit evenly into `threshold`
+    fn fits(self, threshold: FifoThreshold, size: u32) -> bool {
+        let burst = self.beats() * size;
+        burst <= threshold.bytes() && threshold.bytes() % burst == 0
+    }
+}
+
 /// A DMA error
 #[derive(Debug)]
 pub enum Error {
     Transfer,
     DirectMode,
+    /// The DMA stream got back to a buffer region before it was released
+    Overrun,
 }
 
 impl Error {
@@ -760,6 +1765,17 @@ impl SupportedWordSize for u16 {
     }
 }
 
+impl private::Sealed for u32 {}
+impl SupportedWordSize for u32 {
+    fn msize() -> cr::MSIZE_A {
+        cr::MSIZE_A::BITS32
+    }
+
+    fn psize() -> cr::PSIZE_A {
+        cr::MSIZE_A::BITS32
+    }
+}
+
 mod private {
     /// Prevents code outside of the parent module from implementing traits
     ///
@@ -768,3 +1784,58 @@ mod private {
     /// be implemented only in the parent module.
     pub trait Sealed {}
 }
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::{readable_half, Burst, Error, FifoThreshold, Half};
+
+    #[test]
+    fn test_burst_fits() {
+        // Bytes
+        assert!(Burst::Incr4.fits(FifoThreshold::Quarter, 1));
+        assert!(!Burst::Incr8.fits(FifoThreshold::Quarter, 1));
+        assert!(Burst::Incr8.fits(FifoThreshold::Half, 1));
+        assert!(!Burst::Incr8.fits(FifoThreshold::ThreeQuarters, 1));
+        assert!(Burst::Incr16.fits(FifoThreshold::Full, 1));
+
+        // Half-words
+        assert!(!Burst::Incr4.fits(FifoThreshold::Quarter, 2));
+        assert!(Burst::Incr4.fits(FifoThreshold::Half, 2));
+        assert!(!Burst::Incr4.fits(FifoThreshold::ThreeQuarters, 2));
+        assert!(Burst::Incr8.fits(FifoThreshold::Full, 2));
+        assert!(!Burst::Incr16.fits(FifoThreshold::Full, 2));
+
+        // Words
+        assert!(!Burst::Incr4.fits(FifoThreshold::Half, 4));
+        assert!(Burst::Incr4.fits(FifoThreshold::Full, 4));
+        assert!(!Burst::Incr8.fits(FifoThreshold::Full, 4));
+        assert!(Burst::Single.fits(FifoThreshold::Quarter, 4));
+    }
+
+    #[test]
+    fn test_readable_half() {
+        let cleared = Cell::new(false);
+        let clear = || cleared.set(true);
+
+        assert!(matches!(readable_half(false, false, clear), Ok(None)));
+        assert!(matches!(
+            readable_half(true, false, clear),
+            Ok(Some(Half::First))
+        ));
+        assert!(matches!(
+            readable_half(false, true, clear),
+            Ok(Some(Half::Second))
+        ));
+        assert!(!cleared.get());
+
+        // Both flags are cleared on an overrun, so the next call only sees
+        // the half finished after it
+        assert!(matches!(
+            readable_half(true, true, clear),
+            Err(Error::Overrun)
+        ));
+        assert!(cleared.get());
+    }
+}
@@ -1,17 +1,30 @@
 //! Inter-Integrated Circuit (I2C) bus
-//! For now, only master mode is implemented
+//! Master mode is provided by `I2c`/`BlockingI2c`, slave mode by `I2cSlave`
 
 // NB : this implementation started as a modified copy of https://github.com/stm32-rs/stm32f1xx-hal/blob/master/src/i2c.rs
 
 use crate::gpio::{self, Alternate, OpenDrain};
 use crate::hal::blocking::i2c::{Read, Write, WriteRead};
-use crate::pac::{DWT, I2C1, I2C2, I2C3};
-use crate::rcc::{Clocks, Enable, GetBusFreq, RccBus, Reset};
+use crate::pac::{self, DWT, I2C1, I2C2, I2C3};
+#[cfg(any(
+    feature = "svd-f745",
+    feature = "svd-f750",
+    feature = "svd-f7x6",
+    feature = "svd-f765",
+    feature = "svd-f7x7",
+    feature = "svd-f7x9",
+))]
+use crate::pac::I2C4;
+use crate::rcc::{Clocks, Enable, KernelClock, Reset};
 use fugit::HertzU32 as Hertz;
 use nb::Error::{Other, WouldBlock};
 use nb::{Error as NbError, Result as NbResult};
 
 use cast::u16;
+use core::ops::Deref;
+
+#[cfg(feature = "async")]
+use atomic_waker::AtomicWaker;
 
 /// I2C error
 #[derive(Debug, Eq, PartialEq)]
@@ -27,9 +40,10 @@ pub enum Error {
     Overrun,
     /// Bus is busy
     Busy,
-    // Pec, // SMBUS mode only
-    // Timeout, // SMBUS mode only
-    // Alert, // SMBUS mode only
+    /// PEC mismatch (SMBus mode only)
+    Pec,
+    /// SCL low or cumulative clock extension timeout (SMBus mode only)
+    Timeout,
 }
 
 /// SPI mode. The user should make sure that the requested frequency can be
@@ -56,6 +70,54 @@ impl Mode {
     }
 }
 
+/// Implemented by all I2C instances
+///
+/// Users of this crate should not implement this trait.
+pub trait Instance:
+    crate::Sealed + Deref<Target = pac::i2c1::RegisterBlock> + Enable + Reset + KernelClock
+{
+    fn ptr() -> *const pac::i2c1::RegisterBlock;
+    /// Waker of the task waiting for the current transfer
+    #[cfg(feature = "async")]
+    fn waker() -> &'static AtomicWaker;
+}
+
+macro_rules! impl_instance {
+    ($($I2CX:ident,)+) => {
+        $(
+            impl Instance for $I2CX {
+                fn ptr() -> *const pac::i2c1::RegisterBlock {
+                    $I2CX::ptr()
+                }
+
+                #[cfg(feature = "async")]
+                fn waker() -> &'static AtomicWaker {
+                    static WAKER: AtomicWaker = AtomicWaker::new();
+                    &WAKER
+                }
+            }
+        )+
+    }
+}
+
+impl_instance! {
+    I2C1,
+    I2C2,
+    I2C3,
+}
+
+#[cfg(any(
+    feature = "svd-f745",
+    feature = "svd-f750",
+    feature = "svd-f7x6",
+    feature = "svd-f765",
+    feature = "svd-f7x7",
+    feature = "svd-f7x9",
+))]
+impl_instance! {
+    I2C4,
+}
+
 /// Marker trait to define SCL pins for an I2C interface.
 pub trait PinScl<I2C> {}
 
@@ -78,6 +140,42 @@ impl PinSda<I2C2> for gpio::PH5<Alternate<4, OpenDrain>> {}
 impl PinSda<I2C3> for gpio::PC9<Alternate<4, OpenDrain>> {}
 impl PinSda<I2C3> for gpio::PH8<Alternate<4, OpenDrain>> {}
 
+#[cfg(any(
+    feature = "svd-f745",
+    feature = "svd-f750",
+    feature = "svd-f7x6",
+    feature = "svd-f765",
+    feature = "svd-f7x7",
+    feature = "svd-f7x9",
+))]
+mod i2c4_pins {
+    use super::{PinScl, PinSda};
+    use crate::gpio::{self, Alternate, OpenDrain};
+    use crate::pac::I2C4;
+
+    impl PinScl<I2C4> for gpio::PD12<Alternate<4, OpenDrain>> {}
+    impl PinScl<I2C4> for gpio::PF14<Alternate<4, OpenDrain>> {}
+    impl PinScl<I2C4> for gpio::PH11<Alternate<4, OpenDrain>> {}
+
+    impl PinSda<I2C4> for gpio::PD13<Alternate<4, OpenDrain>> {}
+    impl PinSda<I2C4> for gpio::PF15<Alternate<4, OpenDrain>> {}
+    impl PinSda<I2C4> for gpio::PH12<Alternate<4, OpenDrain>> {}
+}
+
+// Only the F76x/F77x route I2C4 to port B
+#[cfg(any(feature = "svd-f765", feature = "svd-f7x7", feature = "svd-f7x9"))]
+mod i2c4_portb_pins {
+    use super::{PinScl, PinSda};
+    use crate::gpio::{self, Alternate, OpenDrain};
+    use crate::pac::I2C4;
+
+    impl PinScl<I2C4> for gpio::PB6<Alternate<11, OpenDrain>> {}
+    impl PinScl<I2C4> for gpio::PB8<Alternate<1, OpenDrain>> {}
+
+    impl PinSda<I2C4> for gpio::PB7<Alternate<11, OpenDrain>> {}
+    impl PinSda<I2C4> for gpio::PB9<Alternate<1, OpenDrain>> {}
+}
+
 /// I2C peripheral operating in master mode
 pub struct I2c<I2C, SCL, SDA> {
     i2c: I2C,
@@ -92,108 +190,101 @@ pub struct BlockingI2c<I2C, SCL, SDA> {
     data_timeout: u32,
 }
 
-impl<SCL, SDA> I2c<I2C1, SCL, SDA> {
-    /// Creates a generic I2C1 object.
-    pub fn i2c1(
-        i2c: I2C1,
-        pins: (SCL, SDA),
-        mode: Mode,
-        clocks: Clocks,
-        apb: &mut <I2C1 as RccBus>::Bus,
-    ) -> Self
+impl<I2C, SCL, SDA> I2c<I2C, SCL, SDA>
+where
+    I2C: Instance,
+{
+    /// Configures the I2C peripheral to work in master mode
+    ///
+    /// Panics if the bus frequency can't be generated with
+    /// `TimingConfig::for_mode(&mode)`, see `new_with_timing`.
+    pub fn new(i2c: I2C, pins: (SCL, SDA), mode: Mode, clocks: Clocks, apb: &mut I2C::Bus) -> Self
     where
-        SCL: PinScl<I2C1>,
-        SDA: PinSda<I2C1>,
+        SCL: PinScl<I2C>,
+        SDA: PinSda<I2C>,
     {
-        I2c::_i2c1(i2c, pins, mode, clocks, apb)
+        let config = TimingConfig::for_mode(&mode);
+        Self::new_with_timing(i2c, pins, mode, config, clocks, apb)
+            .expect("I2C bus frequency can't be generated")
     }
-}
 
-impl<SCL, SDA> BlockingI2c<I2C1, SCL, SDA> {
-    /// Creates a blocking I2C1 object using the embedded-hal `BlockingI2c` trait.
-    pub fn i2c1(
-        i2c: I2C1,
+    /// Configures the I2C peripheral to work in master mode, with the bus
+    /// and filter parameters of `config`
+    pub fn new_with_timing(
+        i2c: I2C,
         pins: (SCL, SDA),
         mode: Mode,
+        config: TimingConfig,
         clocks: Clocks,
-        apb: &mut <I2C1 as RccBus>::Bus,
-        data_timeout_us: u32,
-    ) -> Self
+        apb: &mut I2C::Bus,
+    ) -> Result<Self, TimingError>
     where
-        SCL: PinScl<I2C1>,
-        SDA: PinSda<I2C1>,
+        SCL: PinScl<I2C>,
+        SDA: PinSda<I2C>,
     {
-        BlockingI2c::_i2c1(i2c, pins, mode, clocks, apb, data_timeout_us)
-    }
-}
+        let pclk = clocks.kernel_clock::<I2C>();
+        compute_timing(pclk, &mode, &config)?;
 
-impl<SCL, SDA> I2c<I2C2, SCL, SDA> {
-    /// Creates a generic I2C2 object.
-    pub fn i2c2(
-        i2c: I2C2,
-        pins: (SCL, SDA),
-        mode: Mode,
-        clocks: Clocks,
-        apb: &mut <I2C2 as RccBus>::Bus,
-    ) -> Self
-    where
-        SCL: PinScl<I2C2>,
-        SDA: PinSda<I2C2>,
-    {
-        I2c::_i2c2(i2c, pins, mode, clocks, apb)
-    }
-}
+        I2C::enable(apb);
+        I2C::reset(apb);
 
-impl<SCL, SDA> BlockingI2c<I2C2, SCL, SDA> {
-    /// Creates a blocking I2C2 object using the embedded-hal `BlockingI2c` trait.
-    pub fn i2c2(
-        i2c: I2C2,
-        pins: (SCL, SDA),
-        mode: Mode,
-        clocks: Clocks,
-        apb: &mut <I2C2 as RccBus>::Bus,
-        data_timeout_us: u32,
-    ) -> Self
-    where
-        SCL: PinScl<I2C2>,
-        SDA: PinSda<I2C2>,
-    {
-        BlockingI2c::_i2c2(i2c, pins, mode, clocks, apb, data_timeout_us)
+        configure_timing(&i2c, pclk, &mode, &config)?;
+
+        Ok(I2c {
+            i2c,
+            pins,
+            mode,
+            pclk,
+        })
     }
 }
 
-impl<SCL, SDA> I2c<I2C3, SCL, SDA> {
-    /// Creates a generic I2C3 object.
-    pub fn i2c3(
-        i2c: I2C3,
+impl<I2C, SCL, SDA> BlockingI2c<I2C, SCL, SDA>
+where
+    I2C: Instance,
+{
+    /// Creates a blocking I2C object using the embedded-hal `BlockingI2c` trait.
+    pub fn new(
+        i2c: I2C,
         pins: (SCL, SDA),
         mode: Mode,
         clocks: Clocks,
-        apb: &mut <I2C3 as RccBus>::Bus,
+        apb: &mut I2C::Bus,
+        data_timeout_us: u32,
     ) -> Self
     where
-        SCL: PinScl<I2C3>,
-        SDA: PinSda<I2C3>,
+        SCL: PinScl<I2C>,
+        SDA: PinSda<I2C>,
     {
-        I2c::_i2c3(i2c, pins, mode, clocks, apb)
+        blocking_i2c(
+            I2c::new(i2c, pins, mode, clocks, apb),
+            clocks,
+            data_timeout_us,
+        )
     }
-}
 
-impl<SCL, SDA> BlockingI2c<I2C3, SCL, SDA> {
-    /// Creates a blocking I2C3 object using the embedded-hal `BlockingI2c` trait.
-    pub fn i2c3(
-        i2c: I2C3,
+    /// Creates a blocking I2C object, with the bus and filter parameters of
+    /// `config`
+    ///
+    /// See [`I2c::new_with_timing`].
+    pub fn new_with_timing(
+        i2c: I2C,
         pins: (SCL, SDA),
         mode: Mode,
+        config: TimingConfig,
         clocks: Clocks,
-        apb: &mut <I2C3 as RccBus>::Bus,
+        apb: &mut I2C::Bus,
         data_timeout_us: u32,
-    ) -> Self
+    ) -> Result<Self, TimingError>
     where
-        SCL: PinScl<I2C3>,
-        SDA: PinSda<I2C3>,
+        SCL: PinScl<I2C>,
+        SDA: PinSda<I2C>,
     {
-        BlockingI2c::_i2c3(i2c, pins, mode, clocks, apb, data_timeout_us)
+        Ok(blocking_i2c(
+            I2c::new_with_timing(i2c, pins, mode, config, clocks, apb)?,
+            clocks,
+            data_timeout_us,
+        ))
     }
 }
 
@@ -210,131 +301,263 @@ fn blocking_i2c<I2C, SCL, SDA>(
     }
 }
 
-// hddat and vddat are removed because SDADEL is always going to be 0 in this implementation so
-// condition is always met
-struct I2cSpec {
-    freq_max: u32,
-    sudat_min: u32,
-    _lscl_min: u32,
-    _hscl_min: u32,
-    trise_max: u32, // in ns
-    _tfall_max: u32,
+/// Bus and filter parameters used to compute the I2C timings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimingConfig {
+    /// Enable the analog noise filter, which suppresses spikes up to 50 ns
+    pub analog_filter: bool,
+    /// Digital noise filter length in kernel clock cycles (DNF), from 0
+    /// (disabled) to 15
+    pub digital_filter: u8,
+    /// SCL and SDA rise time of the bus, in nanoseconds
+    pub rise_time_ns: u32,
+    /// SCL and SDA fall time of the bus, in nanoseconds
+    pub fall_time_ns: u32,
 }
 
-#[derive(Debug)]
-struct I2cTiming {
-    presc: u8,
-    scldel: u8,
-    sdadel: u8,
-    sclh: u8,
-    scll: u8,
+impl Default for TimingConfig {
+    fn default() -> Self {
+        TimingConfig {
+            analog_filter: true,
+            digital_filter: 0,
+            rise_time_ns: 100,
+            fall_time_ns: 10,
+        }
+    }
+}
+
+impl TimingConfig {
+    /// Returns the default parameters for `mode`
+    ///
+    /// The rise time is typical of a bus with pull-ups sized for the mode,
+    /// well below the maximum of the specification: 100 ns in standard and
+    /// fast mode, and 50 ns in fast-mode plus.
+    pub fn for_mode(mode: &Mode) -> Self {
+        let rise_time_ns = match mode {
+            Mode::FastPlus { .. } => 50,
+            _ => 100,
+        };
+
+        TimingConfig {
+            rise_time_ns,
+            ..TimingConfig::default()
+        }
+    }
+}
+
+/// Values of the TIMINGR fields
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timing {
+    pub presc: u8,
+    pub scldel: u8,
+    pub sdadel: u8,
+    pub sclh: u8,
+    pub scll: u8,
+}
+
+impl Timing {
+    fn from_bits(timing_r: u32) -> Self {
+        Timing {
+            presc: ((timing_r & 0xf000_0000) >> 28) as u8,
+            scldel: ((timing_r & 0x00f0_0000) >> 20) as u8,
+            sdadel: ((timing_r & 0x000f_0000) >> 16) as u8,
+            sclh: ((timing_r & 0x0000_ff00) >> 8) as u8,
+            scll: (timing_r & 0x0000_00ff) as u8,
+        }
+    }
+
+    /// Returns the value of the TIMINGR register, e.g. for `Mode::Custom`
+    pub fn bits(&self) -> u32 {
+        u32::from(self.presc) << 28
+            | u32::from(self.scldel) << 20
+            | u32::from(self.sdadel) << 16
+            | u32::from(self.sclh) << 8
+            | u32::from(self.scll)
+    }
+}
+
+/// Error returned when the I2C timings can't be computed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimingError {
+    /// The SCL frequency is zero or above the maximum of the mode
+    FrequencyOutOfRange,
+    /// No register values meet the bus specification with this kernel clock,
+    /// rise/fall times and filters
+    NotAchievable,
+}
+
+/// Timing characteristics of a bus mode, from the I2C-bus specification
+struct I2cSpec {
+    /// Maximum SCL frequency, in Hz
+    rate_max: u32,
+    // All the durations are in ns
+    hddat_min: u32,
+    vddat_max: u32,
+    sudat_min: u32,
+    low_min: u32,
+    high_min: u32,
 }
 
-// everything is in nano seconds
 const I2C_STANDARD_MODE_SPEC: I2cSpec = I2cSpec {
-    freq_max: 102400,
+    rate_max: 100_000,
+    hddat_min: 0,
+    vddat_max: 3450,
     sudat_min: 250,
-    _lscl_min: 4700,
-    _hscl_min: 4000,
-    trise_max: 640,
-    _tfall_max: 20,
+    low_min: 4700,
+    high_min: 4000,
 };
+
 const I2C_FAST_MODE_SPEC: I2cSpec = I2cSpec {
-    freq_max: 409600,
+    rate_max: 400_000,
+    hddat_min: 0,
+    vddat_max: 900,
     sudat_min: 100,
-    _lscl_min: 1300,
-    _hscl_min: 600,
-    trise_max: 250,
-    _tfall_max: 100,
+    low_min: 1300,
+    high_min: 600,
 };
 
 const I2C_FAST_PLUS_MODE_SPEC: I2cSpec = I2cSpec {
-    freq_max: 1024000,
+    rate_max: 1_000_000,
+    hddat_min: 0,
+    vddat_max: 450,
     sudat_min: 50,
-    _lscl_min: 500,
-    _hscl_min: 260,
-    trise_max: 60,
-    _tfall_max: 100,
+    low_min: 500,
+    high_min: 260,
 };
 
-fn calculate_timing(
-    spec: I2cSpec,
-    i2c_freq: u32,
-    scl_freq: u32,
-    an_filter: bool,
-    dnf: u8,
-) -> I2cTiming {
-    // This dependency is not used when `cargo test`ing. More info:
-    // https://docs.rs/micromath/1.1.1/micromath/index.html#unused-import-warnings-when-linking-std
-    #[cfg(not(test))]
-    use micromath::F32Ext as _;
-
-    // frequency limit check
-    assert!(scl_freq <= spec.freq_max);
-    // T_sync or delay introduced in SCL
-    // generally it is 2-3 clock cycles
-    // t_sync + dnf delay
-    let t_dnf = (dnf) as f32 / i2c_freq as f32;
-    // if analog filter is enabled then it offer about 50 - 70 ns delay
-    let t_af: f32 = if an_filter {
-        40.0 / 1_000_000_000f32
-    } else {
-        0.0
+/// Computes the TIMINGR fields for `mode` with a kernel clock of `i2c_freq`
+///
+/// This follows the constraints of the reference manual (section "I2C
+/// timings") and AN4235: SDADEL and SCLDEL are chosen to meet the data hold
+/// and setup times, taking the rise/fall times and the analog and digital
+/// filter delays into account. SCLL and SCLH are then chosen to meet the
+/// minimum low and high periods, with the SCL frequency as close as possible
+/// to, but not above, the requested one, and at least 80% of it.
+///
+/// `Mode::Custom` values are returned as they are.
+pub fn compute_timing(
+    i2c_freq: Hertz,
+    mode: &Mode,
+    config: &TimingConfig,
+) -> Result<Timing, TimingError> {
+    let (spec, scl_freq) = match *mode {
+        Mode::Standard { frequency } => (&I2C_STANDARD_MODE_SPEC, frequency.raw()),
+        Mode::Fast { frequency } => (&I2C_FAST_MODE_SPEC, frequency.raw()),
+        Mode::FastPlus { frequency } => (&I2C_FAST_PLUS_MODE_SPEC, frequency.raw()),
+        Mode::Custom { timing_r } => return Ok(Timing::from_bits(timing_r)),
     };
-    // t_sync = 2 to 3 * i2cclk
-    let t_sync = 2.0 / (i2c_freq as f32);
-    // fall or rise time
-    let t_fall: f32 = 50f32 / 1_000_000_000f32;
-    let t_rise: f32 = 60f32 / 1_000_000_000f32;
-    let t_delay = t_fall + t_rise + 2.0 * (t_dnf + t_af + t_sync);
-    // formula is like F_i2cclk/F/F_scl_clk = (scl_h+scl_l+2)*(Presc + 1)
-    // consider scl_l+scl_h is 256 max. but in that case clock should always
-    // be 50% duty cycle. lets consider scl_l+scl_h to be 128. so that it can
-    // be changed later
-    // (scl_l+scl_h+2)(presc +1 ) ==> as scl_width*presc ==F_i2cclk/F/F_scl_clk
-    let product: f32 = (1.0 - t_delay * (scl_freq as f32)) * (i2c_freq / scl_freq) as f32;
-    let scl_l: u8;
-    let scl_h: u8;
-    let mut presc: u8;
-    // if ratio is > (scll+sclh)*presc. that frequancy is not possible to generate. so
-    // minimum frequancy possible is generated
-    if product > 8192_f32 {
-        // TODO: should we panic or use minimum allowed frequancy
-        scl_l = 0x7fu8;
-        scl_h = 0x7fu8;
-        presc = 0xfu8;
-    } else {
-        // smaller the minimum devition less difference between expected vs
-        // actual scl clock
-        let mut min_deviation = 16f32;
-        // TODO: use duty cycle and based on that use precstart
-        let presc_start = (product / 512.0).ceil() as u8;
-        presc = presc_start;
-        for tmp_presc in presc_start..17 {
-            let deviation = product % tmp_presc as f32;
-            if min_deviation > deviation {
-                min_deviation = deviation;
-                presc = tmp_presc as u8;
+    if scl_freq == 0 || scl_freq > spec.rate_max {
+        return Err(TimingError::FrequencyOutOfRange);
+    }
+    assert!(config.digital_filter <= 15);
+
+    // Everything below is in picoseconds
+    const PS: u64 = 1_000_000_000_000;
+    let ns = |t: u32| u64::from(t) * 1000;
+
+    let t_i2cclk = PS / u64::from(i2c_freq.raw());
+    let (af_min, af_max) = match config.analog_filter {
+        true => (ns(50), ns(260)),
+        false => (0, 0),
+    };
+    let dnf = u64::from(config.digital_filter);
+    let t_dnf = dnf * t_i2cclk;
+    let rise = ns(config.rise_time_ns);
+    let fall = ns(config.fall_time_ns);
+
+    let sdadel_min = (ns(spec.hddat_min) + fall).saturating_sub(af_min + (dnf + 3) * t_i2cclk);
+    let sdadel_max = ns(spec.vddat_max).saturating_sub(rise + af_max + (dnf + 4) * t_i2cclk);
+    let scldel_min = rise + ns(spec.sudat_min);
+
+    // Delay between SCL being driven and its level being detected
+    let t_sync = af_min + t_dnf + 2 * t_i2cclk;
+    let period_min = PS / u64::from(scl_freq);
+    let period_max = PS * 5 / (u64::from(scl_freq) * 4);
+
+    let mut best: Option<(u64, Timing)> = None;
+    for presc in 0..16 {
+        let t_presc = (presc + 1) * t_i2cclk;
+
+        let scldel = match (0..16).find(|l| (l + 1) * t_presc >= scldel_min) {
+            Some(scldel) => scldel,
+            None => continue,
+        };
+        let sdadel =
+            match (0..16).find(|a| (sdadel_min..=sdadel_max).contains(&(a * t_presc + t_i2cclk))) {
+                Some(sdadel) => sdadel,
+                None => continue,
+            };
+
+        for scll in 0..256 {
+            let t_low = (scll + 1) * t_presc + t_sync;
+            if t_low < ns(spec.low_min) || t_i2cclk >= (t_low - af_min - t_dnf) / 4 {
+                continue;
+            }
+            if t_low + t_presc + t_sync + rise + fall > period_max {
+                break;
+            }
+
+            for sclh in 0..256 {
+                let t_high = (sclh + 1) * t_presc + t_sync;
+                let period = t_low + t_high + rise + fall;
+                if period > period_max {
+                    break;
+                }
+                if period < period_min || t_high < ns(spec.high_min) || t_high <= t_i2cclk {
+                    continue;
+                }
+
+                let error = period - period_min;
+                if best.map_or(true, |(best_error, _)| error < best_error) {
+                    let timing = Timing {
+                        presc: presc as u8,
+                        scldel: scldel as u8,
+                        sdadel: sdadel as u8,
+                        sclh: sclh as u8,
+                        scll: scll as u8,
+                    };
+                    best = Some((error, timing));
+                }
             }
         }
-        // now that we have optimal prescalar value. optimal scl_l and scl_h
-        // needs to be calculated
-        let scl_width = (product / presc as f32) as u16; // it will be always less than 256
-        scl_h = (scl_width / 2 - 1) as u8;
-        scl_l = (scl_width - scl_h as u16 - 1) as u8; // This is to get max precision
-        presc -= 1;
-    }
-    let scldel: u8 = (((spec.trise_max + spec.sudat_min) as f32 / 1_000_000_000.0)
-        / ((presc + 1) as f32 / i2c_freq as f32)
-        - 1.0)
-        .ceil() as u8;
-    I2cTiming {
-        presc,
-        scldel,
-        sdadel: 0,
-        sclh: scl_h,
-        scll: scl_l,
     }
+
+    best.map(|(_, timing)| timing)
+        .ok_or(TimingError::NotAchievable)
+}
+
+/// Disables `i2c`, configures its filters and timings and enables it again
+fn configure_timing(
+    i2c: &pac::i2c1::RegisterBlock,
+    i2c_freq: Hertz,
+    mode: &Mode,
+    config: &TimingConfig,
+) -> Result<(), TimingError> {
+    let timing = compute_timing(i2c_freq, mode, config)?;
+
+    // The filters can only be changed while the peripheral is disabled
+    i2c.cr1.modify(|_, w| w.pe().disabled());
+    i2c.cr1.modify(|_, w| {
+        w.anfoff()
+            .bit(!config.analog_filter)
+            .dnf()
+            .bits(config.digital_filter)
+    });
+    i2c.timingr.write(|w| {
+        w.presc()
+            .bits(timing.presc)
+            .scll()
+            .bits(timing.scll)
+            .sclh()
+            .bits(timing.sclh)
+            .sdadel()
+            .bits(timing.sdadel)
+            .scldel()
+            .bits(timing.scldel)
+    });
+    i2c.cr1.modify(|_, w| w.pe().enabled());
+
+    Ok(())
 }
 
 macro_rules! check_status_flag {
@@ -353,6 +576,12 @@ macro_rules! check_status_flag {
         } else if isr.ovr().bit_is_set() {
             $i2c.icr.write(|w| w.stopcf().set_bit().ovrcf().set_bit());
             Err(Other(Error::Overrun))
+        } else if isr.pecerr().bit_is_set() {
+            $i2c.icr.write(|w| w.peccf().set_bit());
+            Err(Other(Error::Pec))
+        } else if isr.timeout().bit_is_set() {
+            $i2c.icr.write(|w| w.timoutcf().set_bit());
+            Err(Other(Error::Timeout))
         } else if isr.$flag().$status() {
             Ok(())
         } else {
@@ -383,273 +612,309 @@ macro_rules! busy_wait_cycles {
     }};
 }
 
-// Generate the same code for both I2Cs
-macro_rules! hal {
-    ($($I2CX:ident: ($i2cX:ident),)+) => {
-        $(
-            impl<SCL, SDA> I2c<$I2CX, SCL, SDA> {
-                /// Configures the I2C peripheral to work in master mode
-                fn $i2cX(
-                    i2c: $I2CX,
-                    pins: (SCL, SDA),
-                    mode: Mode,
-                    clocks: Clocks,
-                    apb: &mut <I2C1 as RccBus>::Bus
-                ) -> Self {
-                    $I2CX::enable(apb);
-                    $I2CX::reset(apb);
-
-                    let pclk = <$I2CX as RccBus>::Bus::get_frequency(&clocks);
-
-                    let mut i2c = I2c { i2c, pins, mode, pclk };
-                    i2c.init();
-                    i2c
-                }
-
-                /// Initializes I2C as master. Configures I2C_PRESC, I2C_SCLDEL,
-                /// I2C_SDAEL, I2C_SCLH, I2C_SCLL
-                ///
-                /// For now, only standard mode is implemented
-                fn init(&mut self) {
-                    // NOTE : operations are in float for better precision,
-                    // STM32F7 usually have FPU and this runs only at
-                    // initialization so the footprint of such heavy calculation
-                    // occurs only once
-                    // Disable I2C during configuration
-                    self.i2c.cr1.write(|w| w.pe().disabled());
-
-                    let an_filter:bool = self.i2c.cr1.read().anfoff().is_enabled();
-                    let dnf = self.i2c.cr1.read().dnf().bits();
-
-                    let i2c_timingr: I2cTiming =  match self.mode {
-                        Mode::Standard{ frequency } => calculate_timing(I2C_STANDARD_MODE_SPEC, self.pclk.raw(), frequency.raw(), an_filter, dnf ),
-                        Mode::Fast{ frequency } => calculate_timing(I2C_FAST_MODE_SPEC, self.pclk.raw(), frequency.raw(), an_filter, dnf),
-                        Mode::FastPlus{ frequency } => calculate_timing(I2C_FAST_PLUS_MODE_SPEC, self.pclk.raw(), frequency.raw(), an_filter, dnf ),
-                        Mode::Custom{ timing_r } => {
-                            I2cTiming{
-                                presc:  ((timing_r & 0xf000_0000) >> 28 ) as u8,
-                                scldel: ((timing_r & 0x00f0_0000) >> 20 ) as u8 ,
-                                sdadel: ((timing_r & 0x000f_0000) >> 16 ) as u8,
-                                sclh:   ((timing_r & 0x0000_ff00) >> 8 ) as u8,
-                                scll:   ((timing_r & 0x0000_00ff) >> 0 ) as u8,
-                            }
-                        }
-                    };
-                    self.i2c.timingr.write(|w|
-                        w.presc()
-                            .bits(i2c_timingr.presc)
-                            .scll()
-                            .bits(i2c_timingr.scll)
-                            .sclh()
-                            .bits(i2c_timingr.sclh)
-                            .sdadel()
-                            .bits(i2c_timingr.sdadel)
-                            .scldel()
-                            .bits(i2c_timingr.scldel)
-                    );
-
-                    self.i2c.cr1.modify(|_, w| w.pe().enabled());
-                }
+impl<I2C: Instance, SCL, SDA> I2c<I2C, SCL, SDA> {
+    /// Recomputes the bus timings for `config`, e.g. to set the rise and
+    /// fall times measured on the board or to enable the digital filter
+    ///
+    /// On error, the previous timings are kept.
+    pub fn set_timing(&mut self, config: TimingConfig) -> Result<(), TimingError> {
+        configure_timing(&self.i2c, self.pclk, &self.mode, &config)
+    }
 
-                /// Perform an I2C software reset
-                #[allow(dead_code)]
-                fn reset(&mut self) {
-                    self.i2c.cr1.write(|w| w.pe().disabled());
-                    // wait for disabled
-                    while self.i2c.cr1.read().pe().is_enabled() {}
+    /// Perform an I2C software reset
+    #[allow(dead_code)]
+    fn reset(&mut self) {
+        self.i2c.cr1.write(|w| w.pe().disabled());
+        // wait for disabled
+        while self.i2c.cr1.read().pe().is_enabled() {}
 
-                    // Re-enable
-                    self.i2c.cr1.write(|w| w.pe().enabled());
-                }
+        // Re-enable
+        self.i2c.cr1.write(|w| w.pe().enabled());
+    }
 
-                /// Set (7-bit) slave address, bus direction (write or read),
-                /// generate START condition and set address.
-                ///
-                /// The user has to specify the number `n_bytes` of bytes to
-                /// read. The peripheral automatically waits for the bus to be
-                /// free before sending the START and address
-                ///
-                /// Data transfers of more than 255 bytes are not yet
-                /// supported, 10-bit slave address are not yet supported
-                fn start(&self, addr: u8, n_bytes: u8, read: bool, auto_stop: bool) {
-                    self.i2c.cr2.write(|mut w| {
-                        // Setup data
-                        w = w.sadd()
-                            .bits(u16(addr << 1 | 0))
-                            .add10().clear_bit()
-                            .nbytes()
-                            .bits(n_bytes as u8)
-                            .start()
-                            .set_bit();
-
-                        // Setup transfer direction
-                        w = match read {
-                            true => w.rd_wrn().read(),
-                            false => w.rd_wrn().write()
-                        };
-
-                        // setup auto-stop
-                        match auto_stop {
-                            true => w.autoend().automatic(),
-                            false => w.autoend().software(),
-                        }
-                    });
-                }
+    /// Set (7-bit) slave address, bus direction (write or read),
+    /// generate START condition and set address.
+    ///
+    /// The user has to specify the number `n_bytes` of bytes to
+    /// read. The peripheral automatically waits for the bus to be
+    /// free before sending the START and address
+    ///
+    /// Data transfers of more than 255 bytes are not yet
+    /// supported, 10-bit slave address are not yet supported
+    fn start(&self, addr: u8, n_bytes: u8, read: bool, auto_stop: bool) {
+        self.start_reload(addr, n_bytes, read, auto_stop, false);
+    }
 
-                /// Releases the I2C peripheral and associated pins
-                pub fn free(self) -> ($I2CX, (SCL, SDA)) {
-                    (self.i2c, self.pins)
-                }
+    /// Same as `start`, additionally selecting whether more bytes
+    /// will follow in the same direction once `n_bytes` have been
+    /// transferred (RELOAD).
+    fn start_reload(&self, addr: u8, n_bytes: u8, read: bool, auto_stop: bool, reload: bool) {
+        self.i2c.cr2.write(|mut w| {
+            // Setup data
+            w = w
+                .sadd()
+                .bits(u16(addr << 1 | 0))
+                .add10()
+                .clear_bit()
+                .nbytes()
+                .bits(n_bytes as u8)
+                .reload()
+                .bit(reload)
+                .start()
+                .set_bit();
+
+            // Setup transfer direction
+            w = match read {
+                true => w.rd_wrn().read(),
+                false => w.rd_wrn().write(),
+            };
+
+            // setup auto-stop
+            match auto_stop {
+                true => w.autoend().automatic(),
+                false => w.autoend().software(),
             }
+        });
+    }
 
-            impl<SCL, SDA> BlockingI2c<$I2CX, SCL, SDA> {
-                fn $i2cX(
-                    i2c: $I2CX,
-                    pins: (SCL, SDA),
-                    mode: Mode,
-                    clocks: Clocks,
-                    apb: &mut <$I2CX as RccBus>::Bus,
-                    data_timeout_us: u32
-                ) -> Self {
-                    blocking_i2c(I2c::$i2cX(i2c, pins, mode, clocks, apb),
-                        clocks, data_timeout_us)
-                }
+    /// Releases the I2C peripheral and associated pins
+    pub fn free(self) -> (I2C, (SCL, SDA)) {
+        (self.i2c, self.pins)
+    }
+}
 
-                /// Wait for a byte to be read and return it (ie for RXNE flag
-                /// to be set)
-                fn wait_byte_read(&self) -> NbResult<u8, Error> {
-                    // Wait until we have received something
-                    busy_wait_cycles!(
-                        check_status_flag!(self.nb.i2c, rxne, is_not_empty),
-                        self.data_timeout
-                    )?;
+impl<I2C: Instance, SCL, SDA> BlockingI2c<I2C, SCL, SDA> {
+    /// Recomputes the bus timings for `config`
+    ///
+    /// See [`I2c::set_timing`].
+    pub fn set_timing(&mut self, config: TimingConfig) -> Result<(), TimingError> {
+        self.nb.set_timing(config)
+    }
 
-                    Ok(self.nb.i2c.rxdr.read().rxdata().bits())
-                }
+    /// Wait for a byte to be read and return it (ie for RXNE flag
+    /// to be set)
+    fn wait_byte_read(&self) -> NbResult<u8, Error> {
+        // Wait until we have received something
+        busy_wait_cycles!(
+            check_status_flag!(self.nb.i2c, rxne, is_not_empty),
+            self.data_timeout
+        )?;
 
-                /// Wait the write data register to be empty  (ie for TXIS flag
-                /// to be set) and write the byte to it
-                fn wait_byte_write(&self, byte: u8) -> NbResult<(), Error> {
-                    // Wait until we are allowed to send data
-                    // (START has been ACKed or last byte when through)
-                    busy_wait_cycles!(
-                        check_status_flag!(self.nb.i2c, txis, is_empty),
-                        self.data_timeout
-                    )?;
+        Ok(self.nb.i2c.rxdr.read().rxdata().bits())
+    }
 
-                    // Put byte on the wire
-                    self.nb.i2c.txdr.write(|w| w.txdata().bits(byte));
+    /// Wait the write data register to be empty  (ie for TXIS flag
+    /// to be set) and write the byte to it
+    fn wait_byte_write(&self, byte: u8) -> NbResult<(), Error> {
+        // Wait until we are allowed to send data
+        // (START has been ACKed or last byte when through)
+        busy_wait_cycles!(
+            check_status_flag!(self.nb.i2c, txis, is_empty),
+            self.data_timeout
+        )?;
 
-                    Ok(())
-                }
+        // Put byte on the wire
+        self.nb.i2c.txdr.write(|w| w.txdata().bits(byte));
 
-                /// Wait for any previous address sequence to end automatically.
-                fn wait_start(&self) {
-                    while self.nb.i2c.cr2.read().start().bit_is_set() {};
-                }
-            }
+        Ok(())
+    }
 
-            impl<SCL, SDA> Write for BlockingI2c<$I2CX, SCL, SDA> {
-                type Error = NbError<Error>;
+    /// Wait for any previous address sequence to end automatically.
+    fn wait_start(&self) {
+        while self.nb.i2c.cr2.read().start().bit_is_set() {}
+    }
+}
 
-                /// Write bytes to I2C. Currently, `bytes.len()` must be less or
-                /// equal than 255
-                fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
-                    // TODO support transfers of more than 255 bytes
-                    assert!(bytes.len() < 256 && bytes.len() > 0);
+impl<I2C: Instance, SCL, SDA> Write for BlockingI2c<I2C, SCL, SDA> {
+    type Error = NbError<Error>;
 
-                    // Wait for any previous address sequence to end
-                    // automatically. This could be up to 50% of a bus
-                    // cycle (ie. up to 0.5/freq)
-                    self.wait_start();
+    /// Write bytes to I2C. Currently, `bytes.len()` must be less or
+    /// equal than 255
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        // TODO support transfers of more than 255 bytes
+        assert!(bytes.len() < 256 && bytes.len() > 0);
 
-                    // Set START and prepare to send `bytes`. The
-                    // START bit can be set even if the bus is BUSY or
-                    // I2C is in slave mode.
-                    self.nb.start(addr, bytes.len() as u8, false, true);
+        // Wait for any previous address sequence to end
+        // automatically. This could be up to 50% of a bus
+        // cycle (ie. up to 0.5/freq)
+        self.wait_start();
 
-                    for byte in bytes {
-                        self.wait_byte_write(*byte)?;
-                    }
-                    // automatic STOP
+        // Set START and prepare to send `bytes`. The
+        // START bit can be set even if the bus is BUSY or
+        // I2C is in slave mode.
+        self.nb.start(addr, bytes.len() as u8, false, true);
 
-                    Ok(())
-                }
-            }
+        for byte in bytes {
+            self.wait_byte_write(*byte)?;
+        }
+        // automatic STOP
 
-            impl<SCL, SDA> Read for BlockingI2c<$I2CX, SCL, SDA> {
-                type Error = NbError<Error>;
+        Ok(())
+    }
+}
 
-                /// Reads enough bytes from slave with `address` to fill `buffer`
-                fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
-                    // TODO support transfers of more than 255 bytes
-                    assert!(buffer.len() < 256 && buffer.len() > 0);
+impl<I2C: Instance, SCL, SDA> Read for BlockingI2c<I2C, SCL, SDA> {
+    type Error = NbError<Error>;
 
-                    // Wait for any previous address sequence to end
-                    // automatically. This could be up to 50% of a bus
-                    // cycle (ie. up to 0.5/freq)
-                    self.wait_start();
+    /// Reads enough bytes from slave with `address` to fill `buffer`
+    fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        // TODO support transfers of more than 255 bytes
+        assert!(buffer.len() < 256 && buffer.len() > 0);
 
-                    // Set START and prepare to receive bytes into
-                    // `buffer`. The START bit can be set even if the bus
-                    // is BUSY or I2C is in slave mode.
-                    self.nb.start(addr, buffer.len() as u8, true, true);
+        // Wait for any previous address sequence to end
+        // automatically. This could be up to 50% of a bus
+        // cycle (ie. up to 0.5/freq)
+        self.wait_start();
 
-                    for byte in buffer {
-                        *byte = self.wait_byte_read()?;
-                    }
+        // Set START and prepare to receive bytes into
+        // `buffer`. The START bit can be set even if the bus
+        // is BUSY or I2C is in slave mode.
+        self.nb.start(addr, buffer.len() as u8, true, true);
 
-                    // automatic STOP
+        for byte in buffer {
+            *byte = self.wait_byte_read()?;
+        }
 
-                    Ok(())
-                }
-            }
+        // automatic STOP
 
-            impl<SCL, SDA> WriteRead for BlockingI2c<$I2CX, SCL, SDA> {
-                type Error = NbError<Error>;
-
-                fn write_read(
-                    &mut self,
-                    addr: u8,
-                    bytes: &[u8],
-                    buffer: &mut [u8],
-                ) -> Result<(), Self::Error> {
-                    // TODO support transfers of more than 255 bytes
-                    assert!(bytes.len() < 256 && bytes.len() > 0);
-                    assert!(buffer.len() < 256 && buffer.len() > 0);
-
-                    // Start and make sure we don't send STOP after the write
-                    self.wait_start();
-                    self.nb.start(addr, bytes.len() as u8, false, false);
-
-                    for byte in bytes {
-                        self.wait_byte_write(*byte)?;
-                    }
-
-                    // Wait until the write finishes before beginning to read.
-                    // busy_wait2!(self.nb.i2c, tc, is_complete);
-                    busy_wait_cycles!(
-                        check_status_flag!(self.nb.i2c, tc, is_complete),
-                        self.data_timeout
-                    )?;
-
-                    // reSTART and prepare to receive bytes into `buffer`
-                    self.nb.start(addr, buffer.len() as u8, true, true);
-
-                    for byte in buffer {
-                        *byte = self.wait_byte_read()?;
-                    }
-                    // automatic STOP
-
-                    Ok(())
-                }
-            }
-        )+
+        Ok(())
     }
 }
 
-hal! {
-    I2C1: (_i2c1),
-    I2C2: (_i2c2),
-    I2C3: (_i2c3),
+impl<I2C: Instance, SCL, SDA> WriteRead for BlockingI2c<I2C, SCL, SDA> {
+    type Error = NbError<Error>;
+
+    fn write_read(&mut self, addr: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Self::Error> {
+        // TODO support transfers of more than 255 bytes
+        assert!(bytes.len() < 256 && bytes.len() > 0);
+        assert!(buffer.len() < 256 && buffer.len() > 0);
+
+        // Start and make sure we don't send STOP after the write
+        self.wait_start();
+        self.nb.start(addr, bytes.len() as u8, false, false);
+
+        for byte in bytes {
+            self.wait_byte_write(*byte)?;
+        }
+
+        // Wait until the write finishes before beginning to read.
+        // busy_wait2!(self.nb.i2c, tc, is_complete);
+        busy_wait_cycles!(
+            check_status_flag!(self.nb.i2c, tc, is_complete),
+            self.data_timeout
+        )?;
+
+        // reSTART and prepare to receive bytes into `buffer`
+        self.nb.start(addr, buffer.len() as u8, true, true);
+
+        for byte in buffer {
+            *byte = self.wait_byte_read()?;
+        }
+        // automatic STOP
+
+        Ok(())
+    }
+}
+
+mod slave;
+mod smbus;
+mod transfer;
+pub use slave::{
+    Address, AddressMask, Event, I2cSlave, SlaveConfig, SlaveEvent, TransferDirection,
+};
+pub use smbus::{PinSmba, Smbus, SmbusConfig, SmbusPins};
+pub use transfer::{Rx, Transfer, TransferError, TransferResources, Tx, WaitResult};
+
+#[cfg(feature = "eh1")]
+mod hal_1;
+#[cfg(feature = "async")]
+mod hal_async;
+#[cfg(feature = "async")]
+pub use hal_async::on_interrupt;
+
+#[cfg(test)]
+mod tests {
+    use fugit::RateExtU32;
+
+    use super::{compute_timing, Mode, Timing, TimingConfig, TimingError};
+
+    #[test]
+    fn test_compute_timing() {
+        let config = TimingConfig::default();
+
+        let timing = compute_timing(16.MHz(), &Mode::standard(100.kHz()), &config);
+        assert_eq!(
+            timing,
+            Ok(Timing {
+                presc: 0,
+                scldel: 5,
+                sdadel: 0,
+                sclh: 79,
+                scll: 72,
+            })
+        );
+
+        let timing = compute_timing(216.MHz(), &Mode::fast(400.kHz()), &config);
+        assert_eq!(
+            timing,
+            Ok(Timing {
+                presc: 2,
+                scldel: 14,
+                sdadel: 0,
+                sclh: 73,
+                scll: 89,
+            })
+        );
+    }
+
+    #[test]
+    fn test_compute_timing_filters() {
+        // The analog filter delay leaves no room for the data hold time
+        let mode = Mode::fast_plus(1.MHz());
+        let config = TimingConfig::default();
+        assert_eq!(
+            compute_timing(54.MHz(), &mode, &config),
+            Err(TimingError::NotAchievable)
+        );
+
+        // A faster rise time, as is typical of fast-mode plus buses, leaves
+        // enough room
+        let config = TimingConfig::for_mode(&mode);
+        assert!(compute_timing(54.MHz(), &mode, &config).is_ok());
+        let config = TimingConfig::default();
+
+        let config = TimingConfig {
+            analog_filter: false,
+            ..config
+        };
+        assert_eq!(
+            compute_timing(54.MHz(), &mode, &config),
+            Ok(Timing {
+                presc: 0,
+                scldel: 8,
+                sdadel: 0,
+                sclh: 18,
+                scll: 25,
+            })
+        );
+    }
+
+    #[test]
+    fn test_compute_timing_errors() {
+        let config = TimingConfig::default();
+        assert_eq!(
+            compute_timing(16.MHz(), &Mode::standard(200.kHz()), &config),
+            Err(TimingError::FrequencyOutOfRange)
+        );
+        assert_eq!(
+            compute_timing(8.MHz(), &Mode::fast(400.kHz()), &config),
+            Err(TimingError::NotAchievable)
+        );
+
+        let mode = Mode::Custom {
+            timing_r: 0x3042_0f13,
+        };
+        let timing = compute_timing(16.MHz(), &mode, &config).unwrap();
+        assert_eq!(timing.bits(), 0x3042_0f13);
+    }
 }
@@ -0,0 +1,123 @@
+use embedded_hal_one::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+use nb::Error::{Other, WouldBlock};
+
+use super::{BlockingI2c, Error, Instance, DWT};
+
+impl embedded_hal_one::i2c::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Bus => ErrorKind::Bus,
+            Error::Arbitration => ErrorKind::ArbitrationLoss,
+            Error::Acknowledge => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            Error::Overrun => ErrorKind::Overrun,
+            Error::Busy | Error::Pec | Error::Timeout => ErrorKind::Other,
+        }
+    }
+}
+
+/// Flattens the errors of the blocking implementation
+///
+/// `WouldBlock` means that the data timeout expired while waiting for the bus.
+fn flatten(err: nb::Error<Error>) -> Error {
+    match err {
+        Other(err) => err,
+        WouldBlock => Error::Busy,
+    }
+}
+
+impl<I2C: Instance, SCL, SDA> ErrorType for BlockingI2c<I2C, SCL, SDA> {
+    type Error = Error;
+}
+
+impl<I2C: Instance, SCL, SDA> I2c for BlockingI2c<I2C, SCL, SDA> {
+    /// Executes `operations` as a single transaction
+    ///
+    /// Adjacent operations of the same kind are chained with the
+    /// NBYTES reload mechanism, and a repeated START is only sent
+    /// when the direction changes. Operations longer than 255 bytes
+    /// are split into chunks the same way.
+    fn transaction(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let n = operations.len();
+
+        // Wait for any previous address sequence to end
+        // automatically
+        self.wait_start();
+
+        for i in 0..n {
+            let read = matches!(operations[i], Operation::Read(_));
+            let first = i == 0 || matches!(operations[i - 1], Operation::Read(_)) != read;
+            let chained = i + 1 < n && matches!(operations[i + 1], Operation::Read(_)) == read;
+            let last = i + 1 == n;
+
+            let len = match &operations[i] {
+                Operation::Read(buffer) => buffer.len(),
+                Operation::Write(bytes) => bytes.len(),
+            };
+
+            // Chunks of up to 255 bytes
+            let mut offset = 0;
+            loop {
+                let end = len.min(offset + 255);
+                let n_bytes = (end - offset) as u8;
+                let reload = end < len || chained;
+
+                if first && offset == 0 {
+                    // (re)START, and STOP automatically after the last
+                    // operation
+                    self.nb.start_reload(addr, n_bytes, read, last, reload);
+                } else {
+                    // Wait for the previous chunk to be transferred
+                    // before reloading NBYTES
+                    busy_wait_cycles!(
+                        check_status_flag!(self.nb.i2c, tcr, is_complete),
+                        self.data_timeout
+                    )
+                    .map_err(flatten)?;
+
+                    self.nb.i2c.cr2.modify(|_, w| {
+                        let w = w.nbytes().bits(n_bytes).reload().bit(reload);
+                        if last {
+                            w.autoend().automatic()
+                        } else {
+                            w.autoend().software()
+                        }
+                    });
+                }
+
+                match &mut operations[i] {
+                    Operation::Read(buffer) => {
+                        for byte in buffer[offset..end].iter_mut() {
+                            *byte = self.wait_byte_read().map_err(flatten)?;
+                        }
+                    }
+                    Operation::Write(bytes) => {
+                        for byte in bytes[offset..end].iter() {
+                            self.wait_byte_write(*byte).map_err(flatten)?;
+                        }
+                    }
+                }
+
+                offset = end;
+                if offset == len {
+                    break;
+                }
+            }
+
+            if !last && !chained {
+                // Wait until the transfer finishes before the
+                // repeated START
+                busy_wait_cycles!(
+                    check_status_flag!(self.nb.i2c, tc, is_complete),
+                    self.data_timeout
+                )
+                .map_err(flatten)?;
+            }
+        }
+
+        Ok(())
+    }
+}
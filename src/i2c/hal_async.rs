@@ -0,0 +1,145 @@
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embedded_hal_async::i2c::{ErrorType, Operation};
+use nb::Error::{Other, WouldBlock};
+
+use super::{Error, I2c, Instance};
+
+/// Wakes the task waiting on the I2C instance `I2C`
+///
+/// Call this from both the event and the error interrupt handlers of the I2C
+/// instance when using the `async` APIs. The interrupts stay disabled until
+/// the waiting task has been polled again.
+pub fn on_interrupt<I2C: Instance>() {
+    // NOTE(unsafe) atomic read-modify-write of interrupt enable bits only set
+    // by the waiting task
+    unsafe {
+        (*I2C::ptr()).cr1.modify(|_, w| {
+            w.txie()
+                .clear_bit()
+                .rxie()
+                .clear_bit()
+                .tcie()
+                .clear_bit()
+                .nackie()
+                .clear_bit()
+                .errie()
+                .clear_bit()
+        })
+    };
+    I2C::waker().wake();
+}
+
+/// Waits for a status flag, sleeping until the next I2C interrupt
+macro_rules! wait_flag {
+    ($i2c:expr, $I2CX:ident, $flag:ident, $status:ident) => {
+        poll_fn(|cx| {
+            <$I2CX as Instance>::waker().register(cx.waker());
+
+            match check_status_flag!($i2c, $flag, $status) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(Other(e)) => Poll::Ready(Err(e)),
+                Err(WouldBlock) => {
+                    $i2c.cr1.modify(|_, w| {
+                        w.txie()
+                            .set_bit()
+                            .rxie()
+                            .set_bit()
+                            .tcie()
+                            .set_bit()
+                            .nackie()
+                            .set_bit()
+                            .errie()
+                            .set_bit()
+                    });
+                    Poll::Pending
+                }
+            }
+        })
+    };
+}
+
+impl<I2C: Instance, SCL, SDA> ErrorType for I2c<I2C, SCL, SDA> {
+    type Error = Error;
+}
+
+impl<I2C: Instance, SCL, SDA> embedded_hal_async::i2c::I2c for I2c<I2C, SCL, SDA> {
+    /// Executes `operations` as a single transaction
+    ///
+    /// The sequencing is the same as for the blocking
+    /// implementation, including the chunking of operations longer
+    /// than 255 bytes.
+    async fn transaction(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let n = operations.len();
+
+        // Wait for any previous address sequence to end
+        // automatically
+        while self.i2c.cr2.read().start().bit_is_set() {}
+
+        for i in 0..n {
+            let read = matches!(operations[i], Operation::Read(_));
+            let first = i == 0 || matches!(operations[i - 1], Operation::Read(_)) != read;
+            let chained = i + 1 < n && matches!(operations[i + 1], Operation::Read(_)) == read;
+            let last = i + 1 == n;
+
+            let len = match &operations[i] {
+                Operation::Read(buffer) => buffer.len(),
+                Operation::Write(bytes) => bytes.len(),
+            };
+
+            // Chunks of up to 255 bytes
+            let mut offset = 0;
+            loop {
+                let end = len.min(offset + 255);
+                let n_bytes = (end - offset) as u8;
+                let reload = end < len || chained;
+
+                if first && offset == 0 {
+                    self.start_reload(addr, n_bytes, read, last, reload);
+                } else {
+                    wait_flag!(self.i2c, I2C, tcr, is_complete).await?;
+
+                    self.i2c.cr2.modify(|_, w| {
+                        let w = w.nbytes().bits(n_bytes).reload().bit(reload);
+                        if last {
+                            w.autoend().automatic()
+                        } else {
+                            w.autoend().software()
+                        }
+                    });
+                }
+
+                match &mut operations[i] {
+                    Operation::Read(buffer) => {
+                        for byte in buffer[offset..end].iter_mut() {
+                            wait_flag!(self.i2c, I2C, rxne, is_not_empty).await?;
+                            *byte = self.i2c.rxdr.read().rxdata().bits();
+                        }
+                    }
+                    Operation::Write(bytes) => {
+                        for byte in bytes[offset..end].iter() {
+                            wait_flag!(self.i2c, I2C, txis, is_empty).await?;
+                            self.i2c.txdr.write(|w| w.txdata().bits(*byte));
+                        }
+                    }
+                }
+
+                offset = end;
+                if offset == len {
+                    break;
+                }
+            }
+
+            if !last && !chained {
+                wait_flag!(self.i2c, I2C, tc, is_complete).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
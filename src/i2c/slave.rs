@@ -0,0 +1,326 @@
+//! I2C slave (target) mode
+
+use nb::Error::{Other, WouldBlock};
+
+use super::{compute_timing, Error, Instance, Mode, PinScl, PinSda, TimingConfig, TimingError};
+use crate::rcc::Clocks;
+
+/// Own address of an I2C slave
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Address {
+    /// 7-bit address, without the R/W bit
+    SevenBit(u8),
+    /// 10-bit address
+    TenBit(u16),
+}
+
+/// Bits of the secondary address ignored when matching an incoming address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressMask {
+    /// All bits are compared
+    None,
+    /// Bit 1 is ignored
+    Mask1,
+    /// Bits 1 to 2 are ignored
+    Mask2,
+    /// Bits 1 to 3 are ignored
+    Mask3,
+    /// Bits 1 to 4 are ignored
+    Mask4,
+    /// Bits 1 to 5 are ignored
+    Mask5,
+    /// Bits 1 to 6 are ignored
+    Mask6,
+    /// All addresses except the reserved ones match
+    Mask7,
+}
+
+/// I2C slave configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlaveConfig {
+    /// Primary own address (OAR1)
+    pub address: Address,
+    /// Secondary 7-bit own address and its mask (OAR2)
+    pub secondary_address: Option<(u8, AddressMask)>,
+    /// Acknowledge the general call address 0x00
+    pub general_call: bool,
+    /// Stretch SCL while the application handles an event
+    ///
+    /// Without clock stretching, the application must keep up with the bus,
+    /// otherwise overrun and underrun errors are reported.
+    pub clock_stretching: bool,
+}
+
+impl SlaveConfig {
+    pub fn new(address: Address) -> Self {
+        SlaveConfig {
+            address,
+            secondary_address: None,
+            general_call: false,
+            clock_stretching: true,
+        }
+    }
+}
+
+/// Direction of a transfer, as seen from the master
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferDirection {
+    /// The master writes, the slave receives
+    Write,
+    /// The master reads, the slave transmits
+    Read,
+}
+
+/// Event reported by `I2cSlave::next_event`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlaveEvent {
+    /// The master addressed this slave
+    ///
+    /// `address` holds the matched 7-bit address, or the header of a 10-bit
+    /// address, e.g. to tell the primary, secondary and general call
+    /// addresses apart.
+    AddressMatch {
+        address: u8,
+        direction: TransferDirection,
+    },
+    /// A byte has been received from the master
+    Received(u8),
+    /// The master requests a byte, to be provided with `I2cSlave::write`
+    ///
+    /// With clock stretching enabled, the bus is held until the byte is
+    /// written.
+    ReadRequest,
+    /// The master did not acknowledge the last byte sent to it
+    Nack,
+    /// A STOP condition ended the transfer
+    Stop,
+}
+
+/// Interrupt events of an I2C slave
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Own address matched (ADDR)
+    AddressMatch,
+    /// A byte has been received (RXNE)
+    Receive,
+    /// The master requests a byte (TXIS)
+    Transmit,
+    /// A NACK has been received (NACKF)
+    Nack,
+    /// A STOP condition has been detected (STOPF)
+    Stop,
+    /// Bus error, arbitration loss or overrun/underrun
+    Error,
+}
+
+/// I2C peripheral operating in slave mode
+pub struct I2cSlave<I2C, SCL, SDA> {
+    i2c: I2C,
+    pins: (SCL, SDA),
+}
+
+impl<I2C, SCL, SDA> I2cSlave<I2C, SCL, SDA>
+where
+    I2C: Instance,
+    SCL: PinScl<I2C>,
+    SDA: PinSda<I2C>,
+{
+    /// Configures the I2C peripheral to work in slave mode
+    ///
+    /// `mode` is the bus speed, from which the data setup and hold times are
+    /// derived.
+    ///
+    /// Panics if the timings can't be generated with
+    /// `TimingConfig::for_mode(&mode)`, see `new_with_timing`.
+    pub fn new(
+        i2c: I2C,
+        pins: (SCL, SDA),
+        mode: Mode,
+        config: SlaveConfig,
+        clocks: Clocks,
+        apb: &mut I2C::Bus,
+    ) -> Self {
+        let timing = TimingConfig::for_mode(&mode);
+        Self::new_with_timing(i2c, pins, mode, timing, config, clocks, apb)
+            .expect("I2C bus frequency can't be generated")
+    }
+
+    /// Configures the I2C peripheral to work in slave mode, with the bus and
+    /// filter parameters of `timing`
+    pub fn new_with_timing(
+        i2c: I2C,
+        pins: (SCL, SDA),
+        mode: Mode,
+        timing: TimingConfig,
+        config: SlaveConfig,
+        clocks: Clocks,
+        apb: &mut I2C::Bus,
+    ) -> Result<Self, TimingError> {
+        let t = compute_timing(clocks.kernel_clock::<I2C>(), &mode, &timing)?;
+
+        I2C::enable(apb);
+        I2C::reset(apb);
+
+        // Disable I2C during configuration
+        i2c.cr1.write(|w| w.pe().disabled());
+
+        i2c.timingr.write(|w| {
+            w.presc()
+                .bits(t.presc)
+                .scll()
+                .bits(t.scll)
+                .sclh()
+                .bits(t.sclh)
+                .sdadel()
+                .bits(t.sdadel)
+                .scldel()
+                .bits(t.scldel)
+        });
+
+        // The own addresses can only be changed while they are disabled
+        i2c.oar1.write(|w| w.oa1en().clear_bit());
+        i2c.oar2.write(|w| w.oa2en().clear_bit());
+
+        match config.address {
+            Address::SevenBit(address) => {
+                assert!(address < 0x80);
+                i2c.oar1.write(|w| {
+                    w.oa1()
+                        .bits(u16::from(address) << 1)
+                        .oa1mode()
+                        .clear_bit()
+                        .oa1en()
+                        .set_bit()
+                });
+            }
+            Address::TenBit(address) => {
+                assert!(address < 0x400);
+                i2c.oar1
+                    .write(|w| w.oa1().bits(address).oa1mode().set_bit().oa1en().set_bit());
+            }
+        }
+
+        if let Some((address, mask)) = config.secondary_address {
+            assert!(address < 0x80);
+            i2c.oar2.write(|w| {
+                w.oa2()
+                    .bits(address)
+                    .oa2msk()
+                    .bits(mask as u8)
+                    .oa2en()
+                    .set_bit()
+            });
+        }
+
+        i2c.cr1.write(|w| {
+            w.gcen()
+                .bit(config.general_call)
+                .nostretch()
+                .bit(!config.clock_stretching)
+                .anfoff()
+                .bit(!timing.analog_filter)
+                .dnf()
+                .bits(timing.digital_filter)
+                .pe()
+                .enabled()
+        });
+
+        Ok(I2cSlave { i2c, pins })
+    }
+}
+
+impl<I2C, SCL, SDA> I2cSlave<I2C, SCL, SDA>
+where
+    I2C: Instance,
+{
+    /// Starts listening for an interrupt event
+    pub fn listen(&mut self, event: Event) {
+        match event {
+            Event::AddressMatch => self.i2c.cr1.modify(|_, w| w.addrie().set_bit()),
+            Event::Receive => self.i2c.cr1.modify(|_, w| w.rxie().set_bit()),
+            Event::Transmit => self.i2c.cr1.modify(|_, w| w.txie().set_bit()),
+            Event::Nack => self.i2c.cr1.modify(|_, w| w.nackie().set_bit()),
+            Event::Stop => self.i2c.cr1.modify(|_, w| w.stopie().set_bit()),
+            Event::Error => self.i2c.cr1.modify(|_, w| w.errie().set_bit()),
+        }
+    }
+
+    /// Stops listening for an interrupt event
+    pub fn unlisten(&mut self, event: Event) {
+        match event {
+            Event::AddressMatch => self.i2c.cr1.modify(|_, w| w.addrie().clear_bit()),
+            Event::Receive => self.i2c.cr1.modify(|_, w| w.rxie().clear_bit()),
+            Event::Transmit => self.i2c.cr1.modify(|_, w| w.txie().clear_bit()),
+            Event::Nack => self.i2c.cr1.modify(|_, w| w.nackie().clear_bit()),
+            Event::Stop => self.i2c.cr1.modify(|_, w| w.stopie().clear_bit()),
+            Event::Error => self.i2c.cr1.modify(|_, w| w.errie().clear_bit()),
+        }
+    }
+
+    /// Returns the next pending event, clearing its flag
+    ///
+    /// This is meant to be called from the I2C event and error interrupt
+    /// handlers, or polled. Events are reported in bus order: the address
+    /// match first, then the data bytes, then the STOP condition.
+    pub fn next_event(&mut self) -> nb::Result<SlaveEvent, Error> {
+        let isr = self.i2c.isr.read();
+
+        if isr.berr().bit_is_set() {
+            self.i2c.icr.write(|w| w.berrcf().set_bit());
+            Err(Other(Error::Bus))
+        } else if isr.arlo().bit_is_set() {
+            self.i2c.icr.write(|w| w.arlocf().set_bit());
+            Err(Other(Error::Arbitration))
+        } else if isr.ovr().bit_is_set() {
+            self.i2c.icr.write(|w| w.ovrcf().set_bit());
+            Err(Other(Error::Overrun))
+        } else if isr.rxne().bit_is_set() {
+            // Received data is reported before a following STOP or repeated
+            // START
+            Ok(SlaveEvent::Received(self.i2c.rxdr.read().rxdata().bits()))
+        } else if isr.addr().bit_is_set() {
+            let direction = if isr.dir().bit_is_set() {
+                // Flush the data register, so the first byte sent is the one
+                // written after this event
+                self.i2c.isr.write(|w| w.txe().set_bit());
+                TransferDirection::Read
+            } else {
+                TransferDirection::Write
+            };
+            let address = isr.addcode().bits();
+
+            // Clearing ADDR releases SCL
+            self.i2c.icr.write(|w| w.addrcf().set_bit());
+
+            Ok(SlaveEvent::AddressMatch { address, direction })
+        } else if isr.nackf().bit_is_set() {
+            self.i2c.icr.write(|w| w.nackcf().set_bit());
+            Ok(SlaveEvent::Nack)
+        } else if isr.stopf().bit_is_set() {
+            // Discard a byte written but never sent
+            self.i2c.isr.write(|w| w.txe().set_bit());
+            self.i2c.icr.write(|w| w.stopcf().set_bit());
+            Ok(SlaveEvent::Stop)
+        } else if isr.txis().bit_is_set() {
+            Ok(SlaveEvent::ReadRequest)
+        } else {
+            Err(WouldBlock)
+        }
+    }
+
+    /// Sends `byte` to the master in response to `SlaveEvent::ReadRequest`
+    pub fn write(&mut self, byte: u8) {
+        self.i2c.txdr.write(|w| w.txdata().bits(byte));
+    }
+
+    /// Returns `true` while a transfer is ongoing on the bus
+    pub fn is_busy(&self) -> bool {
+        self.i2c.isr.read().busy().bit_is_set()
+    }
+
+    /// Releases the I2C peripheral and associated pins
+    pub fn free(self) -> (I2C, (SCL, SDA)) {
+        self.i2c.cr1.write(|w| w.pe().disabled());
+        (self.i2c, self.pins)
+    }
+}
@@ -0,0 +1,471 @@
+//! SMBus/PMBus host mode
+
+use nb::Error::{Other, WouldBlock};
+
+use super::{
+    compute_timing, Error, Instance, Mode, PinScl, PinSda, TimingConfig, TimingError, I2C1, I2C2,
+    I2C3,
+};
+use crate::gpio::{self, Alternate, OpenDrain};
+use crate::rcc::Clocks;
+
+/// Marker trait to define SMBALERT pins for an I2C interface.
+pub trait PinSmba<I2C> {}
+
+impl PinSmba<I2C1> for gpio::PB5<Alternate<4, OpenDrain>> {}
+impl PinSmba<I2C2> for gpio::PB12<Alternate<4, OpenDrain>> {}
+impl PinSmba<I2C2> for gpio::PF2<Alternate<4, OpenDrain>> {}
+impl PinSmba<I2C2> for gpio::PH6<Alternate<4, OpenDrain>> {}
+impl PinSmba<I2C3> for gpio::PA9<Alternate<4, OpenDrain>> {}
+impl PinSmba<I2C3> for gpio::PH9<Alternate<4, OpenDrain>> {}
+
+/// Pins of an SMBus host, with an optional SMBALERT pin
+pub trait SmbusPins<I2C> {
+    const ALERT: bool = false;
+}
+
+impl<I2C, SCL, SDA> SmbusPins<I2C> for (SCL, SDA)
+where
+    SCL: PinScl<I2C>,
+    SDA: PinSda<I2C>,
+{
+}
+
+impl<I2C, SCL, SDA, SMBA> SmbusPins<I2C> for (SCL, SDA, SMBA)
+where
+    SCL: PinScl<I2C>,
+    SDA: PinSda<I2C>,
+    SMBA: PinSmba<I2C>,
+{
+    const ALERT: bool = true;
+}
+
+/// SMBus host configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SmbusConfig {
+    /// Append a Packet Error Code to every transfer and check received ones
+    pub pec: bool,
+    /// Maximum time SCL may be held low, in microseconds (tTIMEOUT, 25 ms
+    /// in the SMBus specification)
+    ///
+    /// Without this timeout, a transfer blocks forever on a stuck bus.
+    pub scl_low_timeout_us: Option<u32>,
+    /// Maximum cumulative time the host may stretch the clock in a byte, in
+    /// microseconds (tLOW:MEXT, 10 ms in the SMBus specification)
+    pub clock_extension_timeout_us: Option<u32>,
+}
+
+impl Default for SmbusConfig {
+    fn default() -> Self {
+        SmbusConfig {
+            pec: false,
+            scl_low_timeout_us: Some(25_000),
+            clock_extension_timeout_us: Some(10_000),
+        }
+    }
+}
+
+/// How a transfer started with `Smbus::start` ends after its last byte
+#[derive(Clone, Copy)]
+enum End {
+    /// Automatic STOP
+    Stop,
+    /// TC is set, for a repeated START
+    Restart,
+    /// TCR is set, for the transfer to be extended
+    Reload,
+}
+
+/// Returns the TIMEOUTA/TIMEOUTB value for a timeout of `us` microseconds
+/// with a kernel clock of `i2c_freq`
+fn timeout_bits(us: u32, i2c_freq: u32) -> u16 {
+    // The timeouts count in units of 2048 kernel clock cycles
+    let units = (u64::from(us) * u64::from(i2c_freq) + 2048 * 1_000_000 - 1) / (2048 * 1_000_000);
+    assert!(units >= 1 && units <= 0x1000);
+    (units - 1) as u16
+}
+
+/// I2C peripheral operating as an SMBus/PMBus host
+pub struct Smbus<I2C, PINS> {
+    i2c: I2C,
+    pins: PINS,
+    pec: bool,
+}
+
+impl<I2C, PINS> Smbus<I2C, PINS>
+where
+    I2C: Instance,
+    PINS: SmbusPins<I2C>,
+{
+    /// Configures the I2C peripheral to work as an SMBus host
+    ///
+    /// SMBALERT is monitored when `pins` includes a `PinSmba`.
+    ///
+    /// Panics if the bus frequency can't be generated with
+    /// `TimingConfig::for_mode(&mode)`, see `new_with_timing`.
+    pub fn new(
+        i2c: I2C,
+        pins: PINS,
+        mode: Mode,
+        config: SmbusConfig,
+        clocks: Clocks,
+        apb: &mut I2C::Bus,
+    ) -> Self {
+        let timing = TimingConfig::for_mode(&mode);
+        Self::new_with_timing(i2c, pins, mode, timing, config, clocks, apb)
+            .expect("I2C bus frequency can't be generated")
+    }
+
+    /// Configures the I2C peripheral to work as an SMBus host, with the bus
+    /// and filter parameters of `timing`
+    pub fn new_with_timing(
+        i2c: I2C,
+        pins: PINS,
+        mode: Mode,
+        timing: TimingConfig,
+        config: SmbusConfig,
+        clocks: Clocks,
+        apb: &mut I2C::Bus,
+    ) -> Result<Self, TimingError> {
+        let i2c_freq = clocks.kernel_clock::<I2C>();
+        let t = compute_timing(i2c_freq, &mode, &timing)?;
+
+        I2C::enable(apb);
+        I2C::reset(apb);
+
+        // Disable I2C during configuration
+        i2c.cr1.write(|w| w.pe().disabled());
+
+        i2c.timingr.write(|w| {
+            w.presc()
+                .bits(t.presc)
+                .scll()
+                .bits(t.scll)
+                .sclh()
+                .bits(t.sclh)
+                .sdadel()
+                .bits(t.sdadel)
+                .scldel()
+                .bits(t.scldel)
+        });
+
+        // The timeouts can only be changed while they are disabled
+        i2c.timeoutr
+            .write(|w| w.timouten().clear_bit().texten().clear_bit());
+        i2c.timeoutr.write(|w| {
+            if let Some(us) = config.scl_low_timeout_us {
+                w.timeouta()
+                    .bits(timeout_bits(us, i2c_freq.raw()))
+                    .tidle()
+                    .clear_bit()
+                    .timouten()
+                    .set_bit();
+            }
+            if let Some(us) = config.clock_extension_timeout_us {
+                w.timeoutb()
+                    .bits(timeout_bits(us, i2c_freq.raw()))
+                    .texten()
+                    .set_bit();
+            }
+            w
+        });
+
+        i2c.cr1.write(|w| {
+            w.smbhen()
+                .set_bit()
+                .pecen()
+                .bit(config.pec)
+                .alerten()
+                .bit(PINS::ALERT)
+                .anfoff()
+                .bit(!timing.analog_filter)
+                .dnf()
+                .bits(timing.digital_filter)
+                .pe()
+                .enabled()
+        });
+
+        Ok(Smbus {
+            i2c,
+            pins,
+            pec: config.pec,
+        })
+    }
+}
+
+impl<I2C, PINS> Smbus<I2C, PINS>
+where
+    I2C: Instance,
+{
+    /// Sends a byte without a command code (Send Byte)
+    pub fn send_byte(&mut self, addr: u8, byte: u8) -> Result<(), Error> {
+        self.write(addr, &[byte])
+    }
+
+    /// Receives a byte without a command code (Receive Byte)
+    pub fn receive_byte(&mut self, addr: u8) -> Result<u8, Error> {
+        let mut buffer = [0];
+        self.read(addr, &[], &mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    /// Writes a byte to the register `command` (Write Byte)
+    pub fn write_byte_data(&mut self, addr: u8, command: u8, byte: u8) -> Result<(), Error> {
+        self.write(addr, &[command, byte])
+    }
+
+    /// Reads a byte from the register `command` (Read Byte)
+    pub fn read_byte_data(&mut self, addr: u8, command: u8) -> Result<u8, Error> {
+        let mut buffer = [0];
+        self.read(addr, &[command], &mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    /// Writes a little-endian word to the register `command` (Write Word)
+    pub fn write_word_data(&mut self, addr: u8, command: u8, word: u16) -> Result<(), Error> {
+        let [low, high] = word.to_le_bytes();
+        self.write(addr, &[command, low, high])
+    }
+
+    /// Reads a little-endian word from the register `command` (Read Word)
+    pub fn read_word_data(&mut self, addr: u8, command: u8) -> Result<u16, Error> {
+        let mut buffer = [0; 2];
+        self.read(addr, &[command], &mut buffer)?;
+        Ok(u16::from_le_bytes(buffer))
+    }
+
+    /// Writes a word and reads the word returned by the device (Process
+    /// Call)
+    pub fn process_call(&mut self, addr: u8, command: u8, word: u16) -> Result<u16, Error> {
+        let [low, high] = word.to_le_bytes();
+        let mut buffer = [0; 2];
+        self.read(addr, &[command, low, high], &mut buffer)?;
+        Ok(u16::from_le_bytes(buffer))
+    }
+
+    /// Writes `data`, preceded by its length, to the register `command`
+    /// (Block Write)
+    ///
+    /// `data` must be at most 253 bytes long, or 252 bytes with PEC.
+    pub fn block_write(&mut self, addr: u8, command: u8, data: &[u8]) -> Result<(), Error> {
+        assert!(data.len() + 2 + self.pec as usize <= 255);
+
+        self.start(addr, data.len() as u8 + 2, false, End::Stop, self.pec);
+        self.write_bytes(&[command, data.len() as u8])?;
+        self.write_bytes(data)?;
+        self.wait_stop()
+    }
+
+    /// Reads a block from the register `command` into `buffer`, returning
+    /// its length (Block Read)
+    ///
+    /// If the device announces more bytes than `buffer` holds, or than fit in
+    /// a single transfer with the PEC byte, the transfer is ended after the
+    /// next byte and `Error::Overrun` is returned.
+    pub fn block_read(&mut self, addr: u8, command: u8, buffer: &mut [u8]) -> Result<usize, Error> {
+        self.start(addr, 1, false, End::Restart, false);
+        self.write_bytes(&[command])?;
+        self.wait_transfer_complete()?;
+        self.read_block(addr, buffer)
+    }
+
+    /// Writes `data` to the register `command` and reads the block returned
+    /// by the device into `buffer`, returning its length (Block Write-Block
+    /// Read Process Call)
+    pub fn block_process_call(
+        &mut self,
+        addr: u8,
+        command: u8,
+        data: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<usize, Error> {
+        assert!(data.len() + 2 + self.pec as usize <= 255);
+
+        self.start(addr, data.len() as u8 + 2, false, End::Restart, false);
+        self.write_bytes(&[command, data.len() as u8])?;
+        self.write_bytes(data)?;
+        self.wait_transfer_complete()?;
+        self.read_block(addr, buffer)
+    }
+
+    /// Returns `true` if a device asserted SMBALERT
+    pub fn is_alert(&self) -> bool {
+        self.i2c.isr.read().alert().bit_is_set()
+    }
+
+    /// Clears the SMBALERT flag
+    ///
+    /// The alerting device can be identified by reading the Alert Response
+    /// Address 0x0c with `receive_byte`.
+    pub fn clear_alert(&mut self) {
+        self.i2c.icr.write(|w| w.alertcf().set_bit());
+    }
+
+    /// Enables the error interrupt, which is also raised on SMBALERT
+    pub fn listen_alert(&mut self) {
+        self.i2c.cr1.modify(|_, w| w.errie().set_bit());
+    }
+
+    /// Disables the error interrupt
+    pub fn unlisten_alert(&mut self) {
+        self.i2c.cr1.modify(|_, w| w.errie().clear_bit());
+    }
+
+    /// Releases the I2C peripheral and associated pins
+    pub fn free(self) -> (I2C, PINS) {
+        self.i2c.cr1.write(|w| w.pe().disabled());
+        (self.i2c, self.pins)
+    }
+
+    /// Writes `bytes` in a single transfer ended by a STOP
+    fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.start(addr, bytes.len() as u8, false, End::Stop, self.pec);
+        self.write_bytes(bytes)?;
+        self.wait_stop()
+    }
+
+    /// Writes `bytes`, if any, then reads into `buffer` after a repeated
+    /// START
+    fn read(&mut self, addr: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        if !bytes.is_empty() {
+            self.start(addr, bytes.len() as u8, false, End::Restart, false);
+            self.write_bytes(bytes)?;
+            self.wait_transfer_complete()?;
+        }
+
+        self.start(addr, buffer.len() as u8, true, End::Stop, self.pec);
+        for byte in buffer {
+            *byte = self.read_byte()?;
+        }
+        self.wait_stop()
+    }
+
+    /// Reads a byte count and as many bytes after a repeated START
+    fn read_block(&mut self, addr: u8, buffer: &mut [u8]) -> Result<usize, Error> {
+        // Read the byte count first, then extend the transfer by that many
+        // bytes
+        self.start(addr, 1, true, End::Reload, false);
+        let count = usize::from(self.read_byte()?);
+        self.wait(|i2c| check_status_flag!(i2c, tcr, bit_is_set))?;
+
+        let n_bytes = count + self.pec as usize;
+        if count > buffer.len() || n_bytes > 255 {
+            // The transfer can only end after at least one more byte, which
+            // the host doesn't acknowledge
+            self.extend(1, false);
+            self.read_byte()?;
+            self.wait_stop()?;
+            return Err(Error::Overrun);
+        }
+
+        // NBYTES can't be 0 once the transfer is extended, an empty block
+        // without PEC is ended after a discarded byte
+        self.extend(n_bytes.max(1) as u8, self.pec);
+        for byte in &mut buffer[..count] {
+            *byte = self.read_byte()?;
+        }
+        self.wait_stop()?;
+
+        Ok(count)
+    }
+
+    /// Ends a reloaded transfer after `n_bytes` more bytes, including the PEC
+    /// byte if `pec` is set, with an automatic STOP
+    fn extend(&self, n_bytes: u8, pec: bool) {
+        self.i2c.cr2.modify(|_, w| {
+            w.nbytes()
+                .bits(n_bytes)
+                .reload()
+                .clear_bit()
+                .autoend()
+                .automatic()
+                .pecbyte()
+                .bit(pec)
+        });
+    }
+
+    /// Sets START and prepares to transfer `n_bytes` bytes, plus the PEC byte
+    /// if `pec` is set, ending the transfer as given by `end`
+    fn start(&self, addr: u8, n_bytes: u8, read: bool, end: End, pec: bool) {
+        // Wait for any previous address sequence to end
+        while self.i2c.cr2.read().start().bit_is_set() {}
+
+        self.i2c.cr2.write(|w| {
+            w.sadd()
+                .bits(u16::from(addr) << 1)
+                .add10()
+                .clear_bit()
+                .nbytes()
+                .bits(n_bytes + pec as u8)
+                .rd_wrn()
+                .bit(read)
+                .pecbyte()
+                .bit(pec)
+                .start()
+                .set_bit();
+            match end {
+                End::Stop => w.autoend().automatic().reload().clear_bit(),
+                End::Restart => w.autoend().software().reload().clear_bit(),
+                End::Reload => w.autoend().software().reload().set_bit(),
+            }
+        });
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        for byte in bytes {
+            self.wait(|i2c| check_status_flag!(i2c, txis, is_empty))?;
+            self.i2c.txdr.write(|w| w.txdata().bits(*byte));
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        self.wait(|i2c| check_status_flag!(i2c, rxne, is_not_empty))?;
+        Ok(self.i2c.rxdr.read().rxdata().bits())
+    }
+
+    fn wait_transfer_complete(&mut self) -> Result<(), Error> {
+        self.wait(|i2c| check_status_flag!(i2c, tc, is_complete))
+    }
+
+    /// Waits for the automatic STOP, discarding a received PEC byte
+    fn wait_stop(&mut self) -> Result<(), Error> {
+        self.wait(|i2c| check_status_flag!(i2c, stopf, bit_is_set))?;
+        self.i2c.icr.write(|w| w.stopcf().set_bit());
+        if self.i2c.isr.read().rxne().bit_is_set() {
+            self.i2c.rxdr.read();
+        }
+        Ok(())
+    }
+
+    /// Busy waits for `f` to complete
+    ///
+    /// The SMBus timeouts, when enabled, bound the waiting time. Otherwise,
+    /// this waits forever on a stuck bus.
+    fn wait(
+        &self,
+        f: impl Fn(&crate::pac::i2c1::RegisterBlock) -> nb::Result<(), Error>,
+    ) -> Result<(), Error> {
+        loop {
+            match f(&*self.i2c) {
+                Ok(()) => return Ok(()),
+                Err(Other(e)) => return Err(e),
+                Err(WouldBlock) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::timeout_bits;
+
+    #[test]
+    fn test_timeout_bits() {
+        // 25 ms at 16 MHz is 195.3 units of 2048 cycles
+        assert_eq!(timeout_bits(25_000, 16_000_000), 195);
+        // 10 ms at 54 MHz is 263.7 units
+        assert_eq!(timeout_bits(10_000, 54_000_000), 263);
+        assert_eq!(timeout_bits(128, 16_000_000), 0);
+    }
+}
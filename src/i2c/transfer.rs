@@ -0,0 +1,414 @@
+//! DMA transfers of the I2C peripheral in master mode
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+
+use as_slice::{AsMutSlice, AsSlice};
+use nb::Error::{Other, WouldBlock};
+
+use super::{Error, I2c, Instance};
+use crate::dma;
+use crate::pac;
+use crate::state;
+
+/// RX token used for DMA transfers
+pub struct Rx<I2C>(PhantomData<I2C>);
+
+/// TX token used for DMA transfers
+pub struct Tx<I2C>(PhantomData<I2C>);
+
+impl<I2C, SCL, SDA> I2c<I2C, SCL, SDA>
+where
+    I2C: Instance,
+{
+    /// Writes `data` to the slave with address `addr` using DMA
+    ///
+    /// Buffers longer than 255 bytes are sent in a single transfer, using the
+    /// NBYTES reload mechanism. DMA supports transfers up to 65535 bytes.
+    pub fn write_all<B, S>(
+        self,
+        addr: u8,
+        data: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Transfer<I2C, SCL, SDA, Tx<I2C>, S, B, dma::Ready>
+    where
+        Tx<I2C>: dma::Target<S>,
+        S: dma::Stream,
+        B: Deref + 'static,
+        B::Target: AsSlice<Element = u8>,
+    {
+        assert!(!data.as_slice().is_empty());
+
+        let len = data.as_slice().len();
+        let address = &self.i2c.txdr as *const _ as _;
+
+        // Safe, because the trait bounds on this method guarantee that `data`
+        // can be read from safely.
+        let transfer = unsafe {
+            dma::Transfer::new(
+                dma,
+                stream,
+                data,
+                Tx(PhantomData),
+                address,
+                dma::Direction::MemoryToPeripheral,
+            )
+        };
+
+        Transfer {
+            target: self,
+            transfer,
+            addr,
+            len,
+            read: false,
+        }
+    }
+
+    /// Reads enough bytes from the slave with address `addr` to fill
+    /// `buffer` using DMA
+    ///
+    /// Buffers longer than 255 bytes are received in a single transfer, using
+    /// the NBYTES reload mechanism. DMA supports transfers up to 65535 bytes.
+    pub fn read_all<B, S>(
+        self,
+        addr: u8,
+        buffer: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Transfer<I2C, SCL, SDA, Rx<I2C>, S, B, dma::Ready>
+    where
+        Rx<I2C>: dma::Target<S>,
+        S: dma::Stream,
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u8>,
+    {
+        assert!(!buffer.as_slice().is_empty());
+
+        let len = buffer.as_slice().len();
+        let address = &self.i2c.rxdr as *const _ as _;
+
+        // Safe, because the trait bounds on this method guarantee that
+        // `buffer` can be written to safely.
+        let transfer = unsafe {
+            dma::Transfer::new(
+                dma,
+                stream,
+                buffer,
+                Rx(PhantomData),
+                address,
+                dma::Direction::PeripheralToMemory,
+            )
+        };
+
+        Transfer {
+            target: self,
+            transfer,
+            addr,
+            len,
+            read: true,
+        }
+    }
+
+    /// Writes `bytes` to the slave with address `addr`, then reads enough
+    /// bytes to fill `buffer` using DMA after a repeated START
+    ///
+    /// `bytes`, typically a register address, is sent by polling before this
+    /// method returns, so it must be at most 255 bytes long. If the slave
+    /// doesn't acknowledge it, the resources are returned with the error.
+    pub fn write_read_all<B, S>(
+        self,
+        addr: u8,
+        bytes: &[u8],
+        buffer: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Result<
+        Transfer<I2C, SCL, SDA, Rx<I2C>, S, B, dma::Ready>,
+        (TransferResources<I2C, SCL, SDA, S, B>, TransferError),
+    >
+    where
+        Rx<I2C>: dma::Target<S>,
+        S: dma::Stream,
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u8>,
+    {
+        assert!(bytes.len() < 256 && !bytes.is_empty());
+
+        // Wait for any previous address sequence to end
+        while self.i2c.cr2.read().start().bit_is_set() {}
+
+        // Start and make sure we don't send STOP after the write
+        start(&self.i2c, addr, bytes.len(), false, false);
+
+        let mut result = Ok(());
+        for byte in bytes {
+            result = poll(|| check_status_flag!(self.i2c, txis, is_empty));
+            if result.is_err() {
+                break;
+            }
+            self.i2c.txdr.write(|w| w.txdata().bits(*byte));
+        }
+        if result.is_ok() {
+            result = poll(|| check_status_flag!(self.i2c, tc, is_complete));
+        }
+
+        if let Err(error) = result {
+            // The end of the transfer is software-controlled
+            self.i2c.cr2.modify(|_, w| w.stop().set_bit());
+
+            return Err((
+                TransferResources {
+                    stream,
+                    target: self,
+                    buffer,
+                },
+                TransferError::I2c(error),
+            ));
+        }
+
+        Ok(self.read_all(addr, buffer, dma, stream))
+    }
+}
+
+/// A DMA transfer of the I2C peripheral in master mode
+///
+/// Wraps the underlying [`dma::Transfer`] and keeps track of the NBYTES
+/// reloads needed for transfers longer than 255 bytes.
+pub struct Transfer<I2C, SCL, SDA, T, S: dma::Stream, B, State> {
+    target: I2c<I2C, SCL, SDA>,
+    transfer: dma::Transfer<T, S, B, State>,
+    addr: u8,
+    /// Number of bytes not programmed into NBYTES yet
+    len: usize,
+    read: bool,
+}
+
+impl<I2C, SCL, SDA, T, S, B> Transfer<I2C, SCL, SDA, T, S, B, dma::Ready>
+where
+    I2C: Instance,
+    T: dma::Target<S>,
+    S: dma::Stream,
+    B: 'static,
+{
+    /// Enables the given interrupts for the DMA stream of this transfer
+    pub fn enable_interrupts(
+        &mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+        interrupts: dma::Interrupts,
+    ) {
+        self.transfer.enable_interrupts(handle, interrupts);
+    }
+
+    /// Start the transfer
+    ///
+    /// Starts the DMA stream, then generates the START condition.
+    pub fn start(
+        mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> Transfer<I2C, SCL, SDA, T, S, B, dma::Started> {
+        let transfer = self.transfer.start(handle);
+
+        let i2c = &self.target.i2c;
+        match self.read {
+            true => i2c.cr1.modify(|_, w| w.rxdmaen().set_bit()),
+            false => i2c.cr1.modify(|_, w| w.txdmaen().set_bit()),
+        }
+
+        // Wait for any previous address sequence to end
+        while i2c.cr2.read().start().bit_is_set() {}
+
+        let n_bytes = self.len.min(255);
+        start(i2c, self.addr, self.len, self.read, true);
+        self.len -= n_bytes;
+
+        Transfer {
+            target: self.target,
+            transfer,
+            addr: self.addr,
+            len: self.len,
+            read: self.read,
+        }
+    }
+}
+
+impl<I2C, SCL, SDA, T, S, B> Transfer<I2C, SCL, SDA, T, S, B, dma::Started>
+where
+    I2C: Instance,
+    S: dma::Stream,
+{
+    /// Enables the I2C interrupts needed to drive this transfer from an
+    /// interrupt handler
+    ///
+    /// The handler must call [`Transfer::is_active`], which programs the
+    /// next chunk of a transfer longer than 255 bytes.
+    pub fn listen(&mut self) {
+        self.target.i2c.cr1.modify(|_, w| {
+            w.tcie()
+                .set_bit()
+                .stopie()
+                .set_bit()
+                .nackie()
+                .set_bit()
+                .errie()
+                .set_bit()
+        });
+    }
+
+    /// Checks whether the transfer is still ongoing
+    ///
+    /// Once the previous chunk of 255 bytes has been transferred, this
+    /// programs the next one.
+    pub fn is_active(&mut self) -> bool {
+        let isr = self.target.i2c.isr.read();
+        if isr.tcr().bit_is_set() {
+            self.reload();
+        }
+
+        isr.stopf().bit_is_clear()
+            && isr.nackf().bit_is_clear()
+            && isr.berr().bit_is_clear()
+            && isr.arlo().bit_is_clear()
+    }
+
+    /// Waits for the transfer to end
+    ///
+    /// This method will block if the transfer is still ongoing. Once the STOP
+    /// condition has been sent, or the transfer failed, the DMA stream, the
+    /// buffer and the I2C peripheral are returned.
+    pub fn wait(
+        mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> WaitResult<I2C, SCL, SDA, S, B> {
+        let result = loop {
+            if self.target.i2c.isr.read().tcr().bit_is_set() {
+                self.reload();
+            }
+
+            match check_status_flag!(self.target.i2c, stopf, bit_is_set) {
+                Ok(()) => {
+                    self.target.i2c.icr.write(|w| w.stopcf().set_bit());
+                    break Ok(());
+                }
+                Err(Other(error)) => break Err(error),
+                Err(WouldBlock) => {}
+            }
+        };
+
+        if result.is_err() {
+            self.transfer.cancel(handle);
+        }
+
+        self.target.i2c.cr1.modify(|_, w| {
+            w.rxdmaen()
+                .clear_bit()
+                .txdmaen()
+                .clear_bit()
+                .tcie()
+                .clear_bit()
+                .stopie()
+                .clear_bit()
+                .nackie()
+                .clear_bit()
+                .errie()
+                .clear_bit()
+        });
+
+        let target = self.target;
+        let (res, dma_error) = match self.transfer.wait(handle) {
+            Ok(res) => (res, None),
+            Err((res, error)) => (res, Some(error)),
+        };
+        let res = TransferResources {
+            stream: res.stream,
+            target,
+            buffer: res.buffer,
+        };
+
+        match (result, dma_error) {
+            (Err(error), _) => Err((res, TransferError::I2c(error))),
+            (Ok(()), Some(error)) => Err((res, TransferError::Dma(error))),
+            (Ok(()), None) => Ok(res),
+        }
+    }
+
+    /// Programs the next chunk of up to 255 bytes
+    fn reload(&mut self) {
+        let n_bytes = self.len.min(255);
+        self.target
+            .i2c
+            .cr2
+            .modify(|_, w| w.nbytes().bits(n_bytes as u8).reload().bit(self.len > 255));
+        self.len -= n_bytes;
+    }
+}
+
+/// Sets START for a transfer of `len` bytes to or from the slave with
+/// address `addr`
+///
+/// Transfers longer than 255 bytes use the NBYTES reload mechanism. A STOP is
+/// generated after the last byte if `auto_stop` is set.
+fn start(i2c: &pac::i2c1::RegisterBlock, addr: u8, len: usize, read: bool, auto_stop: bool) {
+    i2c.cr2.write(|w| {
+        w.sadd()
+            .bits(u16::from(addr) << 1)
+            .add10()
+            .clear_bit()
+            .nbytes()
+            .bits(len.min(255) as u8)
+            .reload()
+            .bit(len > 255)
+            .rd_wrn()
+            .bit(read)
+            .start()
+            .set_bit();
+        match auto_stop {
+            true => w.autoend().automatic(),
+            false => w.autoend().software(),
+        }
+    });
+}
+
+/// Busy waits for `f` to complete
+fn poll(f: impl Fn() -> nb::Result<(), Error>) -> Result<(), Error> {
+    loop {
+        match f() {
+            Ok(()) => return Ok(()),
+            Err(Other(error)) => return Err(error),
+            Err(WouldBlock) => {}
+        }
+    }
+}
+
+/// An error of a DMA transfer of the I2C peripheral
+#[derive(Debug)]
+pub enum TransferError {
+    /// The I2C transfer failed, e.g. because the slave didn't acknowledge
+    I2c(Error),
+    /// The DMA transfer failed
+    Dma(dma::Error),
+}
+
+/// Returned by [`Transfer::wait`]
+pub type WaitResult<I2C, SCL, SDA, S, B> = Result<
+    TransferResources<I2C, SCL, SDA, S, B>,
+    (TransferResources<I2C, SCL, SDA, S, B>, TransferError),
+>;
+
+/// The resources that an ongoing transfer needs exclusive access to
+pub struct TransferResources<I2C, SCL, SDA, S, B> {
+    pub stream: S,
+    pub target: I2c<I2C, SCL, SDA>,
+    pub buffer: Pin<B>,
+}
+
+// As `TransferResources` is used in the error variant of `Result`, it needs a
+// `Debug` implementation to enable stuff like `unwrap` and `expect`. This can't
+// be derived without putting requirements on the type arguments.
+impl<I2C, SCL, SDA, S, B> fmt::Debug for TransferResources<I2C, SCL, SDA, S, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TransferResources {{ .. }}")
+    }
+}
@@ -0,0 +1,668 @@
+//! Inter-IC Sound (I2S) interface of SPI1, SPI2 and SPI3
+//!
+//! The I2S interfaces are clocked by the R output of PLLI2S, which must be
+//! enabled with [`CFGR::use_plli2s`] for a master. The sample rate is derived
+//! from that clock, see [`I2s::sample_rate`] for the rate actually achieved.
+//!
+//! The I2S interfaces of this family are half-duplex. Full-duplex audio uses
+//! two interfaces: a master, and a slave in the other direction that shares
+//! the CK and WS lines of the master.
+//!
+//! Samples are transferred as 16-bit half-words. 24-bit and 32-bit samples
+//! take two half-words each, most significant half first.
+//!
+//! See chapter 32 in the STM32F746 Reference Manual.
+//!
+//! [`CFGR::use_plli2s`]: crate::rcc::CFGR::use_plli2s
+
+use core::{
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+};
+
+use as_slice::{AsMutSlice, AsSlice};
+
+use crate::{
+    dma,
+    gpio::{self, Alternate},
+    pac,
+    rcc::{Clocks, Enable, Reset},
+    spi::{self, Mosi, Nss, Polarity, Sck},
+    state,
+};
+use fugit::HertzU32 as Hertz;
+
+/// I2S configuration and direction of the interface
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    MasterTransmit,
+    MasterReceive,
+    SlaveTransmit,
+    SlaveReceive,
+}
+
+impl Mode {
+    fn is_master(self) -> bool {
+        matches!(self, Mode::MasterTransmit | Mode::MasterReceive)
+    }
+}
+
+/// Audio standard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Standard {
+    /// I2S Philips standard
+    Philips,
+    /// Left-justified
+    MsbJustified,
+    /// Right-justified
+    LsbJustified,
+    /// PCM with a frame synchronization pulse of one clock cycle
+    PcmShortSync,
+    /// PCM with a frame synchronization pulse of 13 clock cycles
+    PcmLongSync,
+}
+
+/// Data length and channel length
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    /// 16-bit data in a 16-bit channel
+    Data16Channel16,
+    /// 16-bit data in a 32-bit channel
+    Data16Channel32,
+    /// 24-bit data in a 32-bit channel
+    Data24Channel32,
+    /// 32-bit data in a 32-bit channel
+    Data32Channel32,
+}
+
+impl DataFormat {
+    fn channel_bits(self) -> u32 {
+        match self {
+            DataFormat::Data16Channel16 => 16,
+            _ => 32,
+        }
+    }
+}
+
+/// I2S configuration
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub mode: Mode,
+    pub standard: Standard,
+    pub data_format: DataFormat,
+    /// Idle level of CK
+    pub polarity: Polarity,
+    /// Sample rate of a master, ignored by a slave
+    pub sample_rate: Hertz,
+}
+
+impl Config {
+    /// Creates a Philips configuration with 16-bit samples at 48 kHz
+    pub fn new(mode: Mode) -> Self {
+        Config {
+            mode,
+            standard: Standard::Philips,
+            data_format: DataFormat::Data16Channel16,
+            polarity: Polarity::IdleLow,
+            sample_rate: Hertz::from_raw(48_000),
+        }
+    }
+}
+
+// `Polarity` doesn't implement `Debug`, so this can't be derived.
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let polarity = match self.polarity {
+            Polarity::IdleLow => "IdleLow",
+            Polarity::IdleHigh => "IdleHigh",
+        };
+        f.debug_struct("Config")
+            .field("mode", &self.mode)
+            .field("standard", &self.standard)
+            .field("data_format", &self.data_format)
+            .field("polarity", &format_args!("{}", polarity))
+            .field("sample_rate", &self.sample_rate)
+            .finish()
+    }
+}
+
+/// Audio channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Left,
+    Right,
+}
+
+/// I2S error
+#[derive(Debug)]
+pub enum Error {
+    /// A sample was received before the previous one was read
+    Overrun,
+    /// A slave transmitter had no sample to send
+    Underrun,
+    /// WS changed at an unexpected time, as a slave
+    FrameFormat,
+}
+
+/// Implemented for the SPI instances that support I2S
+///
+/// Users of this crate should not implement this trait.
+pub trait Instance: spi::Instance + Enable + Reset {
+    /// Writes I2SPR, then I2SCFGR with the interface disabled
+    fn configure_i2s(&self, i2scfgr: u32, i2spr: u32);
+    /// Enables or disables the interface
+    fn set_i2s_enabled(&self, enabled: bool);
+    /// Reads SR
+    fn status(&self) -> u32;
+    fn read_data(&self) -> u16;
+    fn write_data(&self, data: u16);
+}
+
+macro_rules! impl_instance {
+    ($($spi:ty,)*) => {
+        $(
+            impl Instance for $spi {
+                fn configure_i2s(&self, i2scfgr: u32, i2spr: u32) {
+                    self.i2scfgr.write(|w| unsafe { w.bits(0) });
+                    self.cr2.write(|w| w.txdmaen().enabled().rxdmaen().enabled());
+                    self.i2spr.write(|w| unsafe { w.bits(i2spr) });
+                    self.i2scfgr.write(|w| unsafe { w.bits(i2scfgr) });
+                }
+
+                fn set_i2s_enabled(&self, enabled: bool) {
+                    self.i2scfgr.modify(|_, w| w.i2se().bit(enabled));
+                }
+
+                fn status(&self) -> u32 {
+                    self.sr.read().bits()
+                }
+
+                fn read_data(&self) -> u16 {
+                    self.dr.read().dr().bits()
+                }
+
+                fn write_data(&self, data: u16) {
+                    self.dr.write(|w| w.dr().bits(data));
+                }
+            }
+        )*
+    };
+}
+
+impl_instance!(pac::SPI1, pac::SPI2, pac::SPI3,);
+
+/// Implemented for all pins that can function as the MCK pin
+///
+/// Users of this crate should not implement this trait.
+pub trait PinMck<SPI> {
+    /// Whether this is an actual pin, enabling the master clock output
+    const OUTPUT: bool = true;
+}
+
+/// Placeholder for a pin when no master clock output is required
+pub struct NoMck;
+impl<SPI> PinMck<SPI> for NoMck {
+    const OUTPUT: bool = false;
+}
+
+impl PinMck<pac::SPI1> for gpio::PC4<Alternate<5>> {}
+impl PinMck<pac::SPI2> for gpio::PC6<Alternate<5>> {}
+impl PinMck<pac::SPI3> for gpio::PC7<Alternate<6>> {}
+
+/// Implemented for all tuples of I2S pins
+///
+/// The tuple is `(WS, CK, MCK, SD)`, which are the NSS, SCK and MOSI pins of
+/// the SPI peripheral, and an MCK pin or [`NoMck`].
+pub trait Pins<SPI> {
+    /// Whether the master clock is output
+    const MCK: bool;
+}
+
+impl<SPI, WS, CK, MCK, SD> Pins<SPI> for (WS, CK, MCK, SD)
+where
+    WS: Nss<SPI>,
+    CK: Sck<SPI>,
+    MCK: PinMck<SPI>,
+    SD: Mosi<SPI>,
+{
+    const MCK: bool = MCK::OUTPUT;
+}
+
+/// I2S interface
+pub struct I2s<SPI, PINS> {
+    spi: SPI,
+    pins: PINS,
+    sample_rate: Option<Hertz>,
+}
+
+impl<SPI, PINS> I2s<SPI, PINS>
+where
+    SPI: Instance,
+    PINS: Pins<SPI>,
+{
+    /// Configures the SPI peripheral as an I2S interface
+    ///
+    /// A master outputs the master clock, at 256 times the sample rate, if
+    /// `pins` contains an MCK pin. The interface is enabled once the first
+    /// transfer starts.
+    ///
+    /// Panics if the sample rate of a master can't be generated from the I2S
+    /// clock.
+    pub fn new(spi: SPI, pins: PINS, config: Config, clocks: Clocks, apb: &mut SPI::Bus) -> Self {
+        let master = config.mode.is_master();
+        assert!(master || !PINS::MCK);
+
+        SPI::enable(apb);
+        SPI::reset(apb);
+
+        let channel_bits = config.data_format.channel_bits();
+        let (i2spr, sample_rate) = if master {
+            let i2s_clock = clocks
+                .i2sclk()
+                .expect("PLLI2S must be enabled to clock an I2S master")
+                .raw();
+            let divider = divider(i2s_clock, config.sample_rate.raw(), channel_bits, PINS::MCK)
+                .expect("I2S sample rate can't be generated");
+
+            (
+                i2spr(divider, PINS::MCK),
+                Some(Hertz::from_raw(sample_rate(
+                    i2s_clock,
+                    divider,
+                    channel_bits,
+                    PINS::MCK,
+                ))),
+            )
+        } else {
+            // The reset value
+            (2, None)
+        };
+
+        spi.configure_i2s(i2scfgr(&config), i2spr);
+
+        I2s {
+            spi,
+            pins,
+            sample_rate,
+        }
+    }
+}
+
+impl<SPI, PINS> I2s<SPI, PINS>
+where
+    SPI: Instance,
+{
+    /// Returns the sample rate achieved by a master
+    ///
+    /// This is the requested rate, rounded to the nearest one that can be
+    /// divided from the I2S clock.
+    pub fn sample_rate(&self) -> Option<Hertz> {
+        self.sample_rate
+    }
+
+    /// Enables the interface
+    ///
+    /// A master starts generating the clocks.
+    pub fn enable(&mut self) {
+        self.spi.set_i2s_enabled(true);
+    }
+
+    /// Disables the interface
+    pub fn disable(&mut self) {
+        self.spi.set_i2s_enabled(false);
+    }
+
+    /// Returns the channel of the last received half-word, or of the next one
+    /// to be sent
+    pub fn channel(&self) -> Channel {
+        match self.spi.status() & SR_CHSIDE {
+            0 => Channel::Left,
+            _ => Channel::Right,
+        }
+    }
+
+    /// Reads a received half-word
+    pub fn read(&mut self) -> nb::Result<u16, Error> {
+        // Reading SR clears FRE
+        let sr = self.spi.status();
+
+        if sr & SR_FRE != 0 {
+            return Err(nb::Error::Other(Error::FrameFormat));
+        }
+        if sr & SR_OVR != 0 {
+            // OVR is cleared by reading DR, then SR
+            self.spi.read_data();
+            self.spi.status();
+            return Err(nb::Error::Other(Error::Overrun));
+        }
+        if sr & SR_RXNE != 0 {
+            return Ok(self.spi.read_data());
+        }
+
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Sends a half-word
+    pub fn send(&mut self, data: u16) -> nb::Result<(), Error> {
+        // Reading SR clears FRE and UDR
+        let sr = self.spi.status();
+
+        if sr & SR_FRE != 0 {
+            return Err(nb::Error::Other(Error::FrameFormat));
+        }
+        if sr & SR_UDR != 0 {
+            return Err(nb::Error::Other(Error::Underrun));
+        }
+        if sr & SR_TXE != 0 {
+            self.spi.write_data(data);
+            return Ok(());
+        }
+
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Receives samples continuously using circular DMA
+    ///
+    /// See [`dma::CircTransfer`] for how to access the buffer halves. The
+    /// buffer must have an even length of at most 65535 half-words.
+    pub fn read_circular<B, S>(
+        self,
+        buffer: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Stream<SPI, PINS, spi::Rx<SPI>, S, B, dma::Ready>
+    where
+        spi::Rx<SPI>: dma::Target<S>,
+        S: dma::Stream,
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u16>,
+    {
+        let address = self.spi.dr_address();
+
+        // Safe, because the trait bounds on this method guarantee that
+        // `buffer` can be written to safely. The token is only created while
+        // `self` is moved into the stream.
+        let transfer = unsafe {
+            dma::CircTransfer::new(
+                dma,
+                stream,
+                buffer,
+                spi::Rx(PhantomData),
+                address,
+                dma::Direction::PeripheralToMemory,
+            )
+        };
+
+        Stream {
+            i2s: self,
+            transfer,
+        }
+    }
+
+    /// Sends samples continuously using circular DMA
+    ///
+    /// See [`dma::CircTransfer`] for how to refill the buffer halves. The
+    /// buffer must have an even length of at most 65535 half-words.
+    pub fn write_circular<B, S>(
+        self,
+        buffer: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Stream<SPI, PINS, spi::Tx<SPI>, S, B, dma::Ready>
+    where
+        spi::Tx<SPI>: dma::Target<S>,
+        S: dma::Stream,
+        B: Deref + 'static,
+        B::Target: AsSlice<Element = u16>,
+    {
+        let address = self.spi.dr_address();
+
+        // Safe, because the trait bounds on this method guarantee that
+        // `buffer` can be read from safely. The token is only created while
+        // `self` is moved into the stream.
+        let transfer = unsafe {
+            dma::CircTransfer::new(
+                dma,
+                stream,
+                buffer,
+                spi::Tx(PhantomData),
+                address,
+                dma::Direction::MemoryToPeripheral,
+            )
+        };
+
+        Stream {
+            i2s: self,
+            transfer,
+        }
+    }
+
+    /// Disables the interface and releases the SPI peripheral and pins
+    pub fn free(self) -> (SPI, PINS) {
+        self.spi.set_i2s_enabled(false);
+        (self.spi, self.pins)
+    }
+}
+
+/// A circular DMA stream of an I2S interface
+///
+/// Wraps the underlying [`dma::CircTransfer`], and enables the interface for
+/// the duration of the stream.
+pub struct Stream<SPI, PINS, T, S: dma::Stream, B, State> {
+    i2s: I2s<SPI, PINS>,
+    transfer: dma::CircTransfer<T, S, B, State>,
+}
+
+impl<SPI, PINS, T, S, B> Stream<SPI, PINS, T, S, B, dma::Ready>
+where
+    SPI: Instance,
+    T: dma::Target<S>,
+    S: dma::Stream,
+    B: 'static,
+{
+    /// Enables the given interrupts for the DMA stream
+    pub fn enable_interrupts(
+        &mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+        interrupts: dma::Interrupts,
+    ) {
+        self.transfer.enable_interrupts(handle, interrupts);
+    }
+
+    /// Starts the DMA stream, then enables the interface
+    pub fn start(
+        mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> Stream<SPI, PINS, T, S, B, dma::Started> {
+        let transfer = self.transfer.start(handle);
+        self.i2s.enable();
+
+        Stream {
+            i2s: self.i2s,
+            transfer,
+        }
+    }
+}
+
+impl<SPI, PINS, T, S, B> Stream<SPI, PINS, T, S, B, dma::Started>
+where
+    SPI: Instance,
+    S: dma::Stream,
+{
+    /// Returns the half of the buffer that the DMA stream is done with, if any
+    ///
+    /// See [`dma::CircTransfer::readable_half`].
+    pub fn readable_half(
+        &self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> Result<Option<dma::Half>, dma::Error> {
+        self.transfer.readable_half(handle)
+    }
+
+    /// Gives access to the received half of the buffer
+    ///
+    /// See [`dma::CircTransfer::peek`].
+    pub fn peek<R, F>(
+        &mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+        f: F,
+    ) -> Result<Option<R>, dma::Error>
+    where
+        B: Deref,
+        B::Target: AsSlice<Element = u16>,
+        F: FnOnce(&[u16], dma::Half) -> R,
+    {
+        self.transfer.peek(handle, f)
+    }
+
+    /// Gives access to the sent half of the buffer, to refill it
+    ///
+    /// See [`dma::CircTransfer::peek_mut`].
+    pub fn peek_mut<R, F>(
+        &mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+        f: F,
+    ) -> Result<Option<R>, dma::Error>
+    where
+        B: DerefMut,
+        B::Target: AsMutSlice<Element = u16>,
+        F: FnOnce(&mut [u16], dma::Half) -> R,
+    {
+        self.transfer.peek_mut(handle, f)
+    }
+
+    /// Disables the interface, stops the DMA stream and returns the resources
+    /// it used
+    pub fn stop(
+        mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> StreamResources<SPI, PINS, S, B> {
+        self.i2s.disable();
+        let res = self.transfer.stop(handle);
+
+        StreamResources {
+            stream: res.stream,
+            i2s: self.i2s,
+            buffer: res.buffer,
+        }
+    }
+}
+
+/// The resources that a [`Stream`] needs exclusive access to
+pub struct StreamResources<SPI, PINS, S, B> {
+    pub stream: S,
+    pub i2s: I2s<SPI, PINS>,
+    pub buffer: Pin<B>,
+}
+
+const SR_RXNE: u32 = 1 << 0;
+const SR_TXE: u32 = 1 << 1;
+const SR_CHSIDE: u32 = 1 << 2;
+const SR_UDR: u32 = 1 << 3;
+const SR_OVR: u32 = 1 << 6;
+const SR_FRE: u32 = 1 << 8;
+
+/// Returns the I2SCFGR value for `config`, with the interface disabled
+fn i2scfgr(config: &Config) -> u32 {
+    let i2scfg = match config.mode {
+        Mode::SlaveTransmit => 0b00,
+        Mode::SlaveReceive => 0b01,
+        Mode::MasterTransmit => 0b10,
+        Mode::MasterReceive => 0b11,
+    };
+    let (i2sstd, pcmsync) = match config.standard {
+        Standard::Philips => (0b00, 0),
+        Standard::MsbJustified => (0b01, 0),
+        Standard::LsbJustified => (0b10, 0),
+        Standard::PcmShortSync => (0b11, 0),
+        Standard::PcmLongSync => (0b11, 1),
+    };
+    let (datlen, chlen) = match config.data_format {
+        DataFormat::Data16Channel16 => (0b00, 0),
+        DataFormat::Data16Channel32 => (0b00, 1),
+        DataFormat::Data24Channel32 => (0b01, 1),
+        DataFormat::Data32Channel32 => (0b10, 1),
+    };
+    let ckpol = (config.polarity == Polarity::IdleHigh) as u32;
+
+    // I2SMOD selects the I2S mode
+    1 << 11 | i2scfg << 8 | pcmsync << 7 | i2sstd << 4 | ckpol << 3 | datlen << 1 | chlen
+}
+
+/// Returns the number of I2S clock cycles per sample, for a divider of 1
+///
+/// With the master clock output, a sample always lasts 256 master clock
+/// cycles. Otherwise, it is made of the bit clock cycles of two channels.
+fn cycles_per_frame(channel_bits: u32, master_clock: bool) -> u32 {
+    match master_clock {
+        true => 256,
+        false => 2 * channel_bits,
+    }
+}
+
+/// Returns the total I2S clock divider, `2 * I2SDIV + ODD`, nearest to
+/// generating `sample_rate`, if it is in range
+fn divider(i2s_clock: u32, sample_rate: u32, channel_bits: u32, master_clock: bool) -> Option<u32> {
+    let step = u64::from(cycles_per_frame(channel_bits, master_clock)) * u64::from(sample_rate);
+    let divider = (u64::from(i2s_clock) + step / 2).checked_div(step)?;
+
+    if (4..=511).contains(&divider) {
+        Some(divider as u32)
+    } else {
+        None
+    }
+}
+
+/// Returns the I2SPR value for the total divider `divider`
+fn i2spr(divider: u32, master_clock: bool) -> u32 {
+    (master_clock as u32) << 9 | (divider & 1) << 8 | divider >> 1
+}
+
+/// Returns the sample rate generated with the total divider `divider`,
+/// rounded to the nearest Hz
+fn sample_rate(i2s_clock: u32, divider: u32, channel_bits: u32, master_clock: bool) -> u32 {
+    let step = cycles_per_frame(channel_bits, master_clock) * divider;
+    (i2s_clock + step / 2) / step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_i2scfgr() {
+        assert_eq!(i2scfgr(&Config::new(Mode::MasterTransmit)), 0x0a00);
+
+        let config = Config {
+            mode: Mode::SlaveReceive,
+            standard: Standard::PcmLongSync,
+            data_format: DataFormat::Data24Channel32,
+            polarity: Polarity::IdleHigh,
+            sample_rate: Hertz::from_raw(48_000),
+        };
+        assert_eq!(i2scfgr(&config), 0x09bb);
+    }
+
+    #[test]
+    fn test_divider() {
+        // PLLI2S at 86 MHz, from a 1 MHz input with N = 258 and R = 3
+        assert_eq!(divider(86_000_000, 48_000, 16, true), Some(7));
+        assert_eq!(i2spr(7, true), 0x0303);
+        assert_eq!(sample_rate(86_000_000, 7, 16, true), 47_991);
+
+        assert_eq!(divider(86_000_000, 48_000, 16, false), Some(56));
+        assert_eq!(i2spr(56, false), 0x001c);
+        assert_eq!(divider(86_000_000, 48_000, 32, false), Some(28));
+
+        // PLLI2S at 135.5 MHz, with N = 271 and R = 2
+        assert_eq!(divider(135_500_000, 44_100, 16, true), Some(12));
+        assert_eq!(sample_rate(135_500_000, 12, 16, true), 44_108);
+
+        // Out of range
+        assert_eq!(divider(86_000_000, 192_000, 16, true), None);
+        assert_eq!(divider(86_000_000, 1_000, 16, false), None);
+        assert_eq!(divider(86_000_000, 0, 16, false), None);
+    }
+}
@@ -1,4 +1,9 @@
 //! HAL for the STM32F7xx family of microcontrollers
+//!
+//! # Minimum supported Rust version
+//!
+//! The crate builds with Rust 1.75 or later, as required by the `async`
+//! feature for `async fn` in traits.
 
 #![cfg_attr(not(test), no_std)]
 #![allow(non_camel_case_types)]
@@ -135,9 +140,21 @@ pub mod serial;
 #[cfg(feature = "device-selected")]
 pub mod spi;
 
+#[cfg(feature = "device-selected")]
+pub mod i2s;
+
+#[cfg(feature = "device-selected")]
+pub mod sai;
+
 #[cfg(feature = "device-selected")]
 pub mod timer;
 
+#[cfg(feature = "device-selected")]
+pub mod pwm;
+
+#[cfg(feature = "device-selected")]
+pub mod capture;
+
 #[cfg(feature = "device-selected")]
 pub mod signature;
 
@@ -0,0 +1,814 @@
+//! Pulse width modulation
+//!
+//! Channels 1 to 4 of the general-purpose timers (TIM2-TIM5, TIM9-TIM14) and
+//! of the advanced-control timers (TIM1, TIM8) can be used to generate PWM
+//! signals. The basic timers TIM6 and TIM7 don't have any capture/compare
+//! channels and can't be used here.
+//!
+//! ```rust
+//! let gpioa = dp.GPIOA.split();
+//! let pins = (gpioa.pa8.into_alternate(), gpioa.pa9.into_alternate());
+//!
+//! let pwm = Pwm::tim1(dp.TIM1, pins, 20.kHz(), clocks, &mut rcc.apb2);
+//! let (mut ch1, mut ch2) = pwm.split();
+//!
+//! let max_duty = ch1.get_max_duty();
+//! ch1.set_duty(max_duty / 2);
+//! ch1.enable();
+//! ```
+//!
+//! The advanced-control timers additionally support complementary outputs
+//! with dead-time insertion and a break input. See chapter 23 in the STM32F746
+//! Reference Manual.
+
+use core::marker::PhantomData;
+
+use crate::gpio::{self, Alternate};
+use crate::hal;
+use crate::pac::{
+    TIM1, TIM10, TIM11, TIM12, TIM13, TIM14, TIM2, TIM3, TIM4, TIM5, TIM8, TIM9,
+};
+use crate::rcc::{Clocks, Enable, RccBus, Reset};
+use fugit::{HertzU32 as Hertz, NanosDurationU32};
+
+/// Channel 1 (type state)
+pub struct C1;
+/// Channel 2 (type state)
+pub struct C2;
+/// Channel 3 (type state)
+pub struct C3;
+/// Channel 4 (type state)
+pub struct C4;
+
+/// Identifies a channel when using the [`embedded_hal::Pwm`](hal::Pwm)
+/// interface
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    C1,
+    C2,
+    C3,
+    C4,
+}
+
+/// PWM mode (OCxM)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The output is active as long as the counter is lower than the duty
+    /// cycle (when counting up)
+    Pwm1 = 0b110,
+    /// The output is inactive as long as the counter is lower than the duty
+    /// cycle (when counting up)
+    Pwm2 = 0b111,
+}
+
+/// Output polarity (CCxP/CCxNP)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// Marker trait for pins that can be used as channel 1 of a timer
+pub trait PinC1<TIM> {}
+
+/// Marker trait for pins that can be used as channel 2 of a timer
+pub trait PinC2<TIM> {}
+
+/// Marker trait for pins that can be used as channel 3 of a timer
+pub trait PinC3<TIM> {}
+
+/// Marker trait for pins that can be used as channel 4 of a timer
+pub trait PinC4<TIM> {}
+
+/// Marker trait for pins that can be used as complementary channel 1 output
+pub trait PinC1N<TIM> {}
+
+/// Marker trait for pins that can be used as complementary channel 2 output
+pub trait PinC2N<TIM> {}
+
+/// Marker trait for pins that can be used as complementary channel 3 output
+pub trait PinC3N<TIM> {}
+
+/// Marker trait for pins that can be used as break input
+pub trait PinBkin<TIM> {}
+
+/// Implemented for all pins and tuples of pins that form a valid set of PWM
+/// outputs of a timer
+///
+/// `P` identifies the channels that are used, e.g. `(C1, C3)`.
+pub trait Pins<TIM, P> {
+    const C1: bool = false;
+    const C2: bool = false;
+    const C3: bool = false;
+    const C4: bool = false;
+
+    /// One [`PwmChannel`] per used channel
+    type Channels;
+
+    /// Returns the channel handles for the pins
+    fn into_channels(self) -> Self::Channels;
+}
+
+macro_rules! pins_impl {
+    ( $( ( $($PINX:ident),+ ), ( $($TRAIT:ident),+ ), ( $($ENCHX:ident),+ ); )+ ) => {
+        $(
+            #[allow(unused_parens)]
+            impl<TIM, $($PINX,)+> Pins<TIM, ($($ENCHX),+)> for ($($PINX),+)
+            where
+                $($PINX: $TRAIT<TIM>,)+
+            {
+                $(const $ENCHX: bool = true;)+
+                type Channels = ($(PwmChannel<TIM, $ENCHX>),+);
+
+                fn into_channels(self) -> Self::Channels {
+                    ($(PwmChannel::<TIM, $ENCHX>::new()),+)
+                }
+            }
+        )+
+    };
+}
+
+pins_impl!(
+    (P1, P2, P3, P4), (PinC1, PinC2, PinC3, PinC4), (C1, C2, C3, C4);
+    (P2, P3, P4), (PinC2, PinC3, PinC4), (C2, C3, C4);
+    (P1, P3, P4), (PinC1, PinC3, PinC4), (C1, C3, C4);
+    (P1, P2, P4), (PinC1, PinC2, PinC4), (C1, C2, C4);
+    (P1, P2, P3), (PinC1, PinC2, PinC3), (C1, C2, C3);
+    (P3, P4), (PinC3, PinC4), (C3, C4);
+    (P2, P4), (PinC2, PinC4), (C2, C4);
+    (P2, P3), (PinC2, PinC3), (C2, C3);
+    (P1, P4), (PinC1, PinC4), (C1, C4);
+    (P1, P3), (PinC1, PinC3), (C1, C3);
+    (P1, P2), (PinC1, PinC2), (C1, C2);
+    (P1), (PinC1), (C1);
+    (P2), (PinC2), (C2);
+    (P3), (PinC3), (C3);
+    (P4), (PinC4), (C4);
+);
+
+/// A timer configured to generate PWM signals
+pub struct Pwm<TIM, P, PINS> {
+    tim: TIM,
+    pins: PINS,
+    clock: Hertz,
+    _channels: PhantomData<P>,
+}
+
+/// A single PWM channel of a timer
+///
+/// Obtained from [`Pwm::split`].
+pub struct PwmChannel<TIM, CHANNEL> {
+    _tim: PhantomData<TIM>,
+    _channel: PhantomData<CHANNEL>,
+}
+
+impl<TIM, CHANNEL> PwmChannel<TIM, CHANNEL> {
+    fn new() -> Self {
+        Self {
+            _tim: PhantomData,
+            _channel: PhantomData,
+        }
+    }
+}
+
+/// A PWM channel of an advanced-control timer driving both its regular and
+/// its complementary output
+pub struct ComplementaryPwmChannel<TIM, CHANNEL, NPIN> {
+    channel: PwmChannel<TIM, CHANNEL>,
+    npin: NPIN,
+}
+
+/// Computes prescaler and auto-reload values to get an update rate of `freq`
+///
+/// Rates below the slowest one the timer can reach, including zero, are
+/// clamped to that rate.
+fn compute_psc_arr(clock: Hertz, freq: Hertz, max_arr: u32) -> (u16, u32) {
+    let max_ticks = (u64::from(u16::MAX) + 1) * (u64::from(max_arr) + 1);
+    let ticks = match freq.raw() {
+        0 => max_ticks,
+        freq => u64::from(clock.raw() / freq).clamp(1, max_ticks),
+    };
+    let psc = (ticks - 1) / (u64::from(max_arr) + 1);
+    let arr = ticks / (psc + 1) - 1;
+
+    (psc as u16, arr as u32)
+}
+
+/// Encodes a dead time of `ticks` timer clock cycles into the DTG field
+///
+/// Values that can't be represented exactly are rounded up. Returns `None` if
+/// the dead time is longer than the 1008 cycles that can be represented.
+fn dead_time_bits(ticks: u32) -> Option<u8> {
+    match ticks {
+        0..=127 => Some(ticks as u8),
+        128..=254 => Some(0b1000_0000 | ((ticks + 1) / 2 - 64) as u8),
+        255..=504 => Some(0b1100_0000 | ((ticks + 7) / 8 - 32) as u8),
+        505..=1008 => Some(0b1110_0000 | ((ticks + 15) / 16 - 32) as u8),
+        _ => None,
+    }
+}
+
+macro_rules! pwm {
+    ($(
+        $TIM:ident: ($tim:ident, $timclk:ident, $bits:ty $(, $bdtr:ident)?, [
+            $($C:ident: ($ch:expr, $ccmrx:ident, $ccr:ident),)+
+        ]),
+    )+) => {
+        $(
+            impl<P, PINS> Pwm<$TIM, P, PINS>
+            where
+                PINS: Pins<$TIM, P>,
+            {
+                /// Configures a TIM peripheral to generate PWM signals with
+                /// frequency `freq` on `pins`
+                ///
+                /// All channels start disabled, in PWM mode 1, and with a duty
+                /// cycle of zero.
+                pub fn $tim<T>(
+                    tim: $TIM,
+                    pins: PINS,
+                    freq: T,
+                    clocks: Clocks,
+                    apb: &mut <$TIM as RccBus>::Bus,
+                ) -> Self
+                where
+                    T: Into<Hertz>,
+                {
+                    // enable and reset peripheral to a clean slate state
+                    <$TIM>::enable(apb);
+                    <$TIM>::reset(apb);
+
+                    $(
+                        if PINS::$C {
+                            PwmChannel::<$TIM, $C>::new().set_mode(Mode::Pwm1);
+                        }
+                    )+
+
+                    // Buffer the auto-reload register, so a period change
+                    // takes effect on the next update event (ARPE)
+                    tim.cr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << 7)) });
+
+                    // Advanced-control timers need their main output enabled
+                    // (MOE) before any channel drives its pin
+                    $(
+                        tim.$bdtr.modify(|r, w| unsafe { w.bits(r.bits() | (1 << 15)) });
+                    )?
+
+                    let mut pwm = Pwm {
+                        tim,
+                        pins,
+                        clock: clocks.$timclk(),
+                        _channels: PhantomData,
+                    };
+                    pwm.set_frequency(freq.into());
+
+                    pwm.tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    pwm
+                }
+
+                /// Sets the PWM frequency
+                ///
+                /// The duty cycle of the channels is not adjusted, so it
+                /// should be set again after calling this method. Frequencies
+                /// too low for the timer are clamped to the lowest one it can
+                /// generate, see [`frequency`](Self::frequency).
+                #[allow(unused_unsafe)]
+                pub fn set_frequency(&mut self, freq: Hertz) {
+                    let (psc, arr) = compute_psc_arr(self.clock, freq, <$bits>::MAX as u32);
+
+                    self.tim.psc.write(|w| unsafe { w.bits(u32::from(psc)) });
+                    self.tim.arr.write(|w| unsafe { w.bits(arr) });
+
+                    // Trigger an update event to load the prescaler value
+                    self.tim.egr.write(|w| w.ug().set_bit());
+                }
+
+                /// Returns the PWM frequency
+                pub fn frequency(&self) -> Hertz {
+                    let psc = self.tim.psc.read().bits() + 1;
+                    let arr = u64::from(self.tim.arr.read().bits()) + 1;
+
+                    Hertz::from_raw((u64::from(self.clock.raw() / psc) / arr) as u32)
+                }
+
+                /// Splits the PWM timer into its channels
+                pub fn split(self) -> PINS::Channels {
+                    self.pins.into_channels()
+                }
+
+                /// Stops the timer and releases the TIM peripheral and pins
+                pub fn free(self) -> ($TIM, PINS) {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+
+                    (self.tim, self.pins)
+                }
+            }
+
+            impl<P, PINS> hal::Pwm for Pwm<$TIM, P, PINS>
+            where
+                PINS: Pins<$TIM, P>,
+            {
+                type Channel = Channel;
+                type Time = Hertz;
+                type Duty = $bits;
+
+                fn disable(&mut self, channel: Channel) {
+                    match channel {
+                        $(Channel::$C => hal::PwmPin::disable(&mut PwmChannel::<$TIM, $C>::new()),)+
+                        #[allow(unreachable_patterns)]
+                        _ => {}
+                    }
+                }
+
+                fn enable(&mut self, channel: Channel) {
+                    match channel {
+                        $(Channel::$C => hal::PwmPin::enable(&mut PwmChannel::<$TIM, $C>::new()),)+
+                        #[allow(unreachable_patterns)]
+                        _ => {}
+                    }
+                }
+
+                fn get_period(&self) -> Hertz {
+                    self.frequency()
+                }
+
+                fn get_duty(&self, channel: Channel) -> $bits {
+                    match channel {
+                        $(Channel::$C => hal::PwmPin::get_duty(&PwmChannel::<$TIM, $C>::new()),)+
+                        #[allow(unreachable_patterns)]
+                        _ => 0,
+                    }
+                }
+
+                fn get_max_duty(&self) -> $bits {
+                    self.tim.arr.read().bits() as $bits
+                }
+
+                fn set_duty(&mut self, channel: Channel, duty: $bits) {
+                    match channel {
+                        $(Channel::$C => hal::PwmPin::set_duty(&mut PwmChannel::<$TIM, $C>::new(), duty),)+
+                        #[allow(unreachable_patterns)]
+                        _ => {}
+                    }
+                }
+
+                fn set_period<T>(&mut self, period: T)
+                where
+                    T: Into<Hertz>,
+                {
+                    self.set_frequency(period.into());
+                }
+            }
+
+            $(
+                impl PwmChannel<$TIM, $C> {
+                    /// Selects PWM mode 1 or 2 for this channel
+                    pub fn set_mode(&mut self, mode: Mode) {
+                        // NOTE(unsafe) this channel owns its half of CCMRx
+                        let tim = unsafe { &*$TIM::ptr() };
+                        let offset = 8 * ($ch % 2);
+
+                        // Output compare mode (OCxM[3:0]) with preload enabled
+                        // (OCxPE) and the channel configured as output (CCxS)
+                        tim.$ccmrx().modify(|r, w| unsafe {
+                            w.bits(
+                                (r.bits() & !((0xff | (1 << 16)) << offset))
+                                    | ((mode as u32) << (4 + offset))
+                                    | (1 << (3 + offset)),
+                            )
+                        });
+                    }
+
+                    /// Sets the polarity of the output
+                    pub fn set_polarity(&mut self, polarity: Polarity) {
+                        // NOTE(unsafe) this channel owns its bits of CCER
+                        let tim = unsafe { &*$TIM::ptr() };
+                        let ccxp = 1 << (4 * $ch + 1);
+
+                        tim.ccer.modify(|r, w| unsafe {
+                            match polarity {
+                                Polarity::ActiveHigh => w.bits(r.bits() & !ccxp),
+                                Polarity::ActiveLow => w.bits(r.bits() | ccxp),
+                            }
+                        });
+                    }
+                }
+
+                impl hal::PwmPin for PwmChannel<$TIM, $C> {
+                    type Duty = $bits;
+
+                    fn disable(&mut self) {
+                        // NOTE(unsafe) atomic read-modify-write of this channel's bit
+                        let tim = unsafe { &*$TIM::ptr() };
+                        tim.ccer.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << (4 * $ch))) });
+                    }
+
+                    fn enable(&mut self) {
+                        // NOTE(unsafe) atomic read-modify-write of this channel's bit
+                        let tim = unsafe { &*$TIM::ptr() };
+                        tim.ccer.modify(|r, w| unsafe { w.bits(r.bits() | (1 << (4 * $ch))) });
+                    }
+
+                    fn get_duty(&self) -> $bits {
+                        // NOTE(unsafe) atomic read with no side effects
+                        unsafe { (*$TIM::ptr()).$ccr.read().bits() as $bits }
+                    }
+
+                    fn get_max_duty(&self) -> $bits {
+                        // NOTE(unsafe) atomic read with no side effects
+                        unsafe { (*$TIM::ptr()).arr.read().bits() as $bits }
+                    }
+
+                    #[allow(unused_unsafe)]
+                    fn set_duty(&mut self, duty: $bits) {
+                        // NOTE(unsafe) this channel owns its CCRx register
+                        unsafe { (*$TIM::ptr()).$ccr.write(|w| w.bits(duty as u32)) }
+                    }
+                }
+            )+
+        )+
+    }
+}
+
+pwm! {
+    TIM2: (tim2, timclk1, u32, [
+        C1: (0, ccmr1_output, ccr1),
+        C2: (1, ccmr1_output, ccr2),
+        C3: (2, ccmr2_output, ccr3),
+        C4: (3, ccmr2_output, ccr4),
+    ]),
+    TIM3: (tim3, timclk1, u16, [
+        C1: (0, ccmr1_output, ccr1),
+        C2: (1, ccmr1_output, ccr2),
+        C3: (2, ccmr2_output, ccr3),
+        C4: (3, ccmr2_output, ccr4),
+    ]),
+    TIM4: (tim4, timclk1, u16, [
+        C1: (0, ccmr1_output, ccr1),
+        C2: (1, ccmr1_output, ccr2),
+        C3: (2, ccmr2_output, ccr3),
+        C4: (3, ccmr2_output, ccr4),
+    ]),
+    TIM5: (tim5, timclk1, u32, [
+        C1: (0, ccmr1_output, ccr1),
+        C2: (1, ccmr1_output, ccr2),
+        C3: (2, ccmr2_output, ccr3),
+        C4: (3, ccmr2_output, ccr4),
+    ]),
+    TIM12: (tim12, timclk1, u16, [
+        C1: (0, ccmr1_output, ccr1),
+        C2: (1, ccmr1_output, ccr2),
+    ]),
+    TIM13: (tim13, timclk1, u16, [
+        C1: (0, ccmr1_output, ccr1),
+    ]),
+    TIM14: (tim14, timclk1, u16, [
+        C1: (0, ccmr1_output, ccr1),
+    ]),
+
+    TIM1: (tim1, timclk2, u16, bdtr, [
+        C1: (0, ccmr1_output, ccr1),
+        C2: (1, ccmr1_output, ccr2),
+        C3: (2, ccmr2_output, ccr3),
+        C4: (3, ccmr2_output, ccr4),
+    ]),
+    TIM8: (tim8, timclk2, u16, bdtr, [
+        C1: (0, ccmr1_output, ccr1),
+        C2: (1, ccmr1_output, ccr2),
+        C3: (2, ccmr2_output, ccr3),
+        C4: (3, ccmr2_output, ccr4),
+    ]),
+    TIM9: (tim9, timclk2, u16, [
+        C1: (0, ccmr1_output, ccr1),
+        C2: (1, ccmr1_output, ccr2),
+    ]),
+    TIM10: (tim10, timclk2, u16, [
+        C1: (0, ccmr1_output, ccr1),
+    ]),
+    TIM11: (tim11, timclk2, u16, [
+        C1: (0, ccmr1_output, ccr1),
+    ]),
+}
+
+// Complementary outputs, dead-time and break input of the advanced-control
+// timers
+macro_rules! pwm_advanced {
+    ($(
+        $TIM:ident: [$($C:ident: ($ch:expr, $PINN:ident),)+],
+    )+) => {
+        $(
+            impl<P, PINS> Pwm<$TIM, P, PINS>
+            where
+                PINS: Pins<$TIM, P>,
+            {
+                /// Sets the dead time inserted between a complementary output
+                /// going inactive and the regular output going active, and
+                /// vice versa
+                ///
+                /// # Panics
+                ///
+                /// Panics if the dead time is longer than 1008 timer clock
+                /// cycles.
+                pub fn set_dead_time(&mut self, dead_time: NanosDurationU32) {
+                    let ticks = u64::from(self.clock.raw()) * u64::from(dead_time.ticks())
+                        / 1_000_000_000;
+                    let dtg = dead_time_bits(ticks as u32).expect("dead time too long");
+
+                    self.tim
+                        .bdtr
+                        .modify(|r, w| unsafe { w.bits((r.bits() & !0xff) | u32::from(dtg)) });
+                }
+
+                /// Enables the break input
+                ///
+                /// When the break input becomes active, all outputs are
+                /// disabled by hardware. They have to be enabled again using
+                /// [`Pwm::enable_outputs`].
+                pub fn enable_break_input<BK>(&mut self, _pin: &BK, polarity: Polarity)
+                where
+                    BK: PinBkin<$TIM>,
+                {
+                    // Break enable (BKE) and break polarity (BKP)
+                    let bkp = match polarity {
+                        Polarity::ActiveHigh => 1 << 13,
+                        Polarity::ActiveLow => 0,
+                    };
+                    self.tim.bdtr.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(1 << 13)) | (1 << 12) | bkp)
+                    });
+                }
+
+                /// Disables the break input
+                pub fn disable_break_input(&mut self) {
+                    self.tim
+                        .bdtr
+                        .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << 12)) });
+                }
+
+                /// Returns `true`, if the break input has been triggered since
+                /// the last call to this method
+                pub fn is_break_triggered(&mut self) -> bool {
+                    let triggered = self.tim.sr.read().bif().bit_is_set();
+                    if triggered {
+                        self.tim.sr.write(|w| unsafe { w.bits(!(1 << 7)) });
+                    }
+                    triggered
+                }
+
+                /// Enables all outputs (MOE)
+                pub fn enable_outputs(&mut self) {
+                    self.tim
+                        .bdtr
+                        .modify(|r, w| unsafe { w.bits(r.bits() | (1 << 15)) });
+                }
+
+                /// Disables all outputs (MOE)
+                pub fn disable_outputs(&mut self) {
+                    self.tim
+                        .bdtr
+                        .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << 15)) });
+                }
+            }
+
+            $(
+                impl PwmChannel<$TIM, $C> {
+                    /// Drives the complementary output of this channel as well
+                    pub fn with_complementary<NPIN>(self, npin: NPIN)
+                        -> ComplementaryPwmChannel<$TIM, $C, NPIN>
+                    where
+                        NPIN: $PINN<$TIM>,
+                    {
+                        ComplementaryPwmChannel { channel: self, npin }
+                    }
+                }
+
+                impl<NPIN> ComplementaryPwmChannel<$TIM, $C, NPIN> {
+                    /// Selects PWM mode 1 or 2 for this channel
+                    pub fn set_mode(&mut self, mode: Mode) {
+                        self.channel.set_mode(mode);
+                    }
+
+                    /// Sets the polarity of the regular output
+                    pub fn set_polarity(&mut self, polarity: Polarity) {
+                        self.channel.set_polarity(polarity);
+                    }
+
+                    /// Sets the polarity of the complementary output
+                    pub fn set_complementary_polarity(&mut self, polarity: Polarity) {
+                        // NOTE(unsafe) this channel owns its bits of CCER
+                        let tim = unsafe { &*$TIM::ptr() };
+                        let ccxnp = 1 << (4 * $ch + 3);
+
+                        tim.ccer.modify(|r, w| unsafe {
+                            match polarity {
+                                Polarity::ActiveHigh => w.bits(r.bits() & !ccxnp),
+                                Polarity::ActiveLow => w.bits(r.bits() | ccxnp),
+                            }
+                        });
+                    }
+
+                    /// Disables the complementary output and returns the
+                    /// channel and the complementary pin
+                    pub fn release(mut self) -> (PwmChannel<$TIM, $C>, NPIN) {
+                        hal::PwmPin::disable(&mut self);
+
+                        (self.channel, self.npin)
+                    }
+                }
+
+                impl<NPIN> hal::PwmPin for ComplementaryPwmChannel<$TIM, $C, NPIN> {
+                    type Duty = u16;
+
+                    fn disable(&mut self) {
+                        // NOTE(unsafe) atomic read-modify-write of this channel's bits
+                        let tim = unsafe { &*$TIM::ptr() };
+                        tim.ccer.modify(|r, w| unsafe { w.bits(r.bits() & !(0b101 << (4 * $ch))) });
+                    }
+
+                    fn enable(&mut self) {
+                        // NOTE(unsafe) atomic read-modify-write of this channel's bits
+                        let tim = unsafe { &*$TIM::ptr() };
+                        tim.ccer.modify(|r, w| unsafe { w.bits(r.bits() | (0b101 << (4 * $ch))) });
+                    }
+
+                    fn get_duty(&self) -> u16 {
+                        self.channel.get_duty()
+                    }
+
+                    fn get_max_duty(&self) -> u16 {
+                        self.channel.get_max_duty()
+                    }
+
+                    fn set_duty(&mut self, duty: u16) {
+                        self.channel.set_duty(duty)
+                    }
+                }
+            )+
+        )+
+    }
+}
+
+pwm_advanced! {
+    TIM1: [
+        C1: (0, PinC1N),
+        C2: (1, PinC2N),
+        C3: (2, PinC3N),
+    ],
+    TIM8: [
+        C1: (0, PinC1N),
+        C2: (1, PinC2N),
+        C3: (2, PinC3N),
+    ],
+}
+
+// See the alternate function mapping tables in the STM32F7 datasheets
+
+impl PinC1<TIM1> for gpio::PA8<Alternate<1>> {}
+impl PinC1<TIM1> for gpio::PE9<Alternate<1>> {}
+impl PinC2<TIM1> for gpio::PA9<Alternate<1>> {}
+impl PinC2<TIM1> for gpio::PE11<Alternate<1>> {}
+impl PinC3<TIM1> for gpio::PA10<Alternate<1>> {}
+impl PinC3<TIM1> for gpio::PE13<Alternate<1>> {}
+impl PinC4<TIM1> for gpio::PA11<Alternate<1>> {}
+impl PinC4<TIM1> for gpio::PE14<Alternate<1>> {}
+impl PinC1N<TIM1> for gpio::PA7<Alternate<1>> {}
+impl PinC1N<TIM1> for gpio::PB13<Alternate<1>> {}
+impl PinC1N<TIM1> for gpio::PE8<Alternate<1>> {}
+impl PinC2N<TIM1> for gpio::PB0<Alternate<1>> {}
+impl PinC2N<TIM1> for gpio::PB14<Alternate<1>> {}
+impl PinC2N<TIM1> for gpio::PE10<Alternate<1>> {}
+impl PinC3N<TIM1> for gpio::PB1<Alternate<1>> {}
+impl PinC3N<TIM1> for gpio::PB15<Alternate<1>> {}
+impl PinC3N<TIM1> for gpio::PE12<Alternate<1>> {}
+impl PinBkin<TIM1> for gpio::PA6<Alternate<1>> {}
+impl PinBkin<TIM1> for gpio::PB12<Alternate<1>> {}
+impl PinBkin<TIM1> for gpio::PE15<Alternate<1>> {}
+
+impl PinC1<TIM2> for gpio::PA0<Alternate<1>> {}
+impl PinC1<TIM2> for gpio::PA5<Alternate<1>> {}
+impl PinC1<TIM2> for gpio::PA15<Alternate<1>> {}
+impl PinC2<TIM2> for gpio::PA1<Alternate<1>> {}
+impl PinC2<TIM2> for gpio::PB3<Alternate<1>> {}
+impl PinC3<TIM2> for gpio::PA2<Alternate<1>> {}
+impl PinC3<TIM2> for gpio::PB10<Alternate<1>> {}
+impl PinC4<TIM2> for gpio::PA3<Alternate<1>> {}
+impl PinC4<TIM2> for gpio::PB11<Alternate<1>> {}
+
+impl PinC1<TIM3> for gpio::PA6<Alternate<2>> {}
+impl PinC1<TIM3> for gpio::PB4<Alternate<2>> {}
+impl PinC1<TIM3> for gpio::PC6<Alternate<2>> {}
+impl PinC2<TIM3> for gpio::PA7<Alternate<2>> {}
+impl PinC2<TIM3> for gpio::PB5<Alternate<2>> {}
+impl PinC2<TIM3> for gpio::PC7<Alternate<2>> {}
+impl PinC3<TIM3> for gpio::PB0<Alternate<2>> {}
+impl PinC3<TIM3> for gpio::PC8<Alternate<2>> {}
+impl PinC4<TIM3> for gpio::PB1<Alternate<2>> {}
+impl PinC4<TIM3> for gpio::PC9<Alternate<2>> {}
+
+impl PinC1<TIM4> for gpio::PB6<Alternate<2>> {}
+impl PinC1<TIM4> for gpio::PD12<Alternate<2>> {}
+impl PinC2<TIM4> for gpio::PB7<Alternate<2>> {}
+impl PinC2<TIM4> for gpio::PD13<Alternate<2>> {}
+impl PinC3<TIM4> for gpio::PB8<Alternate<2>> {}
+impl PinC3<TIM4> for gpio::PD14<Alternate<2>> {}
+impl PinC4<TIM4> for gpio::PB9<Alternate<2>> {}
+impl PinC4<TIM4> for gpio::PD15<Alternate<2>> {}
+
+impl PinC1<TIM5> for gpio::PA0<Alternate<2>> {}
+impl PinC1<TIM5> for gpio::PH10<Alternate<2>> {}
+impl PinC2<TIM5> for gpio::PA1<Alternate<2>> {}
+impl PinC2<TIM5> for gpio::PH11<Alternate<2>> {}
+impl PinC3<TIM5> for gpio::PA2<Alternate<2>> {}
+impl PinC3<TIM5> for gpio::PH12<Alternate<2>> {}
+impl PinC4<TIM5> for gpio::PA3<Alternate<2>> {}
+impl PinC4<TIM5> for gpio::PI0<Alternate<2>> {}
+
+impl PinC1<TIM8> for gpio::PC6<Alternate<3>> {}
+impl PinC1<TIM8> for gpio::PI5<Alternate<3>> {}
+impl PinC2<TIM8> for gpio::PC7<Alternate<3>> {}
+impl PinC2<TIM8> for gpio::PI6<Alternate<3>> {}
+impl PinC3<TIM8> for gpio::PC8<Alternate<3>> {}
+impl PinC3<TIM8> for gpio::PI7<Alternate<3>> {}
+impl PinC4<TIM8> for gpio::PC9<Alternate<3>> {}
+impl PinC4<TIM8> for gpio::PI2<Alternate<3>> {}
+impl PinC1N<TIM8> for gpio::PA5<Alternate<3>> {}
+impl PinC1N<TIM8> for gpio::PA7<Alternate<3>> {}
+impl PinC1N<TIM8> for gpio::PH13<Alternate<3>> {}
+impl PinC2N<TIM8> for gpio::PB0<Alternate<3>> {}
+impl PinC2N<TIM8> for gpio::PB14<Alternate<3>> {}
+impl PinC2N<TIM8> for gpio::PH14<Alternate<3>> {}
+impl PinC3N<TIM8> for gpio::PB1<Alternate<3>> {}
+impl PinC3N<TIM8> for gpio::PB15<Alternate<3>> {}
+impl PinC3N<TIM8> for gpio::PH15<Alternate<3>> {}
+impl PinBkin<TIM8> for gpio::PA6<Alternate<3>> {}
+impl PinBkin<TIM8> for gpio::PI4<Alternate<3>> {}
+
+impl PinC1<TIM9> for gpio::PA2<Alternate<3>> {}
+impl PinC1<TIM9> for gpio::PE5<Alternate<3>> {}
+impl PinC2<TIM9> for gpio::PA3<Alternate<3>> {}
+impl PinC2<TIM9> for gpio::PE6<Alternate<3>> {}
+
+impl PinC1<TIM10> for gpio::PB8<Alternate<3>> {}
+impl PinC1<TIM10> for gpio::PF6<Alternate<3>> {}
+
+impl PinC1<TIM11> for gpio::PB9<Alternate<3>> {}
+impl PinC1<TIM11> for gpio::PF7<Alternate<3>> {}
+
+impl PinC1<TIM12> for gpio::PB14<Alternate<9>> {}
+impl PinC1<TIM12> for gpio::PH6<Alternate<9>> {}
+impl PinC2<TIM12> for gpio::PB15<Alternate<9>> {}
+impl PinC2<TIM12> for gpio::PH9<Alternate<9>> {}
+
+impl PinC1<TIM13> for gpio::PA6<Alternate<9>> {}
+impl PinC1<TIM13> for gpio::PF8<Alternate<9>> {}
+
+impl PinC1<TIM14> for gpio::PA7<Alternate<9>> {}
+impl PinC1<TIM14> for gpio::PF9<Alternate<9>> {}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_psc_arr, dead_time_bits};
+    use fugit::RateExtU32;
+
+    #[test]
+    fn test_psc_arr_16bit() {
+        // 216 MHz / 20 kHz = 10800 ticks, fits without prescaling
+        assert_eq!(compute_psc_arr(216.MHz(), 20.kHz(), 0xffff), (0, 10799));
+        // 108 MHz / 1 Hz needs a prescaler, the period is off by less than
+        // one prescaled tick
+        let (psc, arr) = compute_psc_arr(108.MHz(), 1.Hz(), 0xffff);
+        let psc = u32::from(psc) + 1;
+        assert!(arr <= 0xffff);
+        assert!(108_000_000 - psc * (arr + 1) < psc);
+    }
+
+    #[test]
+    fn test_psc_arr_clamped() {
+        // Too slow to reach, the slowest rate is used instead
+        assert_eq!(compute_psc_arr(216.MHz(), 0.Hz(), 0xffff), (0xffff, 0xffff));
+        // Faster than the timer clock
+        assert_eq!(compute_psc_arr(108.MHz(), 216.MHz(), 0xffff), (0, 0));
+    }
+
+    #[test]
+    fn test_psc_arr_32bit() {
+        assert_eq!(compute_psc_arr(108.MHz(), 1.Hz(), 0xffff_ffff), (0, 107_999_999));
+    }
+
+    #[test]
+    fn test_dead_time_bits() {
+        assert_eq!(dead_time_bits(0), Some(0));
+        assert_eq!(dead_time_bits(127), Some(127));
+        assert_eq!(dead_time_bits(128), Some(0b1000_0000));
+        assert_eq!(dead_time_bits(129), Some(0b1000_0001));
+        assert_eq!(dead_time_bits(254), Some(0b1011_1111));
+        assert_eq!(dead_time_bits(255), Some(0b1100_0000));
+        assert_eq!(dead_time_bits(256), Some(0b1100_0000));
+        assert_eq!(dead_time_bits(504), Some(0b1101_1111));
+        assert_eq!(dead_time_bits(505), Some(0b1110_0000));
+        assert_eq!(dead_time_bits(512), Some(0b1110_0000));
+        assert_eq!(dead_time_bits(1008), Some(0b1111_1111));
+        assert_eq!(dead_time_bits(1009), None);
+    }
+}
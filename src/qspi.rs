@@ -91,14 +91,16 @@ impl Qspi {
     /// DMA read. Wrapper around the HAL DMA driver. Performs QSPI register programming, creates a
     /// DMA transfer from peripheral to memory, and starts the transfer. Caller can use the DMA
     /// `wait` API to block until the transfer is complete.
-    pub fn read_all<B>(
+    pub fn read_all<B, S>(
         &mut self,
         data: Pin<B>,
         transaction: QspiTransaction,
-        dma: &dma::Handle<<RxTx<QUADSPI> as dma::Target>::Instance, state::Enabled>,
-        stream: <RxTx<QUADSPI> as dma::Target>::Stream,
-    ) -> Result<dma::Transfer<RxTx<QUADSPI>, B, dma::Started>, Error>
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Result<dma::Transfer<RxTx<QUADSPI>, S, B, dma::Started>, Error>
     where
+        RxTx<QUADSPI>: dma::Target<S>,
+        S: dma::Stream,
         B: Deref + 'static,
         B::Target: AsSlice<Element = u8>,
     {
@@ -140,14 +142,16 @@ impl Qspi {
     /// DMA write. Wrapper around the HAL DMA driver. Performs QSPI register programming, creates a
     /// DMA transfer from memory to peripheral, and starts the transfer. Caller can use the DMA
     /// `wait` API to block until the transfer is complete.
-    pub fn write_all<B>(
+    pub fn write_all<B, S>(
         &mut self,
         data: Pin<B>,
         transaction: QspiTransaction,
-        dma: &dma::Handle<<RxTx<QUADSPI> as dma::Target>::Instance, state::Enabled>,
-        stream: <RxTx<QUADSPI> as dma::Target>::Stream,
-    ) -> Result<dma::Transfer<RxTx<QUADSPI>, B, dma::Started>, Error>
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Result<dma::Transfer<RxTx<QUADSPI>, S, B, dma::Started>, Error>
     where
+        RxTx<QUADSPI>: dma::Target<S>,
+        S: dma::Stream,
         B: Deref + 'static,
         B::Target: AsSlice<Element = u8>,
     {
@@ -50,10 +50,13 @@ impl RccExt for RCC {
                 plli2sr: 2,
                 plli2sq: 2,
                 plli2sn: 192,
+                pllsaidivq: 1,
+                plli2sdivq: 1,
                 mco1: MCO1::Hsi,
                 mco1pre: MCOPRE::Div1_no_div,
                 mco2: MCO2::Sysclk,
                 mco2pre: MCOPRE::Div1_no_div,
+                kernel_clocks: KernelClocks::default(),
             },
         }
     }
@@ -323,10 +326,13 @@ pub struct CFGR {
     plli2sr: u8,
     plli2sq: u8,
     plli2sn: u16,
+    pllsaidivq: u8,
+    plli2sdivq: u8,
     mco1: MCO1,
     mco1pre: MCOPRE,
     mco2: MCO2,
     mco2pre: MCOPRE,
+    kernel_clocks: KernelClocks,
 }
 
 impl CFGR {
@@ -401,6 +407,12 @@ impl CFGR {
         self
     }
 
+    /// Selects the kernel clock sources of the USARTs, I2Cs, LPTIM1 and SAIs.
+    pub fn kernel_clocks(mut self, kernel_clocks: KernelClocks) -> Self {
+        self.kernel_clocks = kernel_clocks;
+        self
+    }
+
     /// Sets the LSI clock source to 32 kHz.
     ///
     /// Be aware that the tolerance is up to ±47% (Min 17 kHz, Typ 32 kHz, Max 47 kHz).
@@ -534,6 +546,30 @@ impl CFGR {
         self
     }
 
+    /// Sets the PLLSAIDIVQ division factor, between the Q output of PLLSAI and
+    /// the SAIs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the division factor isn't between 1 and 32.
+    pub fn pllsaidivq(mut self, pllsaidivq: u8) -> Self {
+        assert!((1..=32).contains(&pllsaidivq));
+        self.pllsaidivq = pllsaidivq;
+        self
+    }
+
+    /// Sets the PLLI2SDIVQ division factor, between the Q output of PLLI2S and
+    /// the SAIs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the division factor isn't between 1 and 32.
+    pub fn plli2sdivq(mut self, plli2sdivq: u8) -> Self {
+        assert!((1..=32).contains(&plli2sdivq));
+        self.plli2sdivq = plli2sdivq;
+        self
+    }
+
     /// Sets the MCO1 source
     pub fn mco1(mut self, mco1: MCO1) -> Self {
         self.mco1 = mco1;
@@ -616,6 +652,39 @@ impl CFGR {
             }
         }
 
+        // The R output of PLLI2S clocks the I2S interfaces
+        let plli2sr = if self.use_plli2s {
+            Some(
+                (base_clk as u64 * self.plli2sn as u64 / self.pllm as u64 / self.plli2sr as u64)
+                    as u32,
+            )
+        } else {
+            None
+        };
+
+        // The Q outputs of PLLSAI and PLLI2S, divided by PLLSAIDIVQ and
+        // PLLI2SDIVQ, can clock the SAIs
+        let pllsaidivq = if self.use_pllsai {
+            Some(
+                (base_clk as u64 * self.pllsain as u64
+                    / self.pllm as u64
+                    / self.pllsaiq as u64
+                    / self.pllsaidivq as u64) as u32,
+            )
+        } else {
+            None
+        };
+        let plli2sdivq = if self.use_plli2s {
+            Some(
+                (base_clk as u64 * self.plli2sn as u64
+                    / self.pllm as u64
+                    / self.plli2sq as u64
+                    / self.plli2sdivq as u64) as u32,
+            )
+        } else {
+            None
+        };
+
         // SYSCLK, must be <= 216 Mhz. By default, HSI/HSE frequency is chosen
         assert!(sysclk <= 216_000_000);
         let sysclk = sysclk as u32;
@@ -769,6 +838,10 @@ impl CFGR {
             hse: self.hse.map(|hse| hse.freq),
             lse: self.lse.map(|lse| lse.freq),
             lsi: self.lsi,
+            kernel_clocks: self.kernel_clocks,
+            plli2sr: plli2sr.map(|f| f.Hz()),
+            pllsaidivq: pllsaidivq.map(|f| f.Hz()),
+            plli2sdivq: plli2sdivq.map(|f| f.Hz()),
         };
 
         (clocks, config)
@@ -1041,13 +1114,14 @@ impl CFGR {
                     PLLSAIP::Div6 => 6,
                     PLLSAIP::Div8 => 8,
                 };
-            // let pllsaiq_freq = pllsain_freq / self.pllsaiq as u64;
+            let pllsaiq_freq = pllsain_freq / self.pllsaiq as u64;
 
             // The reference manual (RM0410 Rev 4, Page 212), says the following
             // "Caution: The software has to set these bits correctly to ensure that the VCO output frequency is between 100 and 432 MHz.",
             // but STM32CubeMX states 192 MHz as the minimum. SSo the stricter requirement was chosen.
             assert!((192_000_000..=432_000_000).contains(&pllsain_freq));
             assert!(pllsaip_freq <= 48_000_000);
+            assert!(pllsaiq_freq <= 216_000_000);
 
             rcc.pllsaicfgr.modify(|_, w| unsafe {
                 w.pllsain().bits(self.pllsain);
@@ -1055,6 +1129,7 @@ impl CFGR {
                 w.pllsaiq().bits(self.pllsaiq)
             });
             rcc.cr.modify(|_, w| w.pllsaion().on());
+            while rcc.cr.read().pllsairdy().is_not_ready() {}
         }
 
         if let Some(pll48clk) = self.pll48clk {
@@ -1064,6 +1139,13 @@ impl CFGR {
             }
         }
 
+        // Select the kernel clocks
+        assert!(self.lse.is_some() || !self.kernel_clocks.uses_lse());
+        assert!(self.lsi.is_some() || self.kernel_clocks.lptim1 != LptimClock::Lsi);
+        let (mask, bits) = self.kernel_clocks.dckcfgr2();
+        rcc.dckcfgr2
+            .modify(|r, w| unsafe { w.bits(r.bits() & !mask | bits) });
+
         if self.use_plli2s {
             let plli2sn_freq = match self.hse.as_ref() {
                 Some(hse) => hse.freq.raw() as u64 / self.pllm as u64 * self.plli2sn as u64,
@@ -1082,8 +1164,19 @@ impl CFGR {
                 w.plli2sq().bits(self.plli2sq)
             });
             rcc.cr.modify(|_, w| w.plli2son().on());
+            while rcc.cr.read().plli2srdy().is_not_ready() {}
+
+            // Clock the I2S interfaces from PLLI2S rather than I2S_CKIN
+            rcc.cfgr.modify(|_, w| w.i2ssrc().clear_bit());
         }
 
+        // Select the SAI clocks, and the dividers of the PLL outputs feeding
+        // them. PLLSAIDIVR, used by the LTDC, is left untouched.
+        let (mask, bits) = self.kernel_clocks.dckcfgr1();
+        let divq = u32::from(self.pllsaidivq - 1) << 8 | u32::from(self.plli2sdivq - 1);
+        rcc.dckcfgr1
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(mask | 0x1f1f) | bits | divq) });
+
         rcc.cfgr.modify(|_, w| {
             w.mco1()
                 .variant(self.mco1.into())
@@ -1144,6 +1237,10 @@ pub struct Clocks {
     hse: Option<Hertz>,
     lse: Option<Hertz>,
     lsi: Option<Hertz>,
+    kernel_clocks: KernelClocks,
+    plli2sr: Option<Hertz>,
+    pllsaidivq: Option<Hertz>,
+    plli2sdivq: Option<Hertz>,
 }
 
 impl Clocks {
@@ -1198,6 +1295,301 @@ impl Clocks {
     pub fn lsi(&self) -> Option<Hertz> {
         self.lsi
     }
+
+    /// Returns the frequency of the I2S clock, the R output of PLLI2S, if
+    /// enabled
+    pub fn i2sclk(&self) -> Option<Hertz> {
+        self.plli2sr
+    }
+
+    /// Returns the frequency of the Q output of PLLSAI, divided by
+    /// PLLSAIDIVQ, if enabled
+    pub fn pllsaidivq(&self) -> Option<Hertz> {
+        self.pllsaidivq
+    }
+
+    /// Returns the frequency of the Q output of PLLI2S, divided by
+    /// PLLI2SDIVQ, if enabled
+    pub fn plli2sdivq(&self) -> Option<Hertz> {
+        self.plli2sdivq
+    }
+
+    /// Returns the selected kernel clock sources
+    pub fn kernel_clocks(&self) -> KernelClocks {
+        self.kernel_clocks
+    }
+
+    /// Returns the kernel clock frequency of peripheral `P`
+    pub fn kernel_clock<P: KernelClock>(&self) -> Hertz {
+        P::kernel_clock(self)
+    }
+}
+
+/// Kernel clock source of a USART
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsartClock {
+    /// The clock of the APB bus the USART is connected to
+    Pclk,
+    Sysclk,
+    Hsi,
+    Lse,
+}
+
+/// Kernel clock source of an I2C
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum I2cClock {
+    /// The APB1 clock
+    Pclk,
+    Sysclk,
+    Hsi,
+}
+
+/// Kernel clock source of LPTIM1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LptimClock {
+    /// The APB1 clock
+    Pclk,
+    Lsi,
+    Hsi,
+    Lse,
+}
+
+/// Kernel clock source of a SAI
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaiClock {
+    /// The Q output of PLLSAI, divided by PLLSAIDIVQ
+    PllSai,
+    /// The Q output of PLLI2S, divided by PLLI2SDIVQ
+    PllI2s,
+}
+
+/// Kernel clock sources of the peripherals with a clock multiplexer in
+/// DCKCFGR1 or DCKCFGR2
+///
+/// By default, the USARTs run from SYSCLK, the I2Cs and LPTIM1 from their APB
+/// clock, and the SAIs from PLLSAI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KernelClocks {
+    pub usart1: UsartClock,
+    pub usart2: UsartClock,
+    pub usart3: UsartClock,
+    pub uart4: UsartClock,
+    pub uart5: UsartClock,
+    pub usart6: UsartClock,
+    pub uart7: UsartClock,
+    pub uart8: UsartClock,
+    pub i2c1: I2cClock,
+    pub i2c2: I2cClock,
+    pub i2c3: I2cClock,
+    pub i2c4: I2cClock,
+    pub lptim1: LptimClock,
+    pub sai1: SaiClock,
+    pub sai2: SaiClock,
+}
+
+impl Default for KernelClocks {
+    fn default() -> Self {
+        Self {
+            usart1: UsartClock::Sysclk,
+            usart2: UsartClock::Sysclk,
+            usart3: UsartClock::Sysclk,
+            uart4: UsartClock::Sysclk,
+            uart5: UsartClock::Sysclk,
+            usart6: UsartClock::Sysclk,
+            uart7: UsartClock::Sysclk,
+            uart8: UsartClock::Sysclk,
+            i2c1: I2cClock::Pclk,
+            i2c2: I2cClock::Pclk,
+            i2c3: I2cClock::Pclk,
+            i2c4: I2cClock::Pclk,
+            lptim1: LptimClock::Pclk,
+            sai1: SaiClock::PllSai,
+            sai2: SaiClock::PllSai,
+        }
+    }
+}
+
+impl KernelClocks {
+    fn usarts(&self) -> [UsartClock; 8] {
+        [
+            self.usart1,
+            self.usart2,
+            self.usart3,
+            self.uart4,
+            self.uart5,
+            self.usart6,
+            self.uart7,
+            self.uart8,
+        ]
+    }
+
+    fn uses_lse(&self) -> bool {
+        self.usarts().iter().any(|&clock| clock == UsartClock::Lse)
+            || self.lptim1 == LptimClock::Lse
+    }
+
+    /// Returns the mask and the value of the selection fields in DCKCFGR2
+    fn dckcfgr2(&self) -> (u32, u32) {
+        let mut bits = 0;
+
+        // USART1SEL to UART8SEL occupy bits 0 to 15
+        for (i, clock) in self.usarts().iter().enumerate() {
+            let sel = match clock {
+                UsartClock::Pclk => 0b00,
+                UsartClock::Sysclk => 0b01,
+                UsartClock::Hsi => 0b10,
+                UsartClock::Lse => 0b11,
+            };
+            bits |= sel << (2 * i);
+        }
+
+        // I2C1SEL to I2C4SEL occupy bits 16 to 23
+        for (i, clock) in [self.i2c1, self.i2c2, self.i2c3, self.i2c4]
+            .iter()
+            .enumerate()
+        {
+            let sel = match clock {
+                I2cClock::Pclk => 0b00,
+                I2cClock::Sysclk => 0b01,
+                I2cClock::Hsi => 0b10,
+            };
+            bits |= sel << (16 + 2 * i);
+        }
+
+        // LPTIM1SEL occupies bits 24 and 25
+        let sel = match self.lptim1 {
+            LptimClock::Pclk => 0b00,
+            LptimClock::Lsi => 0b01,
+            LptimClock::Hsi => 0b10,
+            LptimClock::Lse => 0b11,
+        };
+        bits |= sel << 24;
+
+        (0x03ff_ffff, bits)
+    }
+
+    /// Returns the mask and the value of the selection fields in DCKCFGR1
+    fn dckcfgr1(&self) -> (u32, u32) {
+        let mut bits = 0;
+
+        // SAI1SEL and SAI2SEL occupy bits 20 to 23
+        for (i, clock) in [self.sai1, self.sai2].iter().enumerate() {
+            let sel = match clock {
+                SaiClock::PllSai => 0b00,
+                SaiClock::PllI2s => 0b01,
+            };
+            bits |= sel << (20 + 2 * i);
+        }
+
+        (0x00f0_0000, bits)
+    }
+}
+
+/// Implemented by the peripherals with a selectable kernel clock
+pub trait KernelClock {
+    /// Returns the frequency of the kernel clock
+    ///
+    /// # Panics
+    ///
+    /// Panics if the selected oscillator has not been configured.
+    fn kernel_clock(clocks: &Clocks) -> Hertz;
+}
+
+macro_rules! usart_kernel_clock {
+    ($($USARTX:ident: ($field:ident, $pclk:ident),)+) => {
+        $(
+            impl KernelClock for crate::pac::$USARTX {
+                fn kernel_clock(clocks: &Clocks) -> Hertz {
+                    match clocks.kernel_clocks.$field {
+                        UsartClock::Pclk => clocks.$pclk,
+                        UsartClock::Sysclk => clocks.sysclk,
+                        UsartClock::Hsi => HSI_FREQUENCY,
+                        UsartClock::Lse => clocks.lse.unwrap(),
+                    }
+                }
+            }
+        )+
+    }
+}
+
+usart_kernel_clock! {
+    USART1: (usart1, pclk2),
+    USART2: (usart2, pclk1),
+    USART3: (usart3, pclk1),
+    UART4: (uart4, pclk1),
+    UART5: (uart5, pclk1),
+    USART6: (usart6, pclk2),
+    UART7: (uart7, pclk1),
+    UART8: (uart8, pclk1),
+}
+
+macro_rules! i2c_kernel_clock {
+    ($($I2CX:ident: $field:ident,)+) => {
+        $(
+            impl KernelClock for crate::pac::$I2CX {
+                fn kernel_clock(clocks: &Clocks) -> Hertz {
+                    match clocks.kernel_clocks.$field {
+                        I2cClock::Pclk => clocks.pclk1,
+                        I2cClock::Sysclk => clocks.sysclk,
+                        I2cClock::Hsi => HSI_FREQUENCY,
+                    }
+                }
+            }
+        )+
+    }
+}
+
+i2c_kernel_clock! {
+    I2C1: i2c1,
+    I2C2: i2c2,
+    I2C3: i2c3,
+}
+
+#[cfg(any(
+    feature = "svd-f745",
+    feature = "svd-f750",
+    feature = "svd-f7x6",
+    feature = "svd-f765",
+    feature = "svd-f7x7",
+    feature = "svd-f7x9",
+))]
+i2c_kernel_clock! {
+    I2C4: i2c4,
+}
+
+impl KernelClock for crate::pac::LPTIM1 {
+    fn kernel_clock(clocks: &Clocks) -> Hertz {
+        match clocks.kernel_clocks.lptim1 {
+            LptimClock::Pclk => clocks.pclk1,
+            LptimClock::Lsi => clocks.lsi.unwrap(),
+            LptimClock::Hsi => HSI_FREQUENCY,
+            LptimClock::Lse => clocks.lse.unwrap(),
+        }
+    }
+}
+
+macro_rules! sai_kernel_clock {
+    ($($SAIX:ident: $field:ident,)+) => {
+        $(
+            impl KernelClock for crate::pac::$SAIX {
+                fn kernel_clock(clocks: &Clocks) -> Hertz {
+                    match clocks.kernel_clocks.$field {
+                        SaiClock::PllSai => clocks
+                            .pllsaidivq
+                            .expect("PLLSAI must be enabled to clock the SAI"),
+                        SaiClock::PllI2s => clocks
+                            .plli2sdivq
+                            .expect("PLLI2S must be enabled to clock the SAI"),
+                    }
+                }
+            }
+        )+
+    }
+}
+
+sai_kernel_clock! {
+    SAI1: sai1,
+    SAI2: sai2,
 }
 
 /// Trait to get the frequency of a bus.
@@ -1354,7 +1746,7 @@ pub trait Reset: RccBus {
 mod tests {
     use fugit::{HertzU32 as Hertz, RateExtU32};
 
-    use super::{FreqRequest, CFGR};
+    use super::{FreqRequest, I2cClock, KernelClocks, LptimClock, SaiClock, UsartClock, CFGR};
 
     fn build_request(sysclk: u32, use_pll48clk: bool) -> FreqRequest {
         let p = Some((sysclk - 1, sysclk + 1));
@@ -1479,10 +1871,13 @@ mod tests {
             plli2sr: 2,
             plli2sq: 2,
             plli2sn: 192,
+            pllsaidivq: 1,
+            plli2sdivq: 1,
             mco1: MCO1::Hsi,
             mco1pre: MCOPRE::Div1_no_div,
             mco2: MCO2::Sysclk,
             mco2pre: MCOPRE::Div1_no_div,
+            kernel_clocks: KernelClocks::default(),
         };
 
         let mut cfgr = cfgr
@@ -1525,10 +1920,13 @@ mod tests {
             plli2sr: 2,
             plli2sq: 2,
             plli2sn: 192,
+            pllsaidivq: 1,
+            plli2sdivq: 1,
             mco1: MCO1::Hsi,
             mco1pre: MCOPRE::Div1_no_div,
             mco2: MCO2::Sysclk,
             mco2pre: MCOPRE::Div1_no_div,
+            kernel_clocks: KernelClocks::default(),
         };
 
         let mut cfgr = cfgr
@@ -1570,10 +1968,13 @@ mod tests {
             plli2sr: 2,
             plli2sq: 2,
             plli2sn: 192,
+            pllsaidivq: 1,
+            plli2sdivq: 1,
             mco1: MCO1::Hsi,
             mco1pre: MCOPRE::Div1_no_div,
             mco2: MCO2::Sysclk,
             mco2pre: MCOPRE::Div1_no_div,
+            kernel_clocks: KernelClocks::default(),
         };
 
         let mut cfgr = cfgr
@@ -1615,10 +2016,13 @@ mod tests {
             plli2sr: 2,
             plli2sq: 2,
             plli2sn: 192,
+            pllsaidivq: 1,
+            plli2sdivq: 1,
             mco1: MCO1::Hsi,
             mco1pre: MCOPRE::Div1_no_div,
             mco2: MCO2::Sysclk,
             mco2pre: MCOPRE::Div1_no_div,
+            kernel_clocks: KernelClocks::default(),
         };
 
         cfgr.pll_configure();
@@ -1626,4 +2030,37 @@ mod tests {
         let (clocks, _config) = cfgr.calculate_clocks();
         assert_eq!(clocks.sysclk().raw(), 16_000_000);
     }
+
+    #[test]
+    fn test_kernel_clocks_dckcfgr2() {
+        let (mask, bits) = KernelClocks::default().dckcfgr2();
+        assert_eq!(mask, 0x03ff_ffff);
+        assert_eq!(bits, 0x0000_5555);
+
+        let kernel_clocks = KernelClocks {
+            usart1: UsartClock::Pclk,
+            usart2: UsartClock::Hsi,
+            uart8: UsartClock::Lse,
+            i2c2: I2cClock::Sysclk,
+            i2c4: I2cClock::Hsi,
+            lptim1: LptimClock::Lse,
+            ..KernelClocks::default()
+        };
+        let (_, bits) = kernel_clocks.dckcfgr2();
+        assert_eq!(bits, 0x0384_d558);
+    }
+
+    #[test]
+    fn test_kernel_clocks_dckcfgr1() {
+        let (mask, bits) = KernelClocks::default().dckcfgr1();
+        assert_eq!(mask, 0x00f0_0000);
+        assert_eq!(bits, 0);
+
+        let kernel_clocks = KernelClocks {
+            sai2: SaiClock::PllI2s,
+            ..KernelClocks::default()
+        };
+        let (_, bits) = kernel_clocks.dckcfgr1();
+        assert_eq!(bits, 0x0040_0000);
+    }
 }
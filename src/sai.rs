@@ -0,0 +1,991 @@
+//! Serial Audio Interface (SAI)
+//!
+//! Each SAI has two independent audio blocks, A and B, obtained with
+//! [`Sai::split`]. A block is a transmitter or a receiver, master or slave.
+//! A slave block can also run synchronously with the other block of the same
+//! SAI, sharing its bit clock and frame synchronization, e.g. for full-duplex
+//! audio on a single set of clock pins.
+//!
+//! The SAIs are clocked by PLLSAI or PLLI2S, selected with
+//! [`KernelClocks`]. The sample rate of a master block is derived from that
+//! clock, see [`Block::sample_rate`] for the rate actually achieved.
+//!
+//! Samples are transferred as right-aligned 32-bit words.
+//!
+//! See chapter 33 in the STM32F746 Reference Manual.
+//!
+//! [`KernelClocks`]: crate::rcc::KernelClocks
+
+use core::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    ptr,
+};
+
+use as_slice::{AsMutSlice, AsSlice};
+
+use crate::{
+    dma,
+    gpio::{self, Alternate},
+    pac,
+    rcc::{Clocks, Enable, KernelClock, Reset},
+    state,
+};
+use fugit::HertzU32 as Hertz;
+
+/// Configuration and direction of an audio block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    MasterTransmit,
+    MasterReceive,
+    SlaveTransmit,
+    SlaveReceive,
+}
+
+impl Mode {
+    fn is_master(self) -> bool {
+        matches!(self, Mode::MasterTransmit | Mode::MasterReceive)
+    }
+}
+
+/// Audio protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// I2S Philips standard, with two slots
+    I2s,
+    /// Left-justified, with two slots
+    MsbJustified,
+    /// Right-justified, with two slots
+    LsbJustified,
+    /// Time-division multiplexing of 1 to 16 slots
+    ///
+    /// The frame synchronization is a pulse of one bit clock cycle, before
+    /// the first bit of the first slot, as expected by TDM codecs and by
+    /// short-frame PCM.
+    Tdm { slots: u8 },
+    /// AC'97 link, with 13 slots in a frame of 256 bits
+    Ac97,
+    /// SPDIF output, for a master transmitter
+    Spdif,
+}
+
+/// Size of the audio data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataSize {
+    Bits8,
+    Bits10,
+    Bits16,
+    Bits20,
+    Bits24,
+    Bits32,
+}
+
+impl DataSize {
+    fn bits(self) -> u32 {
+        match self {
+            DataSize::Bits8 => 8,
+            DataSize::Bits10 => 10,
+            DataSize::Bits16 => 16,
+            DataSize::Bits20 => 20,
+            DataSize::Bits24 => 24,
+            DataSize::Bits32 => 32,
+        }
+    }
+}
+
+/// Size of a slot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotSize {
+    /// Same as the data size
+    DataSize,
+    Bits16,
+    Bits32,
+}
+
+/// Audio block configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub mode: Mode,
+    pub protocol: Protocol,
+    pub data_size: DataSize,
+    /// Size of a slot, at least the data size, ignored by AC'97 and SPDIF
+    pub slot_size: SlotSize,
+    /// Enabled slots, one bit per slot, ignored by SPDIF
+    pub slots: u16,
+    /// Whether a slave uses the clocks of the other block of the same SAI
+    pub synchronous: bool,
+    /// Sample rate of a master, ignored by a slave
+    pub sample_rate: Hertz,
+}
+
+impl Config {
+    /// Creates an I2S configuration with 16-bit samples at 48 kHz
+    pub fn new(mode: Mode) -> Self {
+        Config {
+            mode,
+            protocol: Protocol::I2s,
+            data_size: DataSize::Bits16,
+            slot_size: SlotSize::DataSize,
+            slots: 0b11,
+            synchronous: false,
+            sample_rate: Hertz::from_raw(48_000),
+        }
+    }
+}
+
+/// SAI error
+#[derive(Debug)]
+pub enum Error {
+    /// A receiver had no room for a sample
+    Overrun,
+    /// A transmitter had no sample to send
+    Underrun,
+    /// A slave detected a frame synchronization earlier than expected
+    AnticipatedFrameSync,
+    /// A slave detected a frame synchronization later than expected
+    LateFrameSync,
+    /// The AC'97 codec is not ready to communicate
+    CodecNotReady,
+}
+
+/// Implemented for the SAI peripherals
+///
+/// Users of this crate should not implement this trait.
+pub trait Instance: Enable + Reset + KernelClock {
+    /// Returns the address of the register block
+    fn address() -> usize;
+}
+
+macro_rules! impl_instance {
+    ($($sai:ident,)*) => {
+        $(
+            impl Instance for pac::$sai {
+                fn address() -> usize {
+                    pac::$sai::ptr() as usize
+                }
+            }
+        )*
+    };
+}
+
+impl_instance!(SAI1, SAI2,);
+
+/// Implemented for the audio block markers [`A`] and [`B`]
+pub trait AudioBlock {
+    /// Offset of the block registers in the SAI register block
+    const OFFSET: usize;
+}
+
+/// Audio block A
+pub struct A;
+impl AudioBlock for A {
+    const OFFSET: usize = 0x04;
+}
+
+/// Audio block B
+pub struct B;
+impl AudioBlock for B {
+    const OFFSET: usize = 0x24;
+}
+
+/// Entry point to the SAI API
+pub struct Sai<SAI> {
+    _sai: SAI,
+}
+
+impl<SAI> Sai<SAI>
+where
+    SAI: Instance,
+{
+    /// Enables and resets the SAI peripheral
+    pub fn new(sai: SAI, apb: &mut SAI::Bus) -> Self {
+        SAI::enable(apb);
+        SAI::reset(apb);
+
+        Sai { _sai: sai }
+    }
+
+    /// Splits the SAI into its two audio blocks
+    pub fn split(self) -> (SubBlock<SAI, A>, SubBlock<SAI, B>) {
+        (SubBlock::new(), SubBlock::new())
+    }
+}
+
+/// An unconfigured audio block
+///
+/// The two blocks of an SAI share the register block, at different offsets.
+/// Each token only accesses the registers of its own block.
+pub struct SubBlock<SAI, BLOCK> {
+    _sai: PhantomData<SAI>,
+    _block: PhantomData<BLOCK>,
+}
+
+impl<SAI, BLOCK> SubBlock<SAI, BLOCK>
+where
+    SAI: Instance,
+    BLOCK: AudioBlock,
+{
+    fn new() -> Self {
+        SubBlock {
+            _sai: PhantomData,
+            _block: PhantomData,
+        }
+    }
+
+    fn address(&self, offset: usize) -> usize {
+        SAI::address() + BLOCK::OFFSET + offset
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        // NOTE(unsafe) atomic read of a register of this block
+        unsafe { ptr::read_volatile(self.address(offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        // NOTE(unsafe) atomic write to a register of this block
+        unsafe { ptr::write_volatile(self.address(offset) as *mut u32, value) }
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        let cr1 = self.read(CR1);
+        if enabled {
+            self.write(CR1, cr1 | CR1_SAIEN);
+        } else {
+            self.write(CR1, cr1 & !CR1_SAIEN);
+            // The block is only disabled at the end of the current frame
+            while self.read(CR1) & CR1_SAIEN != 0 {}
+        }
+    }
+}
+
+/// Token used for DMA transfers
+pub struct RxTx<SAI, BLOCK>(PhantomData<(SAI, BLOCK)>);
+
+/// Implemented for all pins that can function as the MCLK pin of a block
+///
+/// Users of this crate should not implement this trait.
+pub trait PinMclk<SAI, BLOCK> {
+    /// Whether this is an actual pin, enabling the master clock output
+    const OUTPUT: bool = true;
+}
+
+/// Implemented for all pins that can function as the SCK pin of a block
+///
+/// Users of this crate should not implement this trait.
+pub trait PinSck<SAI, BLOCK> {
+    /// Whether this is an actual pin
+    const CONNECTED: bool = true;
+}
+
+/// Implemented for all pins that can function as the FS pin of a block
+///
+/// Users of this crate should not implement this trait.
+pub trait PinFs<SAI, BLOCK> {
+    /// Whether this is an actual pin
+    const CONNECTED: bool = true;
+}
+
+/// Implemented for all pins that can function as the SD pin of a block
+///
+/// Users of this crate should not implement this trait.
+pub trait PinSd<SAI, BLOCK> {}
+
+/// Placeholder for a pin when no master clock output is required
+pub struct NoMclk;
+impl<SAI, BLOCK> PinMclk<SAI, BLOCK> for NoMclk {
+    const OUTPUT: bool = false;
+}
+
+/// Placeholder for the SCK pin of a synchronous slave or an SPDIF output
+pub struct NoSck;
+impl<SAI, BLOCK> PinSck<SAI, BLOCK> for NoSck {
+    const CONNECTED: bool = false;
+}
+
+/// Placeholder for the FS pin of a synchronous slave or an SPDIF output
+pub struct NoFs;
+impl<SAI, BLOCK> PinFs<SAI, BLOCK> for NoFs {
+    const CONNECTED: bool = false;
+}
+
+macro_rules! pins {
+    ($(
+        $SAI:ident, $BLOCK:ident:
+            MCLK: [$($MCLK:ty),*]
+            SCK: [$($SCK:ty),*]
+            FS: [$($FS:ty),*]
+            SD: [$($SD:ty),*]
+    )+) => {
+        $(
+            $(
+                impl PinMclk<pac::$SAI, $BLOCK> for $MCLK {}
+            )*
+            $(
+                impl PinSck<pac::$SAI, $BLOCK> for $SCK {}
+            )*
+            $(
+                impl PinFs<pac::$SAI, $BLOCK> for $FS {}
+            )*
+            $(
+                impl PinSd<pac::$SAI, $BLOCK> for $SD {}
+            )*
+        )+
+    }
+}
+
+pins!(
+    SAI1, A:
+        MCLK: [gpio::PE2<Alternate<6>>, gpio::PG7<Alternate<6>>]
+        SCK: [gpio::PE5<Alternate<6>>]
+        FS: [gpio::PE4<Alternate<6>>]
+        SD: [
+            gpio::PD6<Alternate<6>>,
+            gpio::PE6<Alternate<6>>
+        ]
+    SAI1, B:
+        MCLK: [gpio::PF7<Alternate<6>>]
+        SCK: [gpio::PF8<Alternate<6>>]
+        FS: [gpio::PF9<Alternate<6>>]
+        SD: [
+            gpio::PE3<Alternate<6>>,
+            gpio::PF6<Alternate<6>>
+        ]
+    SAI2, A:
+        MCLK: [gpio::PE0<Alternate<10>>, gpio::PI4<Alternate<10>>]
+        SCK: [gpio::PD13<Alternate<10>>, gpio::PI5<Alternate<10>>]
+        FS: [gpio::PD12<Alternate<10>>, gpio::PI7<Alternate<10>>]
+        SD: [gpio::PD11<Alternate<10>>, gpio::PI6<Alternate<10>>]
+    SAI2, B:
+        MCLK: [
+            gpio::PA1<Alternate<10>>,
+            gpio::PE6<Alternate<10>>,
+            gpio::PE14<Alternate<10>>,
+            gpio::PH3<Alternate<10>>
+        ]
+        SCK: [
+            gpio::PA2<Alternate<8>>,
+            gpio::PE12<Alternate<10>>,
+            gpio::PH2<Alternate<10>>
+        ]
+        FS: [
+            gpio::PA12<Alternate<8>>,
+            gpio::PC0<Alternate<8>>,
+            gpio::PE13<Alternate<10>>,
+            gpio::PG9<Alternate<10>>
+        ]
+        SD: [
+            gpio::PA0<Alternate<10>>,
+            gpio::PE11<Alternate<10>>,
+            gpio::PF11<Alternate<10>>,
+            gpio::PG10<Alternate<10>>
+        ]
+);
+
+/// Implemented for all tuples of audio block pins
+///
+/// The tuple is `(MCLK, SCK, FS, SD)`. MCLK can be [`NoMclk`], and SCK and FS
+/// can be [`NoSck`] and [`NoFs`] for a synchronous slave or an SPDIF output.
+pub trait Pins<SAI, BLOCK> {
+    /// Whether the master clock is output
+    const MCLK: bool;
+    /// Whether both SCK and FS are connected
+    const CLOCKS: bool;
+}
+
+impl<SAI, BLOCK, MCLK, SCK, FS, SD> Pins<SAI, BLOCK> for (MCLK, SCK, FS, SD)
+where
+    MCLK: PinMclk<SAI, BLOCK>,
+    SCK: PinSck<SAI, BLOCK>,
+    FS: PinFs<SAI, BLOCK>,
+    SD: PinSd<SAI, BLOCK>,
+{
+    const MCLK: bool = MCLK::OUTPUT;
+    const CLOCKS: bool = SCK::CONNECTED && FS::CONNECTED;
+}
+
+/// A configured audio block
+pub struct Block<SAI, BLOCK, PINS> {
+    block: SubBlock<SAI, BLOCK>,
+    pins: PINS,
+    sample_rate: Option<Hertz>,
+}
+
+impl<SAI, BLOCK, PINS> Block<SAI, BLOCK, PINS>
+where
+    SAI: Instance,
+    BLOCK: AudioBlock,
+    PINS: Pins<SAI, BLOCK>,
+{
+    /// Configures an audio block
+    ///
+    /// A master outputs the master clock, at 256 times the sample rate, if
+    /// `pins` contains an MCLK pin. The block is enabled once the first
+    /// transfer starts. A synchronous slave must be enabled before the master
+    /// block it depends on.
+    ///
+    /// Panics if the configuration is invalid, or if the sample rate of a
+    /// master can't be generated from the SAI clock.
+    pub fn new(block: SubBlock<SAI, BLOCK>, pins: PINS, config: Config, clocks: Clocks) -> Self {
+        let master = config.mode.is_master();
+        assert!(master || !PINS::MCLK);
+        assert!(!config.synchronous || !master);
+        assert!(PINS::CLOCKS || config.synchronous || config.protocol == Protocol::Spdif);
+        check(&config, PINS::MCLK);
+
+        let (mckdiv, sample_rate) = if master {
+            let sai_clock = clocks.kernel_clock::<SAI>().raw();
+            let cycles = cycles_per_frame(&config, PINS::MCLK);
+            let mckdiv = mckdiv(sai_clock, config.sample_rate.raw(), cycles)
+                .expect("SAI sample rate can't be generated");
+
+            (
+                mckdiv,
+                Some(Hertz::from_raw(sample_rate(sai_clock, mckdiv, cycles))),
+            )
+        } else {
+            (0, None)
+        };
+
+        block.set_enabled(false);
+        block.write(CR2, CR2_FTH_HALF | CR2_FFLUSH);
+        block.write(FRCR, frcr(&config));
+        block.write(SLOTR, slotr(&config));
+        block.write(CLRFR, CLRFR_ALL);
+        block.write(CR1, cr1(&config, PINS::MCLK, mckdiv));
+
+        Block {
+            block,
+            pins,
+            sample_rate,
+        }
+    }
+}
+
+impl<SAI, BLOCK, PINS> Block<SAI, BLOCK, PINS>
+where
+    SAI: Instance,
+    BLOCK: AudioBlock,
+{
+    /// Returns the sample rate achieved by a master
+    ///
+    /// This is the requested rate, rounded to the nearest one that can be
+    /// divided from the SAI clock.
+    pub fn sample_rate(&self) -> Option<Hertz> {
+        self.sample_rate
+    }
+
+    /// Enables the block
+    ///
+    /// A master starts generating the clocks.
+    pub fn enable(&mut self) {
+        self.block.set_enabled(true);
+    }
+
+    /// Disables the block, at the end of the current frame
+    pub fn disable(&mut self) {
+        self.block.set_enabled(false);
+    }
+
+    /// Checks and clears the error flags
+    fn check_errors(&self) -> Result<u32, Error> {
+        let sr = self.block.read(SR);
+
+        let (flag, error) = if sr & SR_OVRUDR != 0 {
+            let error = match self.block.read(CR1) & CR1_MODE_RECEIVE {
+                0 => Error::Underrun,
+                _ => Error::Overrun,
+            };
+            (SR_OVRUDR, error)
+        } else if sr & SR_AFSDET != 0 {
+            (SR_AFSDET, Error::AnticipatedFrameSync)
+        } else if sr & SR_LFSDET != 0 {
+            (SR_LFSDET, Error::LateFrameSync)
+        } else if sr & SR_CNRDY != 0 {
+            (SR_CNRDY, Error::CodecNotReady)
+        } else {
+            return Ok(sr);
+        };
+
+        // The bits of CLRFR match the flags in SR
+        self.block.write(CLRFR, flag);
+        Err(error)
+    }
+
+    /// Reads a received sample
+    pub fn read(&mut self) -> nb::Result<u32, Error> {
+        let sr = self.check_errors()?;
+
+        if sr & SR_FLVL != FLVL_EMPTY {
+            return Ok(self.block.read(DR));
+        }
+
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Sends a sample
+    pub fn send(&mut self, sample: u32) -> nb::Result<(), Error> {
+        let sr = self.check_errors()?;
+
+        if sr & SR_FLVL != FLVL_FULL {
+            self.block.write(DR, sample);
+            return Ok(());
+        }
+
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Receives samples continuously using circular DMA
+    ///
+    /// See [`dma::CircTransfer`] for how to access the buffer halves. The
+    /// buffer must have an even length of at most 65535 words.
+    pub fn read_circular<Buf, S>(
+        self,
+        buffer: Pin<Buf>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Stream<SAI, BLOCK, PINS, S, Buf, dma::Ready>
+    where
+        RxTx<SAI, BLOCK>: dma::Target<S>,
+        S: dma::Stream,
+        Buf: DerefMut + 'static,
+        Buf::Target: AsMutSlice<Element = u32>,
+    {
+        let address = self.block.address(DR) as u32;
+
+        // Safe, because the trait bounds on this method guarantee that
+        // `buffer` can be written to safely. The token is only created while
+        // `self` is moved into the stream.
+        let transfer = unsafe {
+            dma::CircTransfer::new(
+                dma,
+                stream,
+                buffer,
+                RxTx(PhantomData),
+                address,
+                dma::Direction::PeripheralToMemory,
+            )
+        };
+
+        Stream {
+            block: self,
+            transfer,
+        }
+    }
+
+    /// Sends samples continuously using circular DMA
+    ///
+    /// See [`dma::CircTransfer`] for how to refill the buffer halves. The
+    /// buffer must have an even length of at most 65535 words.
+    pub fn write_circular<Buf, S>(
+        self,
+        buffer: Pin<Buf>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Stream<SAI, BLOCK, PINS, S, Buf, dma::Ready>
+    where
+        RxTx<SAI, BLOCK>: dma::Target<S>,
+        S: dma::Stream,
+        Buf: Deref + 'static,
+        Buf::Target: AsSlice<Element = u32>,
+    {
+        let address = self.block.address(DR) as u32;
+
+        // Safe, because the trait bounds on this method guarantee that
+        // `buffer` can be read from safely. The token is only created while
+        // `self` is moved into the stream.
+        let transfer = unsafe {
+            dma::CircTransfer::new(
+                dma,
+                stream,
+                buffer,
+                RxTx(PhantomData),
+                address,
+                dma::Direction::MemoryToPeripheral,
+            )
+        };
+
+        Stream {
+            block: self,
+            transfer,
+        }
+    }
+
+    /// Disables the block and releases it and the pins
+    pub fn free(self) -> (SubBlock<SAI, BLOCK>, PINS) {
+        self.block.set_enabled(false);
+        (self.block, self.pins)
+    }
+}
+
+/// A circular DMA stream of an audio block
+///
+/// Wraps the underlying [`dma::CircTransfer`], and enables the block for the
+/// duration of the stream.
+pub struct Stream<SAI, BLOCK, PINS, S: dma::Stream, Buf, State> {
+    block: Block<SAI, BLOCK, PINS>,
+    transfer: dma::CircTransfer<RxTx<SAI, BLOCK>, S, Buf, State>,
+}
+
+impl<SAI, BLOCK, PINS, S, Buf> Stream<SAI, BLOCK, PINS, S, Buf, dma::Ready>
+where
+    SAI: Instance,
+    BLOCK: AudioBlock,
+    RxTx<SAI, BLOCK>: dma::Target<S>,
+    S: dma::Stream,
+    Buf: 'static,
+{
+    /// Enables the given interrupts for the DMA stream
+    pub fn enable_interrupts(
+        &mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+        interrupts: dma::Interrupts,
+    ) {
+        self.transfer.enable_interrupts(handle, interrupts);
+    }
+
+    /// Starts the DMA stream, then enables the block
+    pub fn start(
+        mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> Stream<SAI, BLOCK, PINS, S, Buf, dma::Started> {
+        let transfer = self.transfer.start(handle);
+        self.block.enable();
+
+        Stream {
+            block: self.block,
+            transfer,
+        }
+    }
+}
+
+impl<SAI, BLOCK, PINS, S, Buf> Stream<SAI, BLOCK, PINS, S, Buf, dma::Started>
+where
+    SAI: Instance,
+    BLOCK: AudioBlock,
+    S: dma::Stream,
+{
+    /// Returns the half of the buffer that the DMA stream is done with, if any
+    ///
+    /// See [`dma::CircTransfer::readable_half`].
+    pub fn readable_half(
+        &self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> Result<Option<dma::Half>, dma::Error> {
+        self.transfer.readable_half(handle)
+    }
+
+    /// Gives access to the received half of the buffer
+    ///
+    /// See [`dma::CircTransfer::peek`].
+    pub fn peek<R, F>(
+        &mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+        f: F,
+    ) -> Result<Option<R>, dma::Error>
+    where
+        Buf: Deref,
+        Buf::Target: AsSlice<Element = u32>,
+        F: FnOnce(&[u32], dma::Half) -> R,
+    {
+        self.transfer.peek(handle, f)
+    }
+
+    /// Gives access to the sent half of the buffer, to refill it
+    ///
+    /// See [`dma::CircTransfer::peek_mut`].
+    pub fn peek_mut<R, F>(
+        &mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+        f: F,
+    ) -> Result<Option<R>, dma::Error>
+    where
+        Buf: DerefMut,
+        Buf::Target: AsMutSlice<Element = u32>,
+        F: FnOnce(&mut [u32], dma::Half) -> R,
+    {
+        self.transfer.peek_mut(handle, f)
+    }
+
+    /// Disables the block, stops the DMA stream and returns the resources it
+    /// used
+    pub fn stop(
+        mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> StreamResources<SAI, BLOCK, PINS, S, Buf> {
+        self.block.disable();
+        let res = self.transfer.stop(handle);
+
+        StreamResources {
+            stream: res.stream,
+            block: self.block,
+            buffer: res.buffer,
+        }
+    }
+}
+
+/// The resources that a [`Stream`] needs exclusive access to
+pub struct StreamResources<SAI, BLOCK, PINS, S, Buf> {
+    pub stream: S,
+    pub block: Block<SAI, BLOCK, PINS>,
+    pub buffer: Pin<Buf>,
+}
+
+// Offsets of the block registers, from the block's CR1
+const CR1: usize = 0x00;
+const CR2: usize = 0x04;
+const FRCR: usize = 0x08;
+const SLOTR: usize = 0x0c;
+const SR: usize = 0x14;
+const CLRFR: usize = 0x18;
+const DR: usize = 0x1c;
+
+const CR1_MODE_RECEIVE: u32 = 1 << 0;
+const CR1_SAIEN: u32 = 1 << 16;
+const CR2_FTH_HALF: u32 = 0b010;
+const CR2_FFLUSH: u32 = 1 << 3;
+const SR_OVRUDR: u32 = 1 << 0;
+const SR_CNRDY: u32 = 1 << 4;
+const SR_AFSDET: u32 = 1 << 5;
+const SR_LFSDET: u32 = 1 << 6;
+const SR_FLVL: u32 = 0b111 << 16;
+const FLVL_EMPTY: u32 = 0b000 << 16;
+const FLVL_FULL: u32 = 0b101 << 16;
+const CLRFR_ALL: u32 = 0x77;
+
+/// Returns the number of slots in a frame
+fn slot_count(config: &Config) -> u32 {
+    match config.protocol {
+        Protocol::Tdm { slots } => u32::from(slots),
+        Protocol::Ac97 => 13,
+        Protocol::Spdif => 0,
+        _ => 2,
+    }
+}
+
+/// Returns the size of a slot in bits
+fn slot_bits(config: &Config) -> u32 {
+    match config.slot_size {
+        SlotSize::DataSize => config.data_size.bits(),
+        SlotSize::Bits16 => 16,
+        SlotSize::Bits32 => 32,
+    }
+}
+
+/// Returns the length of a frame in bit clock cycles
+fn frame_bits(config: &Config) -> u32 {
+    match config.protocol {
+        Protocol::Ac97 => 256,
+        _ => slot_count(config) * slot_bits(config),
+    }
+}
+
+/// Panics if the frame described by `config` can't be generated
+fn check(config: &Config, master_clock: bool) {
+    match config.protocol {
+        Protocol::Spdif => {
+            assert!(config.mode == Mode::MasterTransmit && !master_clock);
+            return;
+        }
+        Protocol::Ac97 => assert!(matches!(
+            config.data_size,
+            DataSize::Bits16 | DataSize::Bits20
+        )),
+        Protocol::Tdm { slots } => assert!((1..=16).contains(&slots)),
+        _ => {}
+    }
+
+    let slots = slot_count(config);
+    assert!(config.slots != 0 && u32::from(config.slots) >> slots == 0);
+    assert!(slot_bits(config) >= config.data_size.bits());
+
+    let frame_bits = frame_bits(config);
+    assert!((8..=256).contains(&frame_bits));
+    // With the master clock output, the bit clock is divided from it
+    assert!(!master_clock || frame_bits.is_power_of_two());
+}
+
+/// Returns the CR1 value for `config`, with the block disabled
+fn cr1(config: &Config, master_clock: bool, mckdiv: u32) -> u32 {
+    let mode = match config.mode {
+        Mode::MasterTransmit => 0b00,
+        Mode::MasterReceive => 0b01,
+        Mode::SlaveTransmit => 0b10,
+        Mode::SlaveReceive => 0b11,
+    };
+    let (prtcfg, ckstr) = match config.protocol {
+        Protocol::Spdif => (0b01, 0),
+        // AC'97 sends on the rising edge, and samples on the falling edge
+        Protocol::Ac97 => (0b10, 0),
+        // Send on the falling edge, and sample on the rising edge
+        _ => (0b00, 1),
+    };
+    let ds = match config.data_size {
+        DataSize::Bits8 => 0b010,
+        DataSize::Bits10 => 0b011,
+        DataSize::Bits16 => 0b100,
+        DataSize::Bits20 => 0b101,
+        DataSize::Bits24 => 0b110,
+        DataSize::Bits32 => 0b111,
+    };
+    let syncen = config.synchronous as u32;
+    let nodiv = !master_clock as u32;
+
+    // DMAEN is always set, requests are ignored while no stream is set up
+    mckdiv << 20 | nodiv << 19 | 1 << 17 | syncen << 10 | ckstr << 9 | ds << 5 | prtcfg << 2 | mode
+}
+
+/// Returns the FRCR value for `config`
+fn frcr(config: &Config) -> u32 {
+    let frame_bits = frame_bits(config);
+    let (fsall, fsdef, fspol, fsoff) = match config.protocol {
+        // Frame synchronization low during the first slot, starting one bit
+        // early
+        Protocol::I2s => (frame_bits / 2 - 1, 1, 0, 1),
+        Protocol::MsbJustified | Protocol::LsbJustified => (frame_bits / 2 - 1, 1, 1, 0),
+        Protocol::Tdm { .. } => (0, 0, 1, 1),
+        // The frame is defined by the protocol, use the reset value
+        Protocol::Ac97 | Protocol::Spdif => return 0x0000_0007,
+    };
+
+    fsoff << 18 | fspol << 17 | fsdef << 16 | fsall << 8 | (frame_bits - 1)
+}
+
+/// Returns the SLOTR value for `config`
+fn slotr(config: &Config) -> u32 {
+    let (fboff, slotsz) = match config.protocol {
+        Protocol::Spdif => return 0,
+        Protocol::Ac97 => (0, 0b00),
+        _ => {
+            let fboff = match config.protocol {
+                Protocol::LsbJustified => slot_bits(config) - config.data_size.bits(),
+                _ => 0,
+            };
+            let slotsz = match config.slot_size {
+                SlotSize::DataSize => 0b00,
+                SlotSize::Bits16 => 0b01,
+                SlotSize::Bits32 => 0b10,
+            };
+            (fboff, slotsz)
+        }
+    };
+
+    u32::from(config.slots) << 16 | (slot_count(config) - 1) << 8 | slotsz << 6 | fboff
+}
+
+/// Returns the number of SAI clock cycles per frame, for MCKDIV = 0
+///
+/// With the master clock output, a frame always lasts 256 master clock cycles.
+/// SPDIF sends two channels of 32 biphase-encoded bits per frame. Otherwise,
+/// the bit clock is divided from the SAI clock directly.
+fn cycles_per_frame(config: &Config, master_clock: bool) -> u32 {
+    match (master_clock, config.protocol) {
+        (true, _) => 256,
+        (false, Protocol::Spdif) => 128,
+        (false, _) => frame_bits(config),
+    }
+}
+
+/// Returns the MCKDIV value nearest to generating `sample_rate`, if it is in
+/// range
+///
+/// MCKDIV divides the SAI clock by 1 if 0, and by `2 * MCKDIV` otherwise.
+fn mckdiv(sai_clock: u32, sample_rate: u32, cycles: u32) -> Option<u32> {
+    let step = u64::from(cycles) * u64::from(sample_rate);
+    let divider = (u64::from(sai_clock) + step / 2).checked_div(step)?;
+    if divider == 1 {
+        return Some(0);
+    }
+
+    let mckdiv = (u64::from(sai_clock) + step) / (2 * step);
+    if (1..=15).contains(&mckdiv) {
+        Some(mckdiv as u32)
+    } else {
+        None
+    }
+}
+
+/// Returns the sample rate generated with `mckdiv`, rounded to the nearest Hz
+fn sample_rate(sai_clock: u32, mckdiv: u32, cycles: u32) -> u32 {
+    let divider = if mckdiv == 0 { 1 } else { 2 * mckdiv };
+    let step = cycles * divider;
+    (sai_clock + step / 2) / step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registers() {
+        let config = Config::new(Mode::MasterTransmit);
+        check(&config, true);
+        assert_eq!(cr1(&config, true, 2), 0x0022_0280);
+        assert_eq!(frcr(&config), 0x0005_0f1f);
+        assert_eq!(slotr(&config), 0x0003_0100);
+
+        let config = Config {
+            mode: Mode::SlaveReceive,
+            protocol: Protocol::Tdm { slots: 8 },
+            data_size: DataSize::Bits24,
+            slot_size: SlotSize::Bits32,
+            slots: 0x00ff,
+            synchronous: true,
+            sample_rate: Hertz::from_raw(48_000),
+        };
+        check(&config, false);
+        assert_eq!(cr1(&config, false, 0), 0x000a_06c3);
+        assert_eq!(frcr(&config), 0x0006_00ff);
+        assert_eq!(slotr(&config), 0x00ff_0780);
+
+        let config = Config {
+            protocol: Protocol::LsbJustified,
+            slot_size: SlotSize::Bits32,
+            ..Config::new(Mode::MasterReceive)
+        };
+        check(&config, false);
+        assert_eq!(frcr(&config), 0x0003_1f3f);
+        assert_eq!(slotr(&config), 0x0003_0190);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_check_slots() {
+        let config = Config {
+            slots: 0b111,
+            ..Config::new(Mode::MasterTransmit)
+        };
+        check(&config, false);
+    }
+
+    #[test]
+    fn test_mckdiv() {
+        // PLLI2S Q output at 49.14 MHz, from a 1 MHz input with N = 344 and
+        // Q = 7
+        let config = Config::new(Mode::MasterTransmit);
+        let cycles = cycles_per_frame(&config, true);
+        assert_eq!(mckdiv(49_142_857, 48_000, cycles), Some(2));
+        assert_eq!(sample_rate(49_142_857, 2, cycles), 47_991);
+        assert_eq!(mckdiv(49_142_857, 192_000, cycles), Some(0));
+        assert_eq!(sample_rate(49_142_857, 0, cycles), 191_964);
+
+        // Without the master clock, the bit clock is divided directly
+        let cycles = cycles_per_frame(&config, false);
+        assert_eq!(cycles, 32);
+        assert_eq!(mckdiv(12_285_714, 48_000, cycles), Some(4));
+
+        let config = Config {
+            protocol: Protocol::Spdif,
+            data_size: DataSize::Bits24,
+            ..config
+        };
+        assert_eq!(
+            mckdiv(49_142_857, 48_000, cycles_per_frame(&config, false)),
+            Some(4)
+        );
+
+        // Out of range
+        assert_eq!(mckdiv(49_142_857, 1_000, 256), None);
+        assert_eq!(mckdiv(49_142_857, 0, 256), None);
+    }
+}
@@ -6,22 +6,33 @@ use core::pin::Pin;
 use core::ptr;
 
 use as_slice::{AsMutSlice, AsSlice};
+#[cfg(feature = "async")]
+use atomic_waker::AtomicWaker;
 
 use crate::dma;
+use crate::hal::blocking::spi::{transfer, write};
 use crate::hal::prelude::*;
 use crate::hal::serial;
+use crate::hal::spi::{self, Mode, Phase, Polarity};
 use crate::pac;
 use crate::rcc::{Enable, Reset};
 use crate::state;
 use nb::block;
 
-use crate::pac::{RCC, UART4, UART5, UART7, USART1, USART2, USART3, USART6};
+use crate::pac::{UART4, UART5, UART7, UART8, USART1, USART2, USART3, USART6};
 
-use crate::gpio::{self, Alternate};
+use crate::gpio::{self, Alternate, OpenDrain};
 
-use crate::rcc::Clocks;
+use crate::rcc::{Clocks, KernelClock};
 use crate::{BitsPerSecond, U32Ext};
 
+#[cfg(feature = "eh1")]
+mod hal_1;
+#[cfg(feature = "async")]
+mod hal_async;
+#[cfg(feature = "async")]
+pub use hal_async::on_interrupt;
+
 /// Serial error
 #[derive(Debug)]
 #[non_exhaustive]
@@ -36,9 +47,16 @@ pub enum Error {
     Parity,
 }
 
-pub trait Pins<USART> {}
+pub trait Pins<USART> {
+    /// Whether the pins include RTS and CTS for hardware flow control
+    const FLOW_CONTROL: bool = false;
+}
 pub trait PinTx<USART> {}
 pub trait PinRx<USART> {}
+pub trait PinRts<USART> {}
+pub trait PinCts<USART> {}
+pub trait PinDe<USART> {}
+pub trait PinCk<USART> {}
 
 impl<USART, TX, RX> Pins<USART> for (TX, RX)
 where
@@ -47,55 +65,66 @@ where
 {
 }
 
+impl<USART, TX, RX, RTS, CTS> Pins<USART> for (TX, RX, RTS, CTS)
+where
+    TX: PinTx<USART>,
+    RX: PinRx<USART>,
+    RTS: PinRts<USART>,
+    CTS: PinCts<USART>,
+{
+    const FLOW_CONTROL: bool = true;
+}
+
 mod f7xx_pins {
     //table 13 in stm32f765bg.pdf
     use super::{PinRx, PinTx};
     use crate::gpio::{self, Alternate};
     use crate::pac::{UART4, UART5, UART7, USART1};
-    impl PinTx<USART1> for gpio::PB14<Alternate<4>> {}
+    impl<Otype> PinTx<USART1> for gpio::PB14<Alternate<4, Otype>> {}
     impl PinRx<USART1> for gpio::PB15<Alternate<4>> {}
 
-    impl PinTx<UART4> for gpio::PA11<Alternate<6>> {}
+    impl<Otype> PinTx<UART4> for gpio::PA11<Alternate<6, Otype>> {}
     impl PinRx<UART4> for gpio::PA12<Alternate<6>> {}
 
-    impl PinTx<UART4> for gpio::PD1<Alternate<8>> {}
+    impl<Otype> PinTx<UART4> for gpio::PD1<Alternate<8, Otype>> {}
     impl PinRx<UART4> for gpio::PD0<Alternate<8>> {}
 
-    impl PinTx<UART4> for gpio::PH13<Alternate<8>> {}
+    impl<Otype> PinTx<UART4> for gpio::PH13<Alternate<8, Otype>> {}
     impl PinRx<UART4> for gpio::PH14<Alternate<8>> {}
 
     impl PinRx<UART4> for gpio::PI9<Alternate<8>> {}
 
-    impl PinTx<UART5> for gpio::PB6<Alternate<1>> {}
+    impl<Otype> PinTx<UART5> for gpio::PB6<Alternate<1, Otype>> {}
     impl PinRx<UART5> for gpio::PB5<Alternate<1>> {}
 
-    impl PinTx<UART5> for gpio::PB9<Alternate<7>> {}
+    impl<Otype> PinTx<UART5> for gpio::PB9<Alternate<7, Otype>> {}
     impl PinRx<UART5> for gpio::PB8<Alternate<7>> {}
 
-    impl PinTx<UART5> for gpio::PB13<Alternate<8>> {}
+    impl<Otype> PinTx<UART5> for gpio::PB13<Alternate<8, Otype>> {}
     impl PinRx<UART5> for gpio::PB12<Alternate<8>> {}
 
-    impl PinTx<UART7> for gpio::PA15<Alternate<12>> {}
+    impl<Otype> PinTx<UART7> for gpio::PA15<Alternate<12, Otype>> {}
     impl PinRx<UART7> for gpio::PA8<Alternate<12>> {}
 
-    impl PinTx<UART7> for gpio::PB4<Alternate<12>> {}
+    impl<Otype> PinTx<UART7> for gpio::PB4<Alternate<12, Otype>> {}
     impl PinRx<UART7> for gpio::PB3<Alternate<12>> {}
 }
 
-impl PinTx<USART1> for gpio::PA9<Alternate<7>> {}
-impl PinTx<USART1> for gpio::PB6<Alternate<7>> {}
-impl PinTx<USART2> for gpio::PA2<Alternate<7>> {}
-impl PinTx<USART2> for gpio::PD5<Alternate<7>> {}
-impl PinTx<USART3> for gpio::PB10<Alternate<7>> {}
-impl PinTx<USART3> for gpio::PC10<Alternate<7>> {}
-impl PinTx<USART3> for gpio::PD8<Alternate<7>> {}
-impl PinTx<UART4> for gpio::PA0<Alternate<8>> {}
-impl PinTx<UART4> for gpio::PC10<Alternate<8>> {}
-impl PinTx<UART5> for gpio::PC12<Alternate<8>> {}
-impl PinTx<USART6> for gpio::PC6<Alternate<8>> {}
-impl PinTx<USART6> for gpio::PG14<Alternate<8>> {}
-impl PinTx<UART7> for gpio::PE8<Alternate<8>> {}
-impl PinTx<UART7> for gpio::PF7<Alternate<8>> {}
+impl<Otype> PinTx<USART1> for gpio::PA9<Alternate<7, Otype>> {}
+impl<Otype> PinTx<USART1> for gpio::PB6<Alternate<7, Otype>> {}
+impl<Otype> PinTx<USART2> for gpio::PA2<Alternate<7, Otype>> {}
+impl<Otype> PinTx<USART2> for gpio::PD5<Alternate<7, Otype>> {}
+impl<Otype> PinTx<USART3> for gpio::PB10<Alternate<7, Otype>> {}
+impl<Otype> PinTx<USART3> for gpio::PC10<Alternate<7, Otype>> {}
+impl<Otype> PinTx<USART3> for gpio::PD8<Alternate<7, Otype>> {}
+impl<Otype> PinTx<UART4> for gpio::PA0<Alternate<8, Otype>> {}
+impl<Otype> PinTx<UART4> for gpio::PC10<Alternate<8, Otype>> {}
+impl<Otype> PinTx<UART5> for gpio::PC12<Alternate<8, Otype>> {}
+impl<Otype> PinTx<USART6> for gpio::PC6<Alternate<8, Otype>> {}
+impl<Otype> PinTx<USART6> for gpio::PG14<Alternate<8, Otype>> {}
+impl<Otype> PinTx<UART7> for gpio::PE8<Alternate<8, Otype>> {}
+impl<Otype> PinTx<UART7> for gpio::PF7<Alternate<8, Otype>> {}
+impl<Otype> PinTx<UART8> for gpio::PE1<Alternate<8, Otype>> {}
 
 impl PinRx<USART1> for gpio::PA10<Alternate<7>> {}
 impl PinRx<USART1> for gpio::PB7<Alternate<7>> {}
@@ -111,6 +140,39 @@ impl PinRx<USART6> for gpio::PC7<Alternate<8>> {}
 impl PinRx<USART6> for gpio::PG9<Alternate<8>> {}
 impl PinRx<UART7> for gpio::PE7<Alternate<8>> {}
 impl PinRx<UART7> for gpio::PF6<Alternate<8>> {}
+impl PinRx<UART8> for gpio::PE0<Alternate<8>> {}
+
+impl PinRts<USART1> for gpio::PA12<Alternate<7>> {}
+impl PinRts<USART2> for gpio::PA1<Alternate<7>> {}
+impl PinRts<USART2> for gpio::PD4<Alternate<7>> {}
+impl PinRts<USART3> for gpio::PB14<Alternate<7>> {}
+impl PinRts<USART3> for gpio::PD12<Alternate<7>> {}
+impl PinRts<USART6> for gpio::PG8<Alternate<8>> {}
+impl PinRts<USART6> for gpio::PG12<Alternate<8>> {}
+impl PinRts<UART7> for gpio::PE9<Alternate<8>> {}
+impl PinRts<UART7> for gpio::PF8<Alternate<8>> {}
+
+impl PinCts<USART1> for gpio::PA11<Alternate<7>> {}
+impl PinCts<USART2> for gpio::PA0<Alternate<7>> {}
+impl PinCts<USART2> for gpio::PD3<Alternate<7>> {}
+impl PinCts<USART3> for gpio::PB13<Alternate<7>> {}
+impl PinCts<USART3> for gpio::PD11<Alternate<7>> {}
+impl PinCts<USART6> for gpio::PG13<Alternate<8>> {}
+impl PinCts<USART6> for gpio::PG15<Alternate<8>> {}
+impl PinCts<UART7> for gpio::PE10<Alternate<8>> {}
+impl PinCts<UART7> for gpio::PF9<Alternate<8>> {}
+
+// The driver enable output replaces RTS
+impl<USART, PIN> PinDe<USART> for PIN where PIN: PinRts<USART> {}
+
+impl PinCk<USART1> for gpio::PA8<Alternate<7>> {}
+impl PinCk<USART2> for gpio::PA4<Alternate<7>> {}
+impl PinCk<USART2> for gpio::PD7<Alternate<7>> {}
+impl PinCk<USART3> for gpio::PB12<Alternate<7>> {}
+impl PinCk<USART3> for gpio::PC12<Alternate<7>> {}
+impl PinCk<USART3> for gpio::PD10<Alternate<7>> {}
+impl PinCk<USART6> for gpio::PC8<Alternate<8>> {}
+impl PinCk<USART6> for gpio::PG7<Alternate<8>> {}
 
 /// Serial abstraction
 pub struct Serial<USART, PINS> {
@@ -124,49 +186,328 @@ where
     USART: Instance,
 {
     pub fn new(usart: USART, pins: PINS, clocks: Clocks, config: Config) -> Self {
-        // NOTE(unsafe) This executes only during initialisation
-        let rcc = unsafe { &(*RCC::ptr()) };
+        configure(&usart, clocks, &config);
 
-        // TODO: The unsafe calls below should be replaced with accessing
-        //       the correct registers directly.
+        // Enable hardware flow control, if the pins allow it
+        usart.cr3.modify(|_, w| {
+            w.rtse()
+                .bit(PINS::FLOW_CONTROL)
+                .ctse()
+                .bit(PINS::FLOW_CONTROL)
+        });
 
-        USART::select_sysclock(rcc);
-        unsafe {
-            USART::enable_unchecked();
-        }
+        enable(&usart);
 
-        // Calculate correct baudrate divisor on the fly
-        let brr = match config.oversampling {
-            Oversampling::By8 => {
-                usart.cr1.modify(|_, w| w.over8().set_bit());
+        Serial { usart, pins }
+    }
+}
 
-                let usart_div = 2 * clocks.sysclk() / config.baud_rate;
+impl<USART, TX, RX, DE> Serial<USART, (TX, RX, DE)>
+where
+    TX: PinTx<USART>,
+    RX: PinRx<USART>,
+    DE: PinDe<USART>,
+    USART: Instance,
+{
+    /// Creates a USART driving the driver enable input of an RS-485
+    /// transceiver
+    ///
+    /// The DE pin is asserted by hardware while data is being sent.
+    pub fn rs485(
+        usart: USART,
+        pins: (TX, RX, DE),
+        clocks: Clocks,
+        config: Config,
+        driver_enable: DriverEnable,
+    ) -> Self {
+        assert!(driver_enable.assertion_time < 32 && driver_enable.deassertion_time < 32);
+
+        configure(&usart, clocks, &config);
+
+        usart.cr1.modify(|_, w| {
+            w.deat()
+                .bits(driver_enable.assertion_time)
+                .dedt()
+                .bits(driver_enable.deassertion_time)
+        });
+        usart
+            .cr3
+            .modify(|_, w| w.dem().set_bit().dep().bit(driver_enable.active_low));
 
-                0xfff0 & usart_div | 0x0007 & ((usart_div & 0x000f) >> 1)
-            }
-            Oversampling::By16 => {
-                usart.cr1.modify(|_, w| w.over8().clear_bit());
+        enable(&usart);
 
-                clocks.sysclk() / config.baud_rate
-            }
+        Serial { usart, pins }
+    }
+}
+
+impl<USART, TX, RX> Serial<USART, (TX, RX)>
+where
+    TX: PinTx<USART>,
+    RX: PinRx<USART>,
+    USART: Instance,
+{
+    /// Creates a LIN master or slave
+    ///
+    /// LIN frames use 8 data bits, no parity and 1 stop bit, regardless of
+    /// `config`. Use [`Serial::send_break`] to start a frame as master, and
+    /// [`Event::LinBreak`] to detect breaks.
+    pub fn lin(
+        usart: USART,
+        pins: (TX, RX),
+        clocks: Clocks,
+        config: Config,
+        break_length: LinBreakLength,
+    ) -> Self {
+        let config = Config {
+            data_bits: DataBits::Bits8(Parity::ParityNone),
+            stop_bits: StopBits::STOP1,
+            ..config
         };
+        configure(&usart, clocks, &config);
+
+        usart.cr2.modify(|_, w| {
+            w.linen()
+                .set_bit()
+                .lbdl()
+                .bit(break_length == LinBreakLength::Bits11)
+        });
 
-        usart.brr.write(|w| unsafe { w.bits(brr) });
+        enable(&usart);
 
-        // Set character match and reset other registers to disable advanced USART features
-        let ch = config.character_match.unwrap_or(0);
-        usart.cr2.write(|w| w.add().bits(ch));
+        Serial { usart, pins }
+    }
 
-        // Enable transmission and receiving
+    /// Creates an IrDA SIR encoder/decoder
+    ///
+    /// IrDA frames use 1 stop bit, regardless of `config`.
+    pub fn irda(
+        usart: USART,
+        pins: (TX, RX),
+        clocks: Clocks,
+        config: Config,
+        mode: IrdaMode,
+    ) -> Self {
+        let config = Config {
+            stop_bits: StopBits::STOP1,
+            ..config
+        };
+        configure(&usart, clocks, &config);
+
+        // In normal mode, the prescaler must be 1
+        let (low_power, prescaler) = match mode {
+            IrdaMode::Normal => (false, 1),
+            IrdaMode::LowPower { prescaler } => {
+                assert!(prescaler != 0);
+                (true, prescaler)
+            }
+        };
+        usart.gtpr.modify(|_, w| w.psc().bits(prescaler));
         usart
-            .cr1
-            .modify(|_, w| w.te().enabled().re().enabled().ue().enabled());
+            .cr3
+            .modify(|_, w| w.iren().set_bit().irlp().bit(low_power));
+
+        enable(&usart);
+
+        Serial { usart, pins }
+    }
+}
+
+impl<USART, const P: char, const N: u8, const A: u8>
+    Serial<USART, gpio::Pin<P, N, Alternate<A, OpenDrain>>>
+where
+    gpio::Pin<P, N, Alternate<A, OpenDrain>>: PinTx<USART>,
+    USART: Instance,
+{
+    /// Creates a half-duplex single-wire USART
+    ///
+    /// Sends and receives on the open-drain TX pin, which needs a pull-up.
+    /// The receiver also receives the data that is sent.
+    pub fn half_duplex(
+        usart: USART,
+        tx: gpio::Pin<P, N, Alternate<A, OpenDrain>>,
+        clocks: Clocks,
+        config: Config,
+    ) -> Self {
+        configure(&usart, clocks, &config);
+
+        usart.cr3.modify(|_, w| w.hdsel().set_bit());
+
+        enable(&usart);
+
+        Serial { usart, pins: tx }
+    }
+}
 
-        // Enable DMA
-        usart.cr3.write(|w| w.dmat().enabled().dmar().enabled());
+impl<USART, TX, RX, CK> Serial<USART, (TX, RX, CK)>
+where
+    TX: PinTx<USART>,
+    RX: PinRx<USART>,
+    CK: PinCk<USART>,
+    USART: UsartInstance,
+{
+    /// Creates a synchronous master, clocking the data on CK
+    ///
+    /// `mode` selects the clock polarity and phase, like for SPI. With
+    /// `last_bit_clock`, a clock pulse is output for the last data bit too,
+    /// which SPI devices require. In this mode, the USART implements
+    /// [`FullDuplex`](embedded_hal::spi::FullDuplex) and the blocking SPI
+    /// traits.
+    pub fn synchronous(
+        usart: USART,
+        pins: (TX, RX, CK),
+        clocks: Clocks,
+        config: Config,
+        mode: Mode,
+        last_bit_clock: bool,
+    ) -> Self {
+        configure(&usart, clocks, &config);
+
+        usart.cr2.modify(|_, w| {
+            w.clken()
+                .set_bit()
+                .cpol()
+                .bit(mode.polarity == Polarity::IdleHigh)
+                .cpha()
+                .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                .lbcl()
+                .bit(last_bit_clock)
+        });
+
+        enable(&usart);
 
         Serial { usart, pins }
     }
+}
+
+impl<USART, TX, RX, CK> spi::FullDuplex<u8> for Serial<USART, (TX, RX, CK)>
+where
+    CK: PinCk<USART>,
+    USART: UsartInstance,
+{
+    type Error = Error;
+
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        let mut rx: Rx<USART> = Rx {
+            _usart: PhantomData,
+        };
+        rx.read()
+    }
+
+    fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+        let mut tx: Tx<USART> = Tx {
+            _usart: PhantomData,
+        };
+        tx.write(byte)
+    }
+}
+
+impl<USART, TX, RX, CK> transfer::Default<u8> for Serial<USART, (TX, RX, CK)>
+where
+    CK: PinCk<USART>,
+    USART: UsartInstance,
+{
+}
+
+impl<USART, TX, RX, CK> write::Default<u8> for Serial<USART, (TX, RX, CK)>
+where
+    CK: PinCk<USART>,
+    USART: UsartInstance,
+{
+}
+
+impl<USART, TX, CK> Serial<USART, (TX, CK)>
+where
+    TX: PinTx<USART>,
+    CK: PinCk<USART>,
+    USART: UsartInstance,
+{
+    /// Creates an ISO 7816 smartcard interface
+    ///
+    /// The card's I/O line is connected to TX, which must be configured as
+    /// open-drain, and its clock to CK. Smartcard frames use 8 data bits, a
+    /// parity bit and 1.5 stop bits, so `config` must select 8 data bits and
+    /// a parity.
+    pub fn smartcard(
+        usart: USART,
+        pins: (TX, CK),
+        clocks: Clocks,
+        config: Config,
+        smartcard: SmartcardConfig,
+    ) -> Self {
+        assert!(matches!(
+            config.data_bits,
+            DataBits::Bits8(Parity::ParityEven | Parity::ParityOdd)
+        ));
+        assert!(smartcard.prescaler != 0 && smartcard.prescaler < 32);
+        assert!(smartcard.retries < 8);
+
+        let config = Config {
+            stop_bits: StopBits::STOP1P5,
+            ..config
+        };
+        configure(&usart, clocks, &config);
+
+        usart.gtpr.modify(|_, w| {
+            w.psc()
+                .bits(smartcard.prescaler)
+                .gt()
+                .bits(smartcard.guard_time)
+        });
+        usart.cr2.modify(|_, w| w.clken().set_bit());
+        usart.cr3.modify(|_, w| {
+            w.scen()
+                .set_bit()
+                .nack()
+                .bit(smartcard.nack)
+                .scarcnt()
+                .bits(smartcard.retries)
+        });
+
+        enable(&usart);
+
+        Serial { usart, pins }
+    }
+}
+
+/// Receiver wakeup from mute mode flag in ISR
+const ISR_RWU: u32 = 1 << 19;
+
+impl<USART, PINS> Serial<USART, PINS>
+where
+    USART: Instance,
+{
+    /// Mutes the receiver until the wakeup event selected by
+    /// [`Config::mute_mode`]
+    ///
+    /// Frames received while muted are ignored. In address mark mode, the
+    /// frame carrying the matching address wakes up the receiver, but is not
+    /// received itself.
+    pub fn enter_mute_mode(&mut self) {
+        self.usart.rqr.write(|w| w.mmrq().set_bit());
+    }
+
+    /// Checks whether the receiver is muted
+    pub fn is_muted(&self) -> bool {
+        // RWU is missing from the ISR of some PACs
+        self.usart.isr.read().bits() & ISR_RWU != 0
+    }
+
+    /// Sends a LIN break
+    ///
+    /// Only has an effect on a USART created with [`Serial::lin`].
+    pub fn send_break(&mut self) {
+        self.usart.rqr.write(|w| w.sbkrq().set_bit());
+    }
+
+    /// Checks whether a LIN break has been detected
+    pub fn is_break_detected(&self) -> bool {
+        self.usart.isr.read().lbdf().bit_is_set()
+    }
+
+    /// Clears the LIN break detection flag
+    pub fn clear_break_detected(&mut self) {
+        self.usart.icr.write(|w| w.lbdcf().clear());
+    }
 
     /// Starts listening for an interrupt event
     pub fn listen(&mut self, event: Event) {
@@ -174,6 +515,9 @@ where
             Event::Rxne => self.usart.cr1.modify(|_, w| w.rxneie().set_bit()),
             Event::Txe => self.usart.cr1.modify(|_, w| w.txeie().set_bit()),
             Event::CharacterMatch => self.usart.cr1.modify(|_, w| w.cmie().set_bit()),
+            Event::Idle => self.usart.cr1.modify(|_, w| w.idleie().set_bit()),
+            Event::ReceiverTimeout => self.usart.cr1.modify(|_, w| w.rtoie().set_bit()),
+            Event::LinBreak => self.usart.cr2.modify(|_, w| w.lbdie().set_bit()),
             Event::Error => self.usart.cr3.modify(|_, w| w.eie().set_bit()),
         }
     }
@@ -184,6 +528,9 @@ where
             Event::Rxne => self.usart.cr1.modify(|_, w| w.rxneie().clear_bit()),
             Event::Txe => self.usart.cr1.modify(|_, w| w.txeie().clear_bit()),
             Event::CharacterMatch => self.usart.cr1.modify(|_, w| w.cmie().clear_bit()),
+            Event::Idle => self.usart.cr1.modify(|_, w| w.idleie().clear_bit()),
+            Event::ReceiverTimeout => self.usart.cr1.modify(|_, w| w.rtoie().clear_bit()),
+            Event::LinBreak => self.usart.cr2.modify(|_, w| w.lbdie().clear_bit()),
             Event::Error => self.usart.cr3.modify(|_, w| w.eie().clear_bit()),
         }
     }
@@ -204,6 +551,110 @@ where
     }
 }
 
+/// Configures `usart` according to `config`, leaving it disabled
+fn configure<USART: Instance>(usart: &USART, clocks: Clocks, config: &Config) {
+    // The kernel clock is selected when the clocks are frozen
+    let kernel_clock = clocks.kernel_clock::<USART>();
+
+    unsafe {
+        USART::enable_unchecked();
+    }
+
+    // The mode and frame format can only be changed while the USART is
+    // disabled.
+    usart.cr1.modify(|_, w| w.ue().disabled());
+
+    // Calculate correct baudrate divisor on the fly
+    let brr = match config.oversampling {
+        Oversampling::By8 => {
+            usart.cr1.modify(|_, w| w.over8().set_bit());
+
+            let usart_div = 2 * kernel_clock / config.baud_rate;
+
+            0xfff0 & usart_div | 0x0007 & ((usart_div & 0x000f) >> 1)
+        }
+        Oversampling::By16 => {
+            usart.cr1.modify(|_, w| w.over8().clear_bit());
+
+            kernel_clock / config.baud_rate
+        }
+    };
+
+    usart.brr.write(|w| unsafe { w.bits(brr) });
+
+    // Configure the frame format. The word length includes the parity bit.
+    let parity = config.data_bits.parity();
+    let (m1, m0) = match config.data_bits.word_bits() {
+        7 => (true, false),
+        8 => (false, false),
+        _ => (false, true),
+    };
+    usart.cr1.modify(|_, w| {
+        w.m1()
+            .bit(m1)
+            .m0()
+            .bit(m0)
+            .pce()
+            .bit(parity != Parity::ParityNone)
+            .ps()
+            .bit(parity == Parity::ParityOdd)
+    });
+
+    // Configure mute mode. In address mark mode, the address replaces the
+    // character to match.
+    let (wake_address, address7, address) = match config.mute_mode {
+        None | Some(MuteWakeup::IdleLine) => (false, false, None),
+        Some(MuteWakeup::Address4(address)) => {
+            assert!(address < 1 << 4);
+            (true, false, Some(address))
+        }
+        Some(MuteWakeup::Address7(address)) => {
+            assert!(address < 1 << 7);
+            (true, true, Some(address))
+        }
+    };
+    assert!(
+        address.is_none() || config.character_match.is_none(),
+        "Character match is not available in address mark mode."
+    );
+    usart.cr1.modify(|_, w| {
+        w.mme()
+            .bit(config.mute_mode.is_some())
+            .wake()
+            .bit(wake_address)
+    });
+
+    // Set character match, frame format and pin options, and reset other
+    // registers to disable advanced USART features
+    let ch = address.or(config.character_match).unwrap_or(0);
+    usart.cr2.write(|w| {
+        w.add()
+            .bits(ch)
+            .addm7()
+            .bit(address7)
+            .stop()
+            .bits(config.stop_bits.bits())
+            .msbfirst()
+            .bit(config.msb_first)
+            .swap()
+            .bit(config.swap)
+            .txinv()
+            .bit(config.invert_tx)
+            .rxinv()
+            .bit(config.invert_rx)
+    });
+
+    // Enable DMA
+    usart.cr3.write(|w| w.dmat().enabled().dmar().enabled());
+}
+
+/// Enables `usart` with its transmitter and receiver
+fn enable<USART: Instance>(usart: &USART) {
+    usart
+        .cr1
+        .modify(|_, w| w.te().enabled().re().enabled().ue().enabled());
+}
+
 impl<USART, PINS> serial::Read<u8> for Serial<USART, PINS>
 where
     USART: Instance,
@@ -240,26 +691,63 @@ where
 }
 
 /// Serial receiver
-pub struct Rx<USART> {
-    _usart: PhantomData<USART>,
+///
+/// Receives `u8` words by default. Words of 9 data bits are received as `u16`
+/// after converting the receiver with [`Rx::with_u16_data`].
+pub struct Rx<USART, WORD = u8> {
+    _usart: PhantomData<(USART, WORD)>,
+}
+
+impl<USART> Rx<USART> {
+    /// Converts the receiver to receive `u16` words, for 9 data bits
+    pub fn with_u16_data(self) -> Rx<USART, u16> {
+        Rx {
+            _usart: PhantomData,
+        }
+    }
+}
+
+impl<USART> Rx<USART, u16> {
+    /// Converts the receiver back to receive `u8` words
+    pub fn with_u8_data(self) -> Rx<USART> {
+        Rx {
+            _usart: PhantomData,
+        }
+    }
 }
 
 impl<USART> Rx<USART>
 where
     USART: Instance,
-    Self: dma::Target,
 {
+    /// Mutes the receiver until the wakeup event selected by
+    /// [`Config::mute_mode`]
+    ///
+    /// See [`Serial::enter_mute_mode`].
+    pub fn enter_mute_mode(&mut self) {
+        // NOTE(unsafe): atomic write to a request register
+        unsafe { (*USART::ptr()).rqr.write(|w| w.mmrq().set_bit()) };
+    }
+
+    /// Checks whether the receiver is muted
+    pub fn is_muted(&self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*USART::ptr()).isr.read().bits() & ISR_RWU != 0 }
+    }
+
     /// Reads data using DMA until `buffer` is full
     ///
     /// DMA supports transfers up to 65535 bytes. If `buffer` is longer, this
     /// method will panic.
-    pub fn read_all<B>(
+    pub fn read_all<B, S>(
         self,
         buffer: Pin<B>,
-        dma: &dma::Handle<<Self as dma::Target>::Instance, state::Enabled>,
-        stream: <Self as dma::Target>::Stream,
-    ) -> dma::Transfer<Self, B, dma::Ready>
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> dma::Transfer<Self, S, B, dma::Ready>
     where
+        Self: dma::Target<S>,
+        S: dma::Stream,
         B: DerefMut + 'static,
         B::Target: AsMutSlice<Element = u8>,
     {
@@ -280,8 +768,133 @@ where
             )
         }
     }
+
+    /// Reads data continuously using DMA, in circular mode
+    ///
+    /// The stream fills `buffer` over and over again. Use
+    /// [`dma::CircTransfer::peek`] to access the half of it that was filled
+    /// last. `buffer` must have an even length of at most 65534 bytes.
+    pub fn read_circular<B, S>(
+        self,
+        buffer: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> dma::CircTransfer<Self, S, B, dma::Ready>
+    where
+        Self: dma::Target<S>,
+        S: dma::Stream,
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u8>,
+    {
+        // This is safe, as we're only using the USART instance to access the
+        // address of one register.
+        let address = &unsafe { &*USART::ptr() }.rdr as *const _ as _;
+
+        // Safe, because the trait bounds on this method guarantee that `buffer`
+        // can be written to safely.
+        unsafe {
+            dma::CircTransfer::new(
+                dma,
+                stream,
+                buffer,
+                self,
+                address,
+                dma::Direction::PeripheralToMemory,
+            )
+        }
+    }
+
+    /// Reads data continuously using DMA, in double-buffer mode
+    ///
+    /// The stream fills the two buffers in turn. Use
+    /// [`dma::DoubleBufferTransfer::peek`] to access the one that was filled
+    /// last. Both buffers must have the same length, of at most 65535 bytes.
+    pub fn read_double_buffer<B, S>(
+        self,
+        buffers: [Pin<B>; 2],
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> dma::DoubleBufferTransfer<Self, S, B, dma::Ready>
+    where
+        Self: dma::Target<S>,
+        S: dma::Stream,
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u8>,
+    {
+        // This is safe, as we're only using the USART instance to access the
+        // address of one register.
+        let address = &unsafe { &*USART::ptr() }.rdr as *const _ as _;
+
+        // Safe, because the trait bounds on this method guarantee that the
+        // buffers can be written to safely.
+        unsafe {
+            dma::DoubleBufferTransfer::new(
+                dma,
+                stream,
+                buffers,
+                self,
+                address,
+                dma::Direction::PeripheralToMemory,
+            )
+        }
+    }
+
+    /// Reads a variable-length frame using DMA
+    ///
+    /// The transfer ends when `buffer` is full, or earlier, when the end of the
+    /// frame is detected as defined by `end`. [`FrameTransfer::wait`] then
+    /// reports the number of bytes received.
+    ///
+    /// DMA supports transfers up to 65535 bytes. If `buffer` is longer, this
+    /// method will panic.
+    pub fn read_frame<B, S>(
+        self,
+        buffer: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+        end: FrameEnd,
+    ) -> FrameTransfer<USART, S, B, dma::Ready>
+    where
+        Self: dma::Target<S>,
+        S: dma::Stream,
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u8>,
+    {
+        let len = buffer.as_slice().len();
+
+        FrameTransfer {
+            transfer: self.read_all(buffer, dma, stream),
+            len,
+            end,
+        }
+    }
+
+    /// Reads data using DMA until `buffer` is full, waiting asynchronously
+    ///
+    /// This is the `async` version of [`Rx::read_all`]. The interrupt handler
+    /// of the DMA stream must call [`dma::on_interrupt`].
+    #[cfg(feature = "async")]
+    pub async fn read_all_async<B, S>(
+        self,
+        buffer: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Result<dma::TransferResources<Self, S, B>, (dma::TransferResources<Self, S, B>, dma::Error)>
+    where
+        Self: dma::Target<S>,
+        S: dma::Stream,
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = u8>,
+    {
+        let mut transfer = self.read_all(buffer, dma, stream);
+        transfer.enable_interrupts(dma, dma::Interrupts::ASYNC);
+
+        transfer.start(dma).wait_async(dma).await
+    }
 }
 
+/// Words of 9 data bits are truncated to their lower 8 bits. Use
+/// [`Rx::with_u16_data`] to receive them in full.
 impl<USART> serial::Read<u8> for Rx<USART>
 where
     USART: Instance,
@@ -289,63 +902,240 @@ where
     type Error = Error;
 
     fn read(&mut self) -> nb::Result<u8, Error> {
-        // NOTE(unsafe) atomic read with no side effects
-        let isr = unsafe { (*USART::ptr()).isr.read() };
+        read::<USART>().map(|word| word as u8)
+    }
+}
 
-        // NOTE(unsafe): Only used for atomic writes, to clear error flags.
-        let icr = unsafe { &(*USART::ptr()).icr };
+impl<USART> serial::Read<u16> for Rx<USART, u16>
+where
+    USART: Instance,
+{
+    type Error = Error;
 
-        if isr.pe().bit_is_set() {
-            icr.write(|w| w.pecf().clear());
-            return Err(nb::Error::Other(Error::Parity));
-        }
-        if isr.fe().bit_is_set() {
-            icr.write(|w| w.fecf().clear());
-            return Err(nb::Error::Other(Error::Framing));
+    fn read(&mut self) -> nb::Result<u16, Error> {
+        read::<USART>()
+    }
+}
+
+/// Reads a word, without its parity bit
+fn read<USART: Instance>() -> nb::Result<u16, Error> {
+    // NOTE(unsafe) atomic read with no side effects
+    let isr = unsafe { (*USART::ptr()).isr.read() };
+
+    // NOTE(unsafe): Only used for atomic writes, to clear error flags.
+    let icr = unsafe { &(*USART::ptr()).icr };
+
+    if isr.pe().bit_is_set() {
+        icr.write(|w| w.pecf().clear());
+        return Err(nb::Error::Other(Error::Parity));
+    }
+    if isr.fe().bit_is_set() {
+        icr.write(|w| w.fecf().clear());
+        return Err(nb::Error::Other(Error::Framing));
+    }
+    if isr.nf().bit_is_set() {
+        icr.write(|w| w.ncf().clear());
+        return Err(nb::Error::Other(Error::Noise));
+    }
+    if isr.ore().bit_is_set() {
+        icr.write(|w| w.orecf().clear());
+        return Err(nb::Error::Other(Error::Overrun));
+    }
+
+    if isr.rxne().bit_is_set() {
+        // NOTE(unsafe): Atomic reads with no side effects
+        let (cr1, rdr) = unsafe {
+            (
+                (*USART::ptr()).cr1.read(),
+                (*USART::ptr()).rdr.read().rdr().bits(),
+            )
+        };
+
+        // Strip the parity bit
+        let word_bits = match (cr1.m1().bit_is_set(), cr1.m0().bit_is_set()) {
+            (true, _) => 7,
+            (false, false) => 8,
+            (false, true) => 9,
+        };
+        return Ok(rdr & data_mask(word_bits, cr1.pce().bit_is_set()));
+    }
+
+    Err(nb::Error::WouldBlock)
+}
+
+/// Condition ending a frame received with [`Rx::read_frame`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameEnd {
+    /// The line is idle for the duration of one character
+    Idle,
+    /// The line is idle for the given number of bit durations, up to
+    /// 16777215 (receiver timeout)
+    ReceiverTimeout(u32),
+}
+
+/// A DMA reception of a variable-length frame
+///
+/// Returned by [`Rx::read_frame`]. Wraps the underlying [`dma::Transfer`] and
+/// ends it early when the frame is complete.
+pub struct FrameTransfer<USART, S: dma::Stream, B, State> {
+    transfer: dma::Transfer<Rx<USART>, S, B, State>,
+    len: usize,
+    end: FrameEnd,
+}
+
+impl<USART, S, B> FrameTransfer<USART, S, B, dma::Ready>
+where
+    USART: Instance,
+    Rx<USART>: dma::Target<S>,
+    S: dma::Stream,
+    B: 'static,
+{
+    /// Enables the given DMA interrupts for this transfer
+    ///
+    /// See [`dma::Transfer::enable_interrupts`].
+    pub fn enable_interrupts(
+        &mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+        interrupts: dma::Interrupts,
+    ) {
+        self.transfer.enable_interrupts(handle, interrupts);
+    }
+
+    /// Enables the USART interrupt signalling the end of the frame
+    ///
+    /// That's [`Event::Idle`] or [`Event::ReceiverTimeout`], depending on the
+    /// [`FrameEnd`] of the transfer. It is disabled again by
+    /// [`FrameTransfer::wait`].
+    pub fn listen(&mut self) {
+        // NOTE(unsafe): The receiver owns these interrupt enable bits.
+        let usart = unsafe { &*USART::ptr() };
+        match self.end {
+            FrameEnd::Idle => usart.cr1.modify(|_, w| w.idleie().set_bit()),
+            FrameEnd::ReceiverTimeout(_) => usart.cr1.modify(|_, w| w.rtoie().set_bit()),
         }
-        if isr.nf().bit_is_set() {
-            icr.write(|w| w.ncf().clear());
-            return Err(nb::Error::Other(Error::Noise));
+    }
+
+    /// Start the DMA transfer
+    pub fn start(
+        self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> FrameTransfer<USART, S, B, dma::Started> {
+        // NOTE(unsafe): The receiver owns the receiver timeout, and the flags
+        // are cleared with atomic writes.
+        let usart = unsafe { &*USART::ptr() };
+        if let FrameEnd::ReceiverTimeout(bits) = self.end {
+            assert!(bits < 1 << 24);
+            usart.rtor.modify(|_, w| w.rto().bits(bits));
+            usart.cr2.modify(|_, w| w.rtoen().set_bit());
         }
-        if isr.ore().bit_is_set() {
-            icr.write(|w| w.orecf().clear());
-            return Err(nb::Error::Other(Error::Overrun));
+        usart.icr.write(|w| w.idlecf().clear().rtocf().clear());
+
+        FrameTransfer {
+            transfer: self.transfer.start(handle),
+            len: self.len,
+            end: self.end,
         }
+    }
+}
+
+impl<USART, S, B> FrameTransfer<USART, S, B, dma::Started>
+where
+    USART: Instance,
+    S: dma::Stream,
+{
+    /// Checks whether the end of the frame has been detected
+    pub fn is_frame_end(&self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        let isr = unsafe { (*USART::ptr()).isr.read() };
 
-        if isr.rxne().bit_is_set() {
-            // NOTE(unsafe): Atomic read with no side effects
-            return Ok(unsafe {
-                // Casting to `u8` should be fine, as we've configured the USART
-                // to use 8 data bits.
-                (*USART::ptr()).rdr.read().rdr().bits() as u8
-            });
+        match self.end {
+            FrameEnd::Idle => isr.idle().bit_is_set(),
+            FrameEnd::ReceiverTimeout(_) => isr.rtof().bit_is_set(),
         }
+    }
 
-        Err(nb::Error::WouldBlock)
+    /// Checks whether the frame is still being received
+    pub fn is_active(&self, handle: &dma::Handle<S::Instance, state::Enabled>) -> bool {
+        self.transfer.is_active(handle) && !self.is_frame_end()
+    }
+
+    /// Waits for the end of the frame
+    ///
+    /// Blocks until the frame ends or the buffer is full, then returns the
+    /// transfer resources along with the number of bytes received.
+    pub fn wait(
+        self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> FrameResult<USART, S, B> {
+        while self.is_active(handle) {}
+
+        // Stop the stream, if the frame ended before the buffer was full
+        self.transfer.cancel(handle);
+        while self.transfer.is_active(handle) {}
+        let received = self.len - self.transfer.remaining(handle);
+
+        // NOTE(unsafe): The receiver owns these bits, and the flags are
+        // cleared with atomic writes.
+        let usart = unsafe { &*USART::ptr() };
+        usart
+            .cr1
+            .modify(|_, w| w.idleie().clear_bit().rtoie().clear_bit());
+        usart.cr2.modify(|_, w| w.rtoen().clear_bit());
+        usart.icr.write(|w| w.idlecf().clear().rtocf().clear());
+
+        self.transfer.wait(handle).map(|res| (res, received))
     }
 }
 
+/// Returned by [`FrameTransfer::wait`]
+pub type FrameResult<USART, S, B> = Result<
+    (dma::TransferResources<Rx<USART>, S, B>, usize),
+    (dma::TransferResources<Rx<USART>, S, B>, dma::Error),
+>;
+
 /// Serial transmitter
-pub struct Tx<USART> {
-    _usart: PhantomData<USART>,
+///
+/// Sends `u8` words by default. Words of 9 data bits are sent as `u16` after
+/// converting the transmitter with [`Tx::with_u16_data`].
+pub struct Tx<USART, WORD = u8> {
+    _usart: PhantomData<(USART, WORD)>,
+}
+
+impl<USART> Tx<USART> {
+    /// Converts the transmitter to send `u16` words, for 9 data bits
+    pub fn with_u16_data(self) -> Tx<USART, u16> {
+        Tx {
+            _usart: PhantomData,
+        }
+    }
+}
+
+impl<USART> Tx<USART, u16> {
+    /// Converts the transmitter back to send `u8` words
+    pub fn with_u8_data(self) -> Tx<USART> {
+        Tx {
+            _usart: PhantomData,
+        }
+    }
 }
 
 impl<USART> Tx<USART>
 where
-    Self: dma::Target,
     USART: Instance,
 {
     /// Writes data using DMA
     ///
     /// DMA supports transfers up to 65535 bytes. If `data` is longer, this
     /// method will panic.
-    pub fn write_all<B>(
+    pub fn write_all<B, S>(
         self,
         data: Pin<B>,
-        dma: &dma::Handle<<Self as dma::Target>::Instance, state::Enabled>,
-        stream: <Self as dma::Target>::Stream,
-    ) -> dma::Transfer<Self, B, dma::Ready>
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> dma::Transfer<Self, S, B, dma::Ready>
     where
+        Self: dma::Target<S>,
+        S: dma::Stream,
         B: Deref + 'static,
         B::Target: AsSlice<Element = u8>,
     {
@@ -369,6 +1159,30 @@ where
             )
         }
     }
+
+    /// Writes data using DMA, waiting asynchronously for the end of the
+    /// transfer
+    ///
+    /// This is the `async` version of [`Tx::write_all`]. The interrupt handler
+    /// of the DMA stream must call [`dma::on_interrupt`].
+    #[cfg(feature = "async")]
+    pub async fn write_all_async<B, S>(
+        self,
+        data: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> Result<dma::TransferResources<Self, S, B>, (dma::TransferResources<Self, S, B>, dma::Error)>
+    where
+        Self: dma::Target<S>,
+        S: dma::Stream,
+        B: Deref + 'static,
+        B::Target: AsSlice<Element = u8>,
+    {
+        let mut transfer = self.write_all(data, dma, stream);
+        transfer.enable_interrupts(dma, dma::Interrupts::ASYNC);
+
+        transfer.start(dma).wait_async(dma).await
+    }
 }
 
 impl<USART> serial::Write<u8> for Tx<USART>
@@ -403,11 +1217,190 @@ where
     }
 }
 
+impl<USART> serial::Write<u16> for Tx<USART, u16>
+where
+    USART: Instance,
+{
+    type Error = Error;
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        let mut tx: Tx<USART> = Tx {
+            _usart: PhantomData,
+        };
+        tx.flush()
+    }
+
+    fn write(&mut self, word: u16) -> nb::Result<(), Self::Error> {
+        // NOTE(unsafe) atomic read with no side effects
+        let isr = unsafe { (*USART::ptr()).isr.read() };
+
+        if isr.txe().bit_is_set() {
+            // NOTE(unsafe) atomic write to stateless register
+            unsafe { (*USART::ptr()).tdr.write(|w| w.tdr().bits(word)) }
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
 /// USART configuration
 pub struct Config {
     pub baud_rate: BitsPerSecond,
     pub oversampling: Oversampling,
     pub character_match: Option<u8>,
+    /// Number of data bits, and the parity bit following them
+    pub data_bits: DataBits,
+    pub stop_bits: StopBits,
+    /// Send and receive the most significant bit first
+    pub msb_first: bool,
+    /// Swap the functions of the TX and RX pins
+    pub swap: bool,
+    /// Invert the level of the TX pin
+    pub invert_tx: bool,
+    /// Invert the level of the RX pin
+    pub invert_rx: bool,
+    /// Enables mute mode, waking up the receiver as selected
+    pub mute_mode: Option<MuteWakeup>,
+}
+
+/// Number of data bits of a frame, and its parity
+///
+/// A word is at most 9 bits long, including the parity bit, so there is no
+/// parity with 9 data bits. Words of 9 data bits are exchanged as `u16`, see
+/// [`Rx::with_u16_data`] and [`Tx::with_u16_data`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataBits {
+    Bits7(Parity),
+    Bits8(Parity),
+    Bits9,
+}
+
+impl DataBits {
+    fn parity(self) -> Parity {
+        match self {
+            DataBits::Bits7(parity) | DataBits::Bits8(parity) => parity,
+            DataBits::Bits9 => Parity::ParityNone,
+        }
+    }
+
+    /// Returns the number of bits per word, including the parity bit
+    fn word_bits(self) -> u8 {
+        let data_bits = match self {
+            DataBits::Bits7(_) => 7,
+            DataBits::Bits8(_) => 8,
+            DataBits::Bits9 => 9,
+        };
+
+        match self.parity() {
+            Parity::ParityNone => data_bits,
+            _ => data_bits + 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+    ParityNone,
+    ParityEven,
+    ParityOdd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopBits {
+    /// 1 stop bit
+    STOP1,
+    /// 0.5 stop bits
+    STOP0P5,
+    /// 2 stop bits
+    STOP2,
+    /// 1.5 stop bits
+    STOP1P5,
+}
+
+impl StopBits {
+    fn bits(self) -> u8 {
+        match self {
+            StopBits::STOP1 => 0b00,
+            StopBits::STOP0P5 => 0b01,
+            StopBits::STOP2 => 0b10,
+            StopBits::STOP1P5 => 0b11,
+        }
+    }
+}
+
+/// Event waking up a receiver in mute mode
+///
+/// See [`Serial::enter_mute_mode`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MuteWakeup {
+    /// Wake up when the line is idle
+    IdleLine,
+    /// Wake up on an address mark (MSB set) matching the given 4-bit address
+    Address4(u8),
+    /// Wake up on an address mark (MSB set) matching the given 7-bit address
+    Address7(u8),
+}
+
+/// Driver enable configuration of [`Serial::rs485`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DriverEnable {
+    /// Whether the DE pin is active low
+    pub active_low: bool,
+    /// Time between the activation of DE and the start bit, in sample time
+    /// units (1/16 or 1/8 bit duration, depending on oversampling), up to 31
+    pub assertion_time: u8,
+    /// Time between the end of the last stop bit and the deactivation of DE,
+    /// in sample time units, up to 31
+    pub deassertion_time: u8,
+}
+
+impl Default for DriverEnable {
+    fn default() -> Self {
+        Self {
+            active_low: false,
+            assertion_time: 0,
+            deassertion_time: 0,
+        }
+    }
+}
+
+/// Length of the breaks detected in LIN mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinBreakLength {
+    Bits10,
+    Bits11,
+}
+
+/// IrDA SIR power mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrdaMode {
+    /// Pulses of 3/16 bit duration
+    Normal,
+    /// Pulses of 3 periods of the low-power clock, which is the USART kernel
+    /// clock divided by `prescaler`
+    LowPower { prescaler: u8 },
+}
+
+/// ISO 7816 configuration of [`Serial::smartcard`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SmartcardConfig {
+    /// Divides the USART kernel clock by twice this value, from 1 to 31, to
+    /// generate the card clock
+    pub prescaler: u8,
+    /// Guard time, in baud clock periods
+    pub guard_time: u8,
+    /// Send a NACK on parity errors
+    pub nack: bool,
+    /// Number of automatic retransmissions/receptions on errors, up to 7.
+    /// Zero disables retries.
+    pub retries: u8,
+}
+
+/// Returns the mask of the data bits in words of `word_bits` bits
+fn data_mask(word_bits: u8, parity: bool) -> u16 {
+    let data_bits = if parity { word_bits - 1 } else { word_bits };
+    (1 << data_bits) - 1
 }
 
 pub enum Oversampling {
@@ -421,6 +1414,13 @@ impl Default for Config {
             baud_rate: 115_200.bps(),
             oversampling: Oversampling::By16,
             character_match: None,
+            data_bits: DataBits::Bits8(Parity::ParityNone),
+            stop_bits: StopBits::STOP1,
+            msb_first: false,
+            swap: false,
+            invert_tx: false,
+            invert_rx: false,
+            mute_mode: None,
         }
     }
 }
@@ -434,19 +1434,41 @@ pub enum Event {
     Txe,
     /// Character match interrupt
     CharacterMatch,
+    /// The line went idle after receiving data
+    Idle,
+    /// The receiver timeout elapsed after the last received character
+    ReceiverTimeout,
+    /// A LIN break has been detected
+    LinBreak,
     /// Error interrupt
     Error,
 }
 
 /// Implemented by all USART instances
-pub trait Instance: Deref<Target = pac::usart1::RegisterBlock> + Enable + Reset {
+pub trait Instance:
+    Deref<Target = pac::usart1::RegisterBlock> + Enable + Reset + KernelClock
+{
     fn ptr() -> *const pac::usart1::RegisterBlock;
-    fn select_sysclock(rcc: &pac::rcc::RegisterBlock);
+    /// Wakers of the tasks waiting to receive and to send
+    #[cfg(feature = "async")]
+    fn wakers() -> &'static [AtomicWaker; 2];
+}
+
+/// Implemented by the USART instances, which, unlike the UARTs, have a clock
+/// output for the smartcard and synchronous modes
+pub trait UsartInstance: Instance {}
+
+macro_rules! impl_usart_instance {
+    ($($USARTX:ident,)+) => {
+        $(
+            impl UsartInstance for $USARTX {}
+        )+
+    }
 }
 
 macro_rules! impl_instance {
     ($(
-        $USARTX:ident: ($usartXsel:ident),
+        $USARTX:ident,
     )+) => {
         $(
             impl Instance for $USARTX {
@@ -454,8 +1476,10 @@ macro_rules! impl_instance {
                     $USARTX::ptr()
                 }
 
-                fn select_sysclock(rcc: &pac::rcc::RegisterBlock) {
-                    rcc.dckcfgr2.modify(|_, w| w.$usartXsel().bits(1));
+                #[cfg(feature = "async")]
+                fn wakers() -> &'static [AtomicWaker; 2] {
+                    static WAKERS: [AtomicWaker; 2] = [AtomicWaker::new(), AtomicWaker::new()];
+                    &WAKERS
                 }
             }
         )+
@@ -464,13 +1488,22 @@ macro_rules! impl_instance {
 
 #[cfg(any(feature = "device-selected",))]
 impl_instance! {
-    USART1: (usart1sel),
-    USART2: (usart2sel),
-    USART3: (usart3sel),
-    UART4:  (uart4sel),
-    UART5:  (uart5sel),
-    USART6: (usart6sel),
-    UART7:  (uart7sel),
+    USART1,
+    USART2,
+    USART3,
+    UART4,
+    UART5,
+    USART6,
+    UART7,
+    UART8,
+}
+
+#[cfg(any(feature = "device-selected",))]
+impl_usart_instance! {
+    USART1,
+    USART2,
+    USART3,
+    USART6,
 }
 
 impl<USART> fmt::Write for Tx<USART>
@@ -482,3 +1515,27 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{data_mask, DataBits, Parity};
+
+    #[test]
+    fn test_data_mask() {
+        assert_eq!(data_mask(7, false), 0x7f);
+        assert_eq!(data_mask(8, false), 0xff);
+        assert_eq!(data_mask(8, true), 0x7f);
+        assert_eq!(data_mask(9, false), 0x1ff);
+        assert_eq!(data_mask(9, true), 0xff);
+    }
+
+    #[test]
+    fn test_word_bits() {
+        assert_eq!(DataBits::Bits7(Parity::ParityNone).word_bits(), 7);
+        assert_eq!(DataBits::Bits7(Parity::ParityEven).word_bits(), 8);
+        assert_eq!(DataBits::Bits8(Parity::ParityNone).word_bits(), 8);
+        assert_eq!(DataBits::Bits8(Parity::ParityOdd).word_bits(), 9);
+        assert_eq!(DataBits::Bits9.word_bits(), 9);
+        assert_eq!(DataBits::Bits9.parity(), Parity::ParityNone);
+    }
+}
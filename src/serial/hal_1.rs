@@ -0,0 +1,185 @@
+use core::marker::PhantomData;
+use embedded_hal_nb::serial::ErrorKind;
+
+use nb::block;
+
+use super::{Error, Instance, Rx, Serial, Tx};
+
+impl embedded_hal_nb::serial::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Framing => ErrorKind::FrameFormat,
+            Error::Noise => ErrorKind::Noise,
+            Error::Overrun => ErrorKind::Overrun,
+            Error::Parity => ErrorKind::Parity,
+        }
+    }
+}
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::Other
+    }
+}
+
+// Implementations for `Rx`
+
+impl<USART> embedded_hal_nb::serial::ErrorType for Rx<USART> {
+    type Error = Error;
+}
+
+impl<USART> embedded_hal_nb::serial::Read<u8> for Rx<USART>
+where
+    USART: Instance,
+{
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        crate::hal::serial::Read::read(self)
+    }
+}
+
+impl<USART> embedded_io::ErrorType for Rx<USART> {
+    type Error = Error;
+}
+
+impl<USART> embedded_io::Read for Rx<USART>
+where
+    USART: Instance,
+{
+    /// Blocks until at least one byte is received, then returns the bytes
+    /// that have arrived so far
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        buf[0] = block!(crate::hal::serial::Read::read(self))?;
+
+        let mut n = 1;
+        for byte in &mut buf[1..] {
+            match crate::hal::serial::Read::read(self) {
+                Ok(b) => *byte = b,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+            n += 1;
+        }
+
+        Ok(n)
+    }
+}
+
+// Implementations for `Tx`
+
+impl<USART> embedded_hal_nb::serial::ErrorType for Tx<USART> {
+    type Error = Error;
+}
+
+impl<USART> embedded_hal_nb::serial::Write<u8> for Tx<USART>
+where
+    USART: Instance,
+{
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        crate::hal::serial::Write::write(self, byte)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        crate::hal::serial::Write::flush(self)
+    }
+}
+
+impl<USART> embedded_io::ErrorType for Tx<USART> {
+    type Error = Error;
+}
+
+impl<USART> embedded_io::Write for Tx<USART>
+where
+    USART: Instance,
+{
+    /// Blocks until at least one byte can be sent, then queues as many bytes
+    /// as the transmitter accepts
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        block!(crate::hal::serial::Write::write(self, buf[0]))?;
+
+        let mut n = 1;
+        for byte in &buf[1..] {
+            match crate::hal::serial::Write::write(self, *byte) {
+                Ok(()) => n += 1,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        block!(crate::hal::serial::Write::flush(self))
+    }
+}
+
+// Implementations for `Serial`
+
+impl<USART, PINS> embedded_hal_nb::serial::ErrorType for Serial<USART, PINS> {
+    type Error = Error;
+}
+
+impl<USART, PINS> embedded_hal_nb::serial::Read<u8> for Serial<USART, PINS>
+where
+    USART: Instance,
+{
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        crate::hal::serial::Read::read(self)
+    }
+}
+
+impl<USART, PINS> embedded_hal_nb::serial::Write<u8> for Serial<USART, PINS>
+where
+    USART: Instance,
+{
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        crate::hal::serial::Write::write(self, byte)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        crate::hal::serial::Write::flush(self)
+    }
+}
+
+impl<USART, PINS> embedded_io::ErrorType for Serial<USART, PINS> {
+    type Error = Error;
+}
+
+impl<USART, PINS> embedded_io::Read for Serial<USART, PINS>
+where
+    USART: Instance,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut rx: Rx<USART> = Rx {
+            _usart: PhantomData,
+        };
+        embedded_io::Read::read(&mut rx, buf)
+    }
+}
+
+impl<USART, PINS> embedded_io::Write for Serial<USART, PINS>
+where
+    USART: Instance,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let mut tx: Tx<USART> = Tx {
+            _usart: PhantomData,
+        };
+        embedded_io::Write::write(&mut tx, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        let mut tx: Tx<USART> = Tx {
+            _usart: PhantomData,
+        };
+        embedded_io::Write::flush(&mut tx)
+    }
+}
@@ -0,0 +1,196 @@
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use super::{Error, Instance, Rx, Serial, Tx};
+use crate::hal::serial;
+
+const RX: usize = 0;
+const TX: usize = 1;
+
+/// Wakes the tasks waiting on `USART`
+///
+/// Call this from the USART interrupt handler when using the `async` APIs.
+/// The interrupts that woke a task are disabled again until that task is
+/// polled, so the status flags don't need to be cleared here.
+pub fn on_interrupt<USART: Instance>() {
+    // NOTE(unsafe) atomic read with no side effects
+    let usart = unsafe { &*USART::ptr() };
+    let isr = usart.isr.read();
+    let cr1 = usart.cr1.read();
+
+    let rx = cr1.rxneie().bit_is_set()
+        && (isr.rxne().bit_is_set()
+            || isr.ore().bit_is_set()
+            || isr.pe().bit_is_set()
+            || isr.fe().bit_is_set()
+            || isr.nf().bit_is_set());
+    let tx = (cr1.txeie().bit_is_set() && isr.txe().bit_is_set())
+        || (cr1.tcie().bit_is_set() && isr.tc().bit_is_set());
+
+    usart.cr1.modify(|_, w| {
+        let w = if rx { w.rxneie().clear_bit() } else { w };
+        if tx {
+            w.txeie().clear_bit().tcie().clear_bit()
+        } else {
+            w
+        }
+    });
+
+    if rx {
+        USART::wakers()[RX].wake();
+    }
+    if tx {
+        USART::wakers()[TX].wake();
+    }
+}
+
+impl<USART> Rx<USART>
+where
+    USART: Instance,
+{
+    /// Waits for a byte, listening for RXNE in the meantime
+    async fn read_byte(&mut self) -> Result<u8, Error> {
+        poll_fn(|cx| {
+            USART::wakers()[RX].register(cx.waker());
+
+            match serial::Read::read(self) {
+                Ok(byte) => Poll::Ready(Ok(byte)),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    // NOTE(unsafe) atomic read-modify-write of an interrupt
+                    // enable bit that only this receiver controls
+                    unsafe { (*USART::ptr()).cr1.modify(|_, w| w.rxneie().set_bit()) };
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl<USART> embedded_io_async::Read for Rx<USART>
+where
+    USART: Instance,
+{
+    /// Waits until at least one byte is received, then returns the bytes
+    /// that have arrived so far
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        buf[0] = self.read_byte().await?;
+
+        let mut n = 1;
+        for byte in &mut buf[1..] {
+            match serial::Read::read(self) {
+                Ok(b) => *byte = b,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+            n += 1;
+        }
+
+        Ok(n)
+    }
+}
+
+impl<USART> Tx<USART>
+where
+    USART: Instance,
+{
+    /// Waits until `byte` can be queued, listening for TXE in the meantime
+    async fn write_byte(&mut self, byte: u8) -> Result<(), Error> {
+        poll_fn(|cx| {
+            USART::wakers()[TX].register(cx.waker());
+
+            match serial::Write::write(self, byte) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    // NOTE(unsafe) atomic read-modify-write of an interrupt
+                    // enable bit that only this transmitter controls
+                    unsafe { (*USART::ptr()).cr1.modify(|_, w| w.txeie().set_bit()) };
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl<USART> embedded_io_async::Write for Tx<USART>
+where
+    USART: Instance,
+{
+    /// Waits until at least one byte can be sent, then queues as many bytes
+    /// as the transmitter accepts
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.write_byte(buf[0]).await?;
+
+        let mut n = 1;
+        for byte in &buf[1..] {
+            match serial::Write::write(self, *byte) {
+                Ok(()) => n += 1,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+
+        Ok(n)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        poll_fn(|cx| {
+            USART::wakers()[TX].register(cx.waker());
+
+            match serial::Write::flush(self) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    // NOTE(unsafe) atomic read-modify-write of an interrupt
+                    // enable bit that only this transmitter controls
+                    unsafe { (*USART::ptr()).cr1.modify(|_, w| w.tcie().set_bit()) };
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl<USART, PINS> embedded_io_async::Read for Serial<USART, PINS>
+where
+    USART: Instance,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut rx: Rx<USART> = Rx {
+            _usart: PhantomData,
+        };
+        embedded_io_async::Read::read(&mut rx, buf).await
+    }
+}
+
+impl<USART, PINS> embedded_io_async::Write for Serial<USART, PINS>
+where
+    USART: Instance,
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let mut tx: Tx<USART> = Tx {
+            _usart: PhantomData,
+        };
+        embedded_io_async::Write::write(&mut tx, buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        let mut tx: Tx<USART> = Tx {
+            _usart: PhantomData,
+        };
+        embedded_io_async::Write::flush(&mut tx).await
+    }
+}
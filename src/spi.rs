@@ -5,9 +5,17 @@
 pub use crate::pac::spi1::cr1::BR_A as ClockDivider;
 pub use embedded_hal::spi::{Mode, Phase, Polarity};
 
-use core::{fmt, marker::PhantomData, ops::DerefMut, pin::Pin, ptr};
+use core::{
+    fmt,
+    marker::PhantomData,
+    ops::{DerefMut, RangeInclusive},
+    pin::Pin,
+    ptr,
+};
 
 use as_slice::{AsMutSlice, AsSlice as _};
+#[cfg(feature = "async")]
+use atomic_waker::AtomicWaker;
 use embedded_hal::{
     blocking::spi::{transfer, write, write_iter},
     spi::FullDuplex,
@@ -22,6 +30,18 @@ use crate::{
 
 use crate::dma;
 
+#[cfg(feature = "eh1")]
+mod hal_1;
+#[cfg(feature = "async")]
+mod hal_async;
+mod half_duplex;
+#[cfg(feature = "async")]
+pub use hal_async::on_interrupt;
+pub use half_duplex::{
+    HalfDuplex, HalfDuplexPins, ReceiveOnly, ReceiveOnlyPins, SimplexResources, SimplexTransfer,
+    SimplexWaitResult,
+};
+
 /// Entry point to the SPI API
 pub struct Spi<I, P, State> {
     spi: I,
@@ -32,9 +52,12 @@ pub struct Spi<I, P, State> {
 impl<I, P> Spi<I, P, state::Disabled>
 where
     I: Instance + Enable,
-    P: Pins<I>,
 {
     /// Create a new instance of the SPI API
+    ///
+    /// `pins` is a tuple of the pins used by the mode that is enabled next:
+    /// [`Pins`] for a full-duplex master or slave, [`HalfDuplexPins`] or
+    /// [`ReceiveOnlyPins`].
     pub fn new(instance: I, pins: P) -> Self {
         Self {
             spi: instance,
@@ -42,7 +65,13 @@ where
             _state: state::Disabled,
         }
     }
+}
 
+impl<I, P> Spi<I, P, state::Disabled>
+where
+    I: Instance + Enable,
+    P: Pins<I>,
+{
     /// Initialize the SPI peripheral
     pub fn enable<Word>(
         self,
@@ -53,11 +82,82 @@ where
     where
         Word: SupportedWordSize,
     {
+        self.enable_with_config(apb, clock_divider, mode, Config::default())
+    }
+
+    /// Initialize the SPI peripheral with a data size, CRC or NSS pulses
+    ///
+    /// If the pins include an NSS pin, it is driven by the hardware: low while
+    /// the peripheral is enabled, and between frames too unless
+    /// `config.nss_pulse` is set.
+    pub fn enable_with_config<Word>(
+        self,
+        apb: &mut <I as RccBus>::Bus,
+        clock_divider: ClockDivider,
+        mode: Mode,
+        config: Config,
+    ) -> Spi<I, P, Enabled<Word>>
+    where
+        Word: SupportedWordSize,
+    {
+        // NSS pulses are only generated by the hardware NSS output, and only
+        // make sense if data is captured on the first clock edge
+        assert!(!config.nss_pulse || (P::NSS && mode.phase == Phase::CaptureOnFirstTransition));
+
         I::enable(apb);
-        let cpol = mode.polarity == Polarity::IdleHigh;
-        let cpha = mode.phase == Phase::CaptureOnSecondTransition;
 
-        self.spi.configure::<Word>(clock_divider.into(), cpol, cpha);
+        self.spi.configure::<Word>(
+            clock_divider.into(),
+            FrameFormat::Motorola(mode),
+            true,
+            P::NSS,
+            &config,
+            Lines::FullDuplex,
+        );
+
+        Spi {
+            spi: self.spi,
+            pins: self.pins,
+            _state: Enabled(PhantomData),
+        }
+    }
+}
+
+impl<I, P> Spi<I, P, state::Disabled>
+where
+    I: Instance + Enable,
+    P: NssPins<I>,
+{
+    /// Initialize the SPI peripheral in slave mode
+    ///
+    /// The clock is provided by an external master, and the slave is selected
+    /// by driving the hardware NSS pin low. In TI mode, NSS is the frame
+    /// synchronization input instead.
+    ///
+    /// The returned `Spi` supports the same blocking, non-blocking and DMA
+    /// APIs as a master. A word must be sent before the master clocks the
+    /// matching frame: if a frame has been received that hasn't been read yet
+    /// when sending, the master outran the slave and [`Error::Underrun`] is
+    /// returned.
+    ///
+    /// NSS pulses are generated by the master, so `config.nss_pulse` must not
+    /// be set.
+    pub fn enable_slave<Word>(
+        self,
+        apb: &mut <I as RccBus>::Bus,
+        frame_format: FrameFormat,
+        config: Config,
+    ) -> Spi<I, P, Enabled<Word>>
+    where
+        Word: SupportedWordSize,
+    {
+        assert!(!config.nss_pulse);
+
+        I::enable(apb);
+
+        // The baud rate is set by the master
+        self.spi
+            .configure::<Word>(0, frame_format, false, true, &config, Lines::FullDuplex);
 
         Spi {
             spi: self.spi,
@@ -93,20 +193,30 @@ where
     /// would be nice to simplify that, but I believe that requires an equality
     /// constraint in the where clause, which is not supported yet by the
     /// compiler.
-    pub fn transfer_all<B>(
+    ///
+    /// If the hardware CRC is enabled, its calculation is reset before the
+    /// transfer. The CRC is sent after the last word, and the received CRC is
+    /// checked when waiting for the transfer.
+    pub fn transfer_all<B, RxStream, TxStream>(
         self,
         buffer: Pin<B>,
-        dma_rx: &dma::Handle<<Rx<I> as dma::Target>::Instance, state::Enabled>,
-        dma_tx: &dma::Handle<<Tx<I> as dma::Target>::Instance, state::Enabled>,
-        rx: <Rx<I> as dma::Target>::Stream,
-        tx: <Tx<I> as dma::Target>::Stream,
-    ) -> Transfer<Word, I, P, B, Rx<I>, Tx<I>, dma::Ready>
+        dma_rx: &dma::Handle<RxStream::Instance, state::Enabled>,
+        dma_tx: &dma::Handle<TxStream::Instance, state::Enabled>,
+        rx: RxStream,
+        tx: TxStream,
+    ) -> Transfer<Word, I, P, B, RxStream, TxStream, dma::Ready>
     where
-        Rx<I>: dma::Target,
-        Tx<I>: dma::Target,
+        Rx<I>: dma::Target<RxStream>,
+        Tx<I>: dma::Target<TxStream>,
+        RxStream: dma::Stream,
+        TxStream: dma::Stream,
         B: DerefMut + 'static,
         B::Target: AsMutSlice<Element = Word>,
     {
+        if self.spi.is_crc_enabled() {
+            self.spi.reset_crc();
+        }
+
         // Create the RX/TX tokens for the transfer. Those must only exist once,
         // otherwise it would be possible to create multiple transfers trying to
         // use the same hardware resources.
@@ -172,6 +282,80 @@ where
     }
 }
 
+impl<I, P, Word> Spi<I, P, Enabled<Word>>
+where
+    I: Instance,
+    P: Pins<I>,
+    Word: SupportedWordSize,
+{
+    /// Transmits the CRC after the word that is being sent
+    ///
+    /// Must be called right after sending the last data word of a
+    /// transaction. The CRC is then sent as one more frame, and the CRC
+    /// received at the same time is placed in the receive FIFO, where it must
+    /// be read like a data word. A mismatch is reported as [`Error::Crc`].
+    ///
+    /// Full-duplex DMA transfers send and check the CRC automatically, see
+    /// [`Spi::transfer_all`].
+    pub fn send_crc(&mut self) {
+        self.spi.send_crc();
+    }
+
+    /// Resets the CRC calculation
+    ///
+    /// Must not be called during a transaction.
+    pub fn reset_crc(&mut self) {
+        self.spi.reset_crc();
+    }
+
+    /// Returns the CRC calculated over the received frames
+    pub fn rx_crc(&self) -> u16 {
+        self.spi.rx_crc()
+    }
+
+    /// Returns the CRC calculated over the transmitted frames
+    pub fn tx_crc(&self) -> u16 {
+        self.spi.tx_crc()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<I, P, Word> Spi<I, P, Enabled<Word>>
+where
+    I: Instance,
+    P: Pins<I>,
+    Word: SupportedWordSize,
+{
+    /// Performs an SPI transfer using DMA, waiting asynchronously for its end
+    ///
+    /// This is the `async` version of [`Spi::transfer_all`]. The interrupt
+    /// handlers of both DMA streams must call [`dma::on_interrupt`].
+    pub async fn transfer_all_async<B, RxStream, TxStream>(
+        self,
+        buffer: Pin<B>,
+        dma_rx: &dma::Handle<RxStream::Instance, state::Enabled>,
+        dma_tx: &dma::Handle<TxStream::Instance, state::Enabled>,
+        rx: RxStream,
+        tx: TxStream,
+    ) -> WaitResult<Word, I, P, RxStream, TxStream, B>
+    where
+        Rx<I>: dma::Target<RxStream>,
+        Tx<I>: dma::Target<TxStream>,
+        RxStream: dma::Stream,
+        TxStream: dma::Stream,
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = Word>,
+    {
+        let mut transfer = self.transfer_all(buffer, dma_rx, dma_tx, rx, tx);
+        transfer.enable_interrupts(dma_rx, dma_tx, dma::Interrupts::ASYNC);
+
+        transfer
+            .start(dma_rx, dma_tx)
+            .wait_async(dma_rx, dma_tx)
+            .await
+    }
+}
+
 impl<I, P, Word> FullDuplex<Word> for Spi<I, P, Enabled<Word>>
 where
     I: Instance,
@@ -216,7 +400,6 @@ where
 impl<I, P, State> Spi<I, P, State>
 where
     I: Instance,
-    P: Pins<I>,
 {
     /// Destroy the peripheral API and return a raw SPI peripheral instance
     pub fn free(self) -> (I, P) {
@@ -228,8 +411,15 @@ where
 ///
 /// Users of this crate should not implement this trait.
 pub trait Instance {
-    fn configure<Word>(&self, br: u8, cpol: bool, cpha: bool)
-    where
+    fn configure<Word>(
+        &self,
+        br: u8,
+        frame_format: FrameFormat,
+        master: bool,
+        hardware_nss: bool,
+        config: &Config,
+        lines: Lines,
+    ) where
         Word: SupportedWordSize;
     fn read<Word>(&self) -> nb::Result<Word, Error>
     where
@@ -238,10 +428,40 @@ pub trait Instance {
     where
         Word: SupportedWordSize;
     fn dr_address(&self) -> u32;
+    /// Transmits the CRC after the current frame
+    fn send_crc(&self);
+    /// Resets the CRC calculation
+    fn reset_crc(&self);
+    fn rx_crc(&self) -> u16;
+    fn tx_crc(&self) -> u16;
+    /// Checks whether the hardware CRC calculation is enabled
+    fn is_crc_enabled(&self) -> bool;
+    /// Checks and clears the CRC error flag
+    fn check_crc(&self) -> Result<(), Error>;
+    /// Enables or disables the peripheral
+    fn set_enabled(&self, enabled: bool);
+    /// Selects the direction of the data line in half-duplex mode
+    fn set_output(&self, output: bool);
+    /// Checks whether a frame is being transferred or waits to be sent
+    fn is_busy(&self) -> bool;
+    /// Discards the received frames and clears the overrun flag
+    fn flush(&self);
+    /// Waker of the task waiting for a received word
+    #[cfg(feature = "async")]
+    fn waker() -> &'static AtomicWaker;
+    /// Enables or disables the RX buffer not empty interrupt
+    #[cfg(feature = "async")]
+    fn listen_rxne(&self, enable: bool);
+    /// Disables the RX buffer not empty interrupt and wakes the waiting task
+    #[cfg(feature = "async")]
+    fn on_interrupt();
 }
 
 /// Implemented for all tuples that contain a full set of valid SPI pins
-pub trait Pins<I> {}
+pub trait Pins<I> {
+    /// Whether the hardware NSS pin is part of the set
+    const NSS: bool = false;
+}
 
 impl<I, SCK, MISO, MOSI> Pins<I> for (SCK, MISO, MOSI)
 where
@@ -251,6 +471,29 @@ where
 {
 }
 
+/// Implemented for all tuples that contain a full set of valid SPI pins,
+/// including the hardware NSS pin
+pub trait NssPins<I>: Pins<I> {}
+
+impl<I, SCK, MISO, MOSI, NSS> Pins<I> for (SCK, MISO, MOSI, NSS)
+where
+    SCK: Sck<I>,
+    MISO: Miso<I>,
+    MOSI: Mosi<I>,
+    NSS: Nss<I>,
+{
+    const NSS: bool = true;
+}
+
+impl<I, SCK, MISO, MOSI, NSS> NssPins<I> for (SCK, MISO, MOSI, NSS)
+where
+    SCK: Sck<I>,
+    MISO: Miso<I>,
+    MOSI: Mosi<I>,
+    NSS: Nss<I>,
+{
+}
+
 /// Implemented for all pins that can function as the SCK pin
 ///
 /// Users of this crate should not implement this trait.
@@ -266,6 +509,11 @@ pub trait Miso<I> {}
 /// Users of this crate should not implement this trait.
 pub trait Mosi<I> {}
 
+/// Implemented for all pins that can function as the NSS pin
+///
+/// Users of this crate should not implement this trait.
+pub trait Nss<I> {}
+
 macro_rules! impl_instance {
     (
         $(
@@ -275,6 +523,7 @@ macro_rules! impl_instance {
                     SCK: [$($sck:ty,)*],
                     MISO: [$($miso:ty,)*],
                     MOSI: [$($mosi:ty,)*],
+                    NSS: [$($nss:ty,)*],
                 }
             }
         )*
@@ -292,15 +541,35 @@ macro_rules! impl_instance {
                 // Maybe this is a problem in the SVD file that can be fixed
                 // there.
 
-                fn configure<Word>(&self, br: u8, cpol: bool, cpha: bool)
+                fn configure<Word>(
+                    &self,
+                    br: u8,
+                    frame_format: FrameFormat,
+                    master: bool,
+                    hardware_nss: bool,
+                    config: &Config,
+                    lines: Lines,
+                )
                     where Word: SupportedWordSize
                 {
+                    let data_bits = data_bits::<Word>(config);
+                    let (ti, cpol, cpha) = match frame_format {
+                        FrameFormat::Motorola(mode) => (
+                            false,
+                            mode.polarity == Polarity::IdleHigh,
+                            mode.phase == Phase::CaptureOnSecondTransition,
+                        ),
+                        // Clock polarity and phase are fixed by the TI
+                        // protocol
+                        FrameFormat::Ti => (true, false, false),
+                    };
+
                     self.cr2.write(|w| {
                         // Data size
                         //
-                        // This is safe, as `Word::ds` returns an enum which can
-                        // only encode valid variants for this field.
-                        let w = unsafe { w.ds().bits(Word::ds().into()) };
+                        // This is safe, as `data_bits` checks that the size is
+                        // supported by the peripheral.
+                        let w = unsafe { w.ds().bits(data_bits - 1) };
 
                         w
                             // FIFO reception threshold.
@@ -312,40 +581,65 @@ macro_rules! impl_instance {
                             // Disable error interrupt
                             .errie().masked()
                             // Frame format
-                            .frf().motorola()
+                            .frf().bit(ti)
                             // NSS pulse management
-                            .nssp().no_pulse()
+                            .nssp().bit(config.nss_pulse)
                             // SS output
-                            .ssoe().disabled()
+                            .ssoe().bit(master && hardware_nss)
                             // Enable DMA support
                             .txdmaen().enabled()
                             .rxdmaen().enabled()
                     });
 
+                    // The CRC can only be configured while the peripheral is
+                    // disabled
+                    self.cr1.write(|w| w.spe().disabled());
+
+                    let (crc, crc16) = match config.crc {
+                        None => (false, false),
+                        Some(Crc::Crc8(polynomial)) => {
+                            self.crcpr.write(|w| w.crcpoly().bits(polynomial.into()));
+                            (true, false)
+                        }
+                        Some(Crc::Crc16(polynomial)) => {
+                            self.crcpr.write(|w| w.crcpoly().bits(polynomial));
+                            (true, true)
+                        }
+                    };
+
                     self.cr1.write(|w|
                         w
-                            // Use two lines for MISO/MOSI
-                            .bidimode().unidirectional()
-                            // Disable hardware CRC calculation
-                            .crcen().disabled()
-                            // Enable full-duplex mode
-                            .rxonly().full_duplex()
-                            // Manage slave select pin manually
-                            .ssm().enabled()
-                            .ssi().set_bit()
+                            // Use two lines for MISO/MOSI, or a single
+                            // bidirectional line starting as an output
+                            .bidimode().bit(lines == Lines::HalfDuplex)
+                            .bidioe().set_bit()
+                            // Hardware CRC calculation
+                            .crcen().bit(crc)
+                            .crcl().bit(crc16)
+                            // Full-duplex or receive-only mode
+                            .rxonly().bit(lines == Lines::ReceiveOnly)
+                            // Manage slave select pin manually, unless the
+                            // hardware NSS pin is used
+                            .ssm().bit(master && !hardware_nss)
+                            .ssi().bit(master)
                             // Transmit most significant bit first
                             .lsbfirst().msbfirst()
                             // Set baud rate value
                             .br().bits(br)
-                            // Select master mode
-                            .mstr().master()
+                            // Select master or slave mode
+                            .mstr().bit(master)
                             // Select clock polarity
                             .cpol().bit(cpol)
                             // Select clock phase
                             .cpha().bit(cpha)
-                            // Enable SPI
-                            .spe().enabled()
                     );
+
+                    // Enable SPI. In half-duplex and receive-only modes, this
+                    // starts the clock when receiving, so the peripheral is
+                    // only enabled during transfers.
+                    if lines == Lines::FullDuplex {
+                        self.cr1.modify(|_, w| w.spe().enabled());
+                    }
                 }
 
                 fn read<Word>(&self) -> nb::Result<Word, Error> {
@@ -366,6 +660,10 @@ macro_rules! impl_instance {
                     if sr.modf().is_fault() {
                         return Err(nb::Error::Other(Error::ModeFault));
                     }
+                    if sr.crcerr().bit_is_set() {
+                        self.sr.write(|w| w.crcerr().clear_bit());
+                        return Err(nb::Error::Other(Error::Crc));
+                    }
 
                     // Did we receive something?
                     if sr.rxne().is_not_empty() {
@@ -403,6 +701,17 @@ macro_rules! impl_instance {
                     if sr.modf().is_fault() {
                         return Err(nb::Error::Other(Error::ModeFault));
                     }
+                    if sr.crcerr().bit_is_set() {
+                        self.sr.write(|w| w.crcerr().clear_bit());
+                        return Err(nb::Error::Other(Error::Crc));
+                    }
+
+                    // A slave must send each word before the master clocks
+                    // its frame. A received frame that hasn't been read yet
+                    // means that the master was faster.
+                    if sr.rxne().is_not_empty() && self.cr1.read().mstr().is_slave() {
+                        return Err(nb::Error::Other(Error::Underrun));
+                    }
 
                     // Can we write to the transmit buffer?
                     if sr.txe().is_empty() {
@@ -426,6 +735,87 @@ macro_rules! impl_instance {
                 fn dr_address(&self) -> u32 {
                     &self.dr as *const _ as _
                 }
+
+                fn send_crc(&self) {
+                    self.cr1.modify(|_, w| w.crcnext().set_bit());
+                }
+
+                fn reset_crc(&self) {
+                    // The CRC registers are cleared by disabling the CRC
+                    // calculation, which requires the peripheral to be disabled
+                    let crcen = self.cr1.read().crcen().bit();
+                    self.cr1.modify(|_, w| w.spe().disabled());
+                    self.cr1.modify(|_, w| w.crcen().clear_bit());
+                    self.cr1.modify(|_, w| w.crcen().bit(crcen));
+                    self.cr1.modify(|_, w| w.spe().enabled());
+                }
+
+                fn rx_crc(&self) -> u16 {
+                    self.rxcrcr.read().rx_crc().bits()
+                }
+
+                fn tx_crc(&self) -> u16 {
+                    self.txcrcr.read().tx_crc().bits()
+                }
+
+                fn is_crc_enabled(&self) -> bool {
+                    self.cr1.read().crcen().bit_is_set()
+                }
+
+                fn check_crc(&self) -> Result<(), Error> {
+                    if self.sr.read().crcerr().bit_is_set() {
+                        self.sr.write(|w| w.crcerr().clear_bit());
+                        return Err(Error::Crc);
+                    }
+
+                    Ok(())
+                }
+
+                fn set_enabled(&self, enabled: bool) {
+                    self.cr1.modify(|_, w| w.spe().bit(enabled));
+                }
+
+                fn set_output(&self, output: bool) {
+                    self.cr1.modify(|_, w| w.bidioe().bit(output));
+                }
+
+                fn is_busy(&self) -> bool {
+                    let sr = self.sr.read();
+                    sr.ftlvl().bits() != 0 || sr.bsy().bit_is_set()
+                }
+
+                fn flush(&self) {
+                    while self.sr.read().frlvl().bits() != 0 {
+                        // Reading a byte pops one byte from the FIFO. This is
+                        // safe, as `&self.dr` is a memory-mapped register.
+                        unsafe {
+                            ptr::read_volatile(&self.dr as *const _ as *const u8);
+                        }
+                    }
+
+                    // The overrun flag is cleared by reading the data register,
+                    // then the status register
+                    self.sr.read();
+                }
+
+                #[cfg(feature = "async")]
+                fn waker() -> &'static AtomicWaker {
+                    static WAKER: AtomicWaker = AtomicWaker::new();
+                    &WAKER
+                }
+
+                #[cfg(feature = "async")]
+                fn listen_rxne(&self, enable: bool) {
+                    self.cr2.modify(|_, w| w.rxneie().bit(enable));
+                }
+
+                #[cfg(feature = "async")]
+                fn on_interrupt() {
+                    // NOTE(unsafe) atomic read-modify-write of an interrupt
+                    // enable bit only set by the waiting task
+                    unsafe { (*<$name>::ptr()).cr2.modify(|_, w| w.rxneie().clear_bit()) };
+                    Self::waker().wake();
+                }
             }
 
             $(
@@ -439,6 +829,10 @@ macro_rules! impl_instance {
             $(
                 impl Mosi<$name> for $mosi {}
             )*
+
+            $(
+                impl Nss<$name> for $nss {}
+            )*
         )*
     }
 }
@@ -462,6 +856,11 @@ impl_instance!(
                 gpio::PB5<Alternate<5>>,
                 gpio::PD7<Alternate<5>>,
             ],
+            NSS: [
+                gpio::PA4<Alternate<5>>,
+                gpio::PA15<Alternate<5>>,
+                gpio::PG10<Alternate<5>>,
+            ],
         }
     }
     pac::SPI2 {
@@ -485,6 +884,11 @@ impl_instance!(
                 gpio::PC3<Alternate<5>>,
                 gpio::PI3<Alternate<5>>,
             ],
+            NSS: [
+                gpio::PB9<Alternate<5>>,
+                gpio::PB12<Alternate<5>>,
+                gpio::PI0<Alternate<5>>,
+            ],
         }
     }
     pac::SPI3 {
@@ -504,6 +908,10 @@ impl_instance!(
                 gpio::PC12<Alternate<6>>,
                 gpio::PD6<Alternate<5>>,
             ],
+            NSS: [
+                gpio::PA4<Alternate<6>>,
+                gpio::PA15<Alternate<6>>,
+            ],
         }
     }
     pac::SPI4 {
@@ -521,6 +929,10 @@ impl_instance!(
                 gpio::PE6<Alternate<5>>,
                 gpio::PE14<Alternate<5>>,
             ],
+            NSS: [
+                gpio::PE4<Alternate<5>>,
+                gpio::PE11<Alternate<5>>,
+            ],
         }
     }
     pac::SPI5 {
@@ -538,6 +950,10 @@ impl_instance!(
                 gpio::PF9<Alternate<5>>,
                 gpio::PF11<Alternate<5>>,
             ],
+            NSS: [
+                gpio::PF6<Alternate<5>>,
+                gpio::PH5<Alternate<5>>,
+            ],
         }
     }
 );
@@ -566,6 +982,9 @@ impl_instance!(
             MOSI: [
                 gpio::PG14<Alternate<5>>,
             ],
+            NSS: [
+                gpio::PG8<Alternate<5>>,
+            ],
         }
     }
 );
@@ -582,37 +1001,139 @@ impl<I> Miso<I> for NoMiso {}
 pub struct NoMosi;
 impl<I> Mosi<I> for NoMosi {}
 
+/// Configuration of the SPI peripheral
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Number of bits per frame
+    ///
+    /// Frames of 4 to 8 bits are transferred as `u8` words, frames of 9 to 16
+    /// bits as `u16` words. `None` selects the size of the word.
+    pub data_bits: Option<u8>,
+    /// Hardware CRC calculation and checking
+    pub crc: Option<Crc>,
+    /// Pulse the hardware NSS output between frames
+    ///
+    /// Only available to a master with an NSS pin, using the Motorola frame
+    /// format with data captured on the first clock edge.
+    pub nss_pulse: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            data_bits: None,
+            crc: None,
+            nss_pulse: false,
+        }
+    }
+}
+
+/// CRC length and polynomial
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Crc {
+    /// 8-bit CRC, e.g. `Crc8(0x07)` for CRC-8
+    Crc8(u8),
+    /// 16-bit CRC, e.g. `Crc16(0x1021)` for CRC-16-CCITT
+    Crc16(u16),
+}
+
+/// Returns the number of bits per frame, checking it against the word size
+fn data_bits<Word: SupportedWordSize>(config: &Config) -> u8 {
+    let range = Word::data_bits();
+    let data_bits = config.data_bits.unwrap_or(*range.end());
+    assert!(range.contains(&data_bits));
+    data_bits
+}
+
+/// Data lines used by the SPI peripheral
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lines {
+    /// Separate MISO and MOSI lines
+    FullDuplex,
+    /// A single bidirectional data line, MOSI for a master
+    HalfDuplex,
+    /// The MISO line only, as a master
+    ReceiveOnly,
+}
+
+/// Frame format of the SPI peripheral
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// Motorola SPI, with the given clock polarity and phase
+    Motorola(Mode),
+    /// TI synchronous serial protocol, framed by a pulse on NSS
+    Ti,
+}
+
+// `Mode` doesn't implement `Debug`, so this can't be derived.
+impl fmt::Debug for FrameFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameFormat::Motorola(mode) => {
+                let polarity = match mode.polarity {
+                    Polarity::IdleLow => "IdleLow",
+                    Polarity::IdleHigh => "IdleHigh",
+                };
+                let phase = match mode.phase {
+                    Phase::CaptureOnFirstTransition => "CaptureOnFirstTransition",
+                    Phase::CaptureOnSecondTransition => "CaptureOnSecondTransition",
+                };
+                write!(
+                    f,
+                    "Motorola(Mode {{ polarity: {}, phase: {} }})",
+                    polarity, phase
+                )
+            }
+            FrameFormat::Ti => write!(f, "Ti"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     FrameFormat,
     Overrun,
     ModeFault,
+    /// The master clocked a frame before the slave sent a word for it
+    Underrun,
+    /// The received CRC doesn't match the calculated one
+    Crc,
 }
 
 /// RX token used for DMA transfers
-pub struct Rx<I>(PhantomData<I>);
+pub struct Rx<I>(pub(crate) PhantomData<I>);
 
 /// TX token used for DMA transfers
-pub struct Tx<I>(PhantomData<I>);
+pub struct Tx<I>(pub(crate) PhantomData<I>);
 
 /// A DMA transfer of the SPI peripheral
 ///
 /// Since DMA can send and receive at the same time, using two DMA transfers and
 /// two DMA streams, we need this type to represent this operation and wrap the
 /// underlying [`dma::Transfer`] instances.
-pub struct Transfer<Word: SupportedWordSize, I, P, Buffer, Rx: dma::Target, Tx: dma::Target, State>
-{
+pub struct Transfer<
+    Word: SupportedWordSize,
+    I,
+    P,
+    Buffer,
+    RxStream: dma::Stream,
+    TxStream: dma::Stream,
+    State,
+> {
     buffer: Pin<Buffer>,
     target: Spi<I, P, Enabled<Word>>,
-    rx: dma::Transfer<Rx, dma::PtrBuffer<Word>, State>,
-    tx: dma::Transfer<Tx, dma::PtrBuffer<Word>, State>,
+    rx: dma::Transfer<Rx<I>, RxStream, dma::PtrBuffer<Word>, State>,
+    tx: dma::Transfer<Tx<I>, TxStream, dma::PtrBuffer<Word>, State>,
     _state: State,
 }
 
-impl<Word, I, P, Buffer, Rx, Tx> Transfer<Word, I, P, Buffer, Rx, Tx, dma::Ready>
+impl<Word, I, P, Buffer, RxStream, TxStream>
+    Transfer<Word, I, P, Buffer, RxStream, TxStream, dma::Ready>
 where
-    Rx: dma::Target,
-    Tx: dma::Target,
+    Rx<I>: dma::Target<RxStream>,
+    Tx<I>: dma::Target<TxStream>,
+    RxStream: dma::Stream,
+    TxStream: dma::Stream,
     Word: SupportedWordSize,
 {
     /// Enables the given interrupts for this DMA transfer
@@ -622,8 +1143,8 @@ where
     /// DMA streams.
     pub fn enable_interrupts(
         &mut self,
-        rx_handle: &dma::Handle<Rx::Instance, state::Enabled>,
-        tx_handle: &dma::Handle<Tx::Instance, state::Enabled>,
+        rx_handle: &dma::Handle<RxStream::Instance, state::Enabled>,
+        tx_handle: &dma::Handle<TxStream::Instance, state::Enabled>,
         interrupts: dma::Interrupts,
     ) {
         self.rx.enable_interrupts(rx_handle, interrupts);
@@ -636,9 +1157,9 @@ where
     /// its type state set to indicate the transfer has been started.
     pub fn start(
         self,
-        rx_handle: &dma::Handle<Rx::Instance, state::Enabled>,
-        tx_handle: &dma::Handle<Tx::Instance, state::Enabled>,
-    ) -> Transfer<Word, I, P, Buffer, Rx, Tx, dma::Started> {
+        rx_handle: &dma::Handle<RxStream::Instance, state::Enabled>,
+        tx_handle: &dma::Handle<TxStream::Instance, state::Enabled>,
+    ) -> Transfer<Word, I, P, Buffer, RxStream, TxStream, dma::Started> {
         Transfer {
             buffer: self.buffer,
             target: self.target,
@@ -649,17 +1170,19 @@ where
     }
 }
 
-impl<Word, I, P, Buffer, Rx, Tx> Transfer<Word, I, P, Buffer, Rx, Tx, dma::Started>
+impl<Word, I, P, Buffer, RxStream, TxStream>
+    Transfer<Word, I, P, Buffer, RxStream, TxStream, dma::Started>
 where
-    Rx: dma::Target,
-    Tx: dma::Target,
+    I: Instance,
+    RxStream: dma::Stream,
+    TxStream: dma::Stream,
     Word: SupportedWordSize,
 {
     /// Checks whether the transfer is still ongoing
     pub fn is_active(
         &self,
-        rx_handle: &dma::Handle<Rx::Instance, state::Enabled>,
-        tx_handle: &dma::Handle<Tx::Instance, state::Enabled>,
+        rx_handle: &dma::Handle<RxStream::Instance, state::Enabled>,
+        tx_handle: &dma::Handle<TxStream::Instance, state::Enabled>,
     ) -> bool {
         self.rx.is_active(rx_handle) || self.tx.is_active(tx_handle)
     }
@@ -674,11 +1197,15 @@ where
     /// data buffer, the DMA stream, and the peripheral. Those have been moved
     /// into the `Transfer` instance to prevent concurrent access to them. This
     /// method returns those resources, so they can be used again.
+    ///
+    /// If the hardware CRC is enabled, the received CRC is checked once the
+    /// DMA streams are done. A mismatch is reported as
+    /// `TransferError::Spi(Error::Crc)`.
     pub fn wait(
         self,
-        rx_handle: &dma::Handle<Rx::Instance, state::Enabled>,
-        tx_handle: &dma::Handle<Tx::Instance, state::Enabled>,
-    ) -> WaitResult<Word, I, P, Rx, Tx, Buffer> {
+        rx_handle: &dma::Handle<RxStream::Instance, state::Enabled>,
+        tx_handle: &dma::Handle<TxStream::Instance, state::Enabled>,
+    ) -> WaitResult<Word, I, P, RxStream, TxStream, Buffer> {
         let (rx_res, rx_err) = match self.rx.wait(rx_handle) {
             Ok(res) => (res, None),
             Err((res, err)) => (res, Some(err)),
@@ -695,27 +1222,98 @@ where
             buffer: self.buffer,
         };
 
-        if let Some(err) = rx_err {
-            return Err((res, err));
+        if let Some(err) = rx_err.or(tx_err) {
+            return Err((res, TransferError::Dma(err)));
         }
-        if let Some(err) = tx_err {
-            return Err((res, err));
+        if let Err(err) = finish_crc(&res.target.spi) {
+            return Err((res, TransferError::Spi(err)));
         }
 
         Ok(res)
     }
 }
 
+#[cfg(feature = "async")]
+impl<Word, I, P, Buffer, RxStream, TxStream>
+    Transfer<Word, I, P, Buffer, RxStream, TxStream, dma::Started>
+where
+    I: Instance,
+    RxStream: dma::Stream,
+    TxStream: dma::Stream,
+    Word: SupportedWordSize,
+{
+    /// Waits asynchronously for the transfer to end
+    ///
+    /// This is the `async` version of [`Transfer::wait`]. See
+    /// [`dma::Transfer::wait_async`] for the requirements.
+    pub async fn wait_async(
+        self,
+        rx_handle: &dma::Handle<RxStream::Instance, state::Enabled>,
+        tx_handle: &dma::Handle<TxStream::Instance, state::Enabled>,
+    ) -> WaitResult<Word, I, P, RxStream, TxStream, Buffer> {
+        let (rx_res, rx_err) = match self.rx.wait_async(rx_handle).await {
+            Ok(res) => (res, None),
+            Err((res, err)) => (res, Some(err)),
+        };
+        let (tx_res, tx_err) = match self.tx.wait_async(tx_handle).await {
+            Ok(res) => (res, None),
+            Err((res, err)) => (res, Some(err)),
+        };
+
+        let res = TransferResources {
+            rx_stream: rx_res.stream,
+            tx_stream: tx_res.stream,
+            target: self.target,
+            buffer: self.buffer,
+        };
+
+        if let Some(err) = rx_err.or(tx_err) {
+            return Err((res, TransferError::Dma(err)));
+        }
+        if let Err(err) = finish_crc(&res.target.spi) {
+            return Err((res, TransferError::Spi(err)));
+        }
+
+        Ok(res)
+    }
+}
+
+/// Discards the CRC received at the end of a DMA transfer and checks it
+///
+/// The CRC is sent after the last word sent by the TX stream. The received
+/// CRC isn't read by the RX stream, but left in the receive FIFO.
+fn finish_crc<I: Instance>(spi: &I) -> Result<(), Error> {
+    if !spi.is_crc_enabled() {
+        return Ok(());
+    }
+
+    while spi.is_busy() {}
+    spi.flush();
+    spi.check_crc()
+}
+
+/// An error of a DMA transfer of the SPI peripheral
+#[derive(Debug)]
+pub enum TransferError {
+    /// The SPI transfer failed, e.g. because the received CRC doesn't match
+    Spi(Error),
+    /// The DMA transfer failed
+    Dma(dma::Error),
+}
+
 /// Returned by [`Transfer::wait`]
-pub type WaitResult<Word, I, P, Rx, Tx, Buffer> = Result<
-    TransferResources<Word, I, P, Rx, Tx, Buffer>,
-    (TransferResources<Word, I, P, Rx, Tx, Buffer>, dma::Error),
+pub type WaitResult<Word, I, P, RxStream, TxStream, Buffer> = Result<
+    TransferResources<Word, I, P, RxStream, TxStream, Buffer>,
+    (
+        TransferResources<Word, I, P, RxStream, TxStream, Buffer>,
+        TransferError,
+    ),
 >;
 
 /// The resources that an ongoing transfer needs exclusive access to
-pub struct TransferResources<Word, I, P, Rx: dma::Target, Tx: dma::Target, Buffer> {
-    pub rx_stream: Rx::Stream,
-    pub tx_stream: Tx::Stream,
+pub struct TransferResources<Word, I, P, RxStream, TxStream, Buffer> {
+    pub rx_stream: RxStream,
+    pub tx_stream: TxStream,
     pub target: Spi<I, P, Enabled<Word>>,
     pub buffer: Pin<Buffer>,
 }
@@ -723,10 +1321,8 @@ pub struct TransferResources<Word, I, P, Rx: dma::Target, Tx: dma::Target, Buffe
 // As `TransferResources` is used in the error variant of `Result`, it needs a
 // `Debug` implementation to enable stuff like `unwrap` and `expect`. This can't
 // be derived without putting requirements on the type arguments.
-impl<Word, I, P, Rx, Tx, Buffer> fmt::Debug for TransferResources<Word, I, P, Rx, Tx, Buffer>
-where
-    Rx: dma::Target,
-    Tx: dma::Target,
+impl<Word, I, P, RxStream, TxStream, Buffer> fmt::Debug
+    for TransferResources<Word, I, P, RxStream, TxStream, Buffer>
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "TransferResources {{ .. }}")
@@ -742,6 +1338,8 @@ pub struct Enabled<Word>(PhantomData<Word>);
 pub trait SupportedWordSize: dma::SupportedWordSize + private::Sealed {
     fn frxth() -> cr2::FRXTH_A;
     fn ds() -> cr2::DS_A;
+    /// Frame sizes that are transferred as this word
+    fn data_bits() -> RangeInclusive<u8>;
 }
 
 impl private::Sealed for u8 {}
@@ -753,6 +1351,10 @@ impl SupportedWordSize for u8 {
     fn ds() -> cr2::DS_A {
         cr2::DS_A::EIGHTBIT
     }
+
+    fn data_bits() -> RangeInclusive<u8> {
+        4..=8
+    }
 }
 
 impl private::Sealed for u16 {}
@@ -764,6 +1366,10 @@ impl SupportedWordSize for u16 {
     fn ds() -> cr2::DS_A {
         cr2::DS_A::SIXTEENBIT
     }
+
+    fn data_bits() -> RangeInclusive<u8> {
+        9..=16
+    }
 }
 
 mod private {
@@ -0,0 +1,92 @@
+use embedded_hal_one::spi::{ErrorKind, ErrorType, SpiBus};
+use nb::block;
+
+use super::{Enabled, Error, Instance, Pins, Spi, SupportedWordSize};
+
+impl embedded_hal_one::spi::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::FrameFormat => ErrorKind::FrameFormat,
+            Error::Overrun => ErrorKind::Overrun,
+            Error::ModeFault => ErrorKind::ModeFault,
+            Error::Underrun | Error::Crc => ErrorKind::Other,
+        }
+    }
+}
+
+impl<I, P, Word> ErrorType for Spi<I, P, Enabled<Word>>
+where
+    I: Instance,
+    P: Pins<I>,
+    Word: SupportedWordSize,
+{
+    type Error = Error;
+}
+
+impl<I, P, Word> embedded_hal_nb::spi::FullDuplex<Word> for Spi<I, P, Enabled<Word>>
+where
+    I: Instance,
+    P: Pins<I>,
+    Word: SupportedWordSize + Copy,
+{
+    fn read(&mut self) -> nb::Result<Word, Self::Error> {
+        self.spi.read()
+    }
+
+    fn write(&mut self, word: Word) -> nb::Result<(), Self::Error> {
+        self.spi.send(word)
+    }
+}
+
+impl<I, P, Word> SpiBus<Word> for Spi<I, P, Enabled<Word>>
+where
+    I: Instance,
+    P: Pins<I>,
+    Word: SupportedWordSize + Copy + Default,
+{
+    fn read(&mut self, words: &mut [Word]) -> Result<(), Self::Error> {
+        for word in words {
+            block!(self.spi.send(Word::default()))?;
+            *word = block!(self.spi.read())?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[Word]) -> Result<(), Self::Error> {
+        for word in words {
+            block!(self.spi.send(*word))?;
+            block!(self.spi.read::<Word>())?;
+        }
+
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [Word], write: &[Word]) -> Result<(), Self::Error> {
+        for i in 0..read.len().max(write.len()) {
+            let word = write.get(i).copied().unwrap_or_default();
+            block!(self.spi.send(word))?;
+
+            let word = block!(self.spi.read())?;
+            if let Some(dst) = read.get_mut(i) {
+                *dst = word;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [Word]) -> Result<(), Self::Error> {
+        for word in words {
+            block!(self.spi.send(*word))?;
+            *word = block!(self.spi.read())?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        // Every word sent has already been received back, so the bus is idle
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embedded_hal_async::spi::SpiBus;
+use nb::block;
+
+use super::{Enabled, Error, Instance, Pins, Spi, SupportedWordSize};
+
+/// Wakes the task waiting on the SPI instance `I`
+///
+/// Call this from the SPI interrupt handler when using the `async` APIs. The
+/// RX buffer not empty interrupt stays disabled until the waiting task has
+/// been polled again.
+pub fn on_interrupt<I: Instance>() {
+    I::on_interrupt();
+}
+
+impl<I, P, Word> Spi<I, P, Enabled<Word>>
+where
+    I: Instance,
+    P: Pins<I>,
+    Word: SupportedWordSize + Copy,
+{
+    /// Sends `word` and waits for the word received in exchange
+    async fn exchange(&mut self, word: Word) -> Result<Word, Error> {
+        // The transmit buffer is empty, as every word sent before has already
+        // been received back
+        block!(self.spi.send(word))?;
+
+        poll_fn(|cx| {
+            I::waker().register(cx.waker());
+
+            match self.spi.read() {
+                Ok(word) => Poll::Ready(Ok(word)),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    self.spi.listen_rxne(true);
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+/// Interrupt-driven implementation, word by word
+///
+/// Long transfers are more efficient with [`Spi::transfer_all_async`], which
+/// uses DMA.
+impl<I, P, Word> SpiBus<Word> for Spi<I, P, Enabled<Word>>
+where
+    I: Instance,
+    P: Pins<I>,
+    Word: SupportedWordSize + Copy + Default,
+{
+    async fn read(&mut self, words: &mut [Word]) -> Result<(), Self::Error> {
+        for word in words {
+            *word = self.exchange(Word::default()).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn write(&mut self, words: &[Word]) -> Result<(), Self::Error> {
+        for word in words {
+            self.exchange(*word).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn transfer(&mut self, read: &mut [Word], write: &[Word]) -> Result<(), Self::Error> {
+        for i in 0..read.len().max(write.len()) {
+            let word = self
+                .exchange(write.get(i).copied().unwrap_or_default())
+                .await?;
+            if let Some(dst) = read.get_mut(i) {
+                *dst = word;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [Word]) -> Result<(), Self::Error> {
+        for word in words {
+            *word = self.exchange(*word).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        // Every word sent has already been received back, so the bus is idle
+        Ok(())
+    }
+}
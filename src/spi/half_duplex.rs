@@ -0,0 +1,442 @@
+//! Half-duplex (3-wire) and receive-only SPI masters
+//!
+//! In both modes, the master generates the clock as long as the peripheral is
+//! enabled and receiving. The peripheral is only enabled during transfers,
+//! and disabled while the last frame is being received. Frames clocked in
+//! excess, e.g. because the CPU didn't keep up, are discarded.
+
+use core::{
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+};
+
+use as_slice::{AsMutSlice, AsSlice};
+use nb::block;
+
+use super::{
+    ClockDivider, Config, Error, FrameFormat, Instance, Lines, Miso, Mode, Mosi, Rx, Sck, Spi,
+    SupportedWordSize, Tx,
+};
+use crate::{
+    dma,
+    rcc::{Enable, RccBus},
+    state,
+};
+
+/// Implemented for all tuples of pins of a half-duplex master
+///
+/// The tuple is `(SCK, MOSI)`, MOSI being the bidirectional data line.
+pub trait HalfDuplexPins<I> {}
+
+impl<I, SCK, MOSI> HalfDuplexPins<I> for (SCK, MOSI)
+where
+    SCK: Sck<I>,
+    MOSI: Mosi<I>,
+{
+}
+
+/// Implemented for all tuples of pins of a receive-only master
+///
+/// The tuple is `(SCK, MISO)`.
+pub trait ReceiveOnlyPins<I> {}
+
+impl<I, SCK, MISO> ReceiveOnlyPins<I> for (SCK, MISO)
+where
+    SCK: Sck<I>,
+    MISO: Miso<I>,
+{
+}
+
+/// Indicates that the SPI peripheral is enabled as a half-duplex master
+pub struct HalfDuplex<Word>(PhantomData<Word>);
+
+/// Indicates that the SPI peripheral is enabled as a receive-only master
+pub struct ReceiveOnly<Word>(PhantomData<Word>);
+
+impl<I, P> Spi<I, P, state::Disabled>
+where
+    I: Instance + Enable,
+    P: HalfDuplexPins<I>,
+{
+    /// Initialize the SPI peripheral as a half-duplex master
+    ///
+    /// Data is sent and received on the MOSI pin, which is switched to an
+    /// input while receiving.
+    pub fn enable_half_duplex<Word>(
+        self,
+        apb: &mut <I as RccBus>::Bus,
+        clock_divider: ClockDivider,
+        mode: Mode,
+        config: Config,
+    ) -> Spi<I, P, HalfDuplex<Word>>
+    where
+        Word: SupportedWordSize,
+    {
+        // There is no NSS pin to pulse
+        assert!(!config.nss_pulse);
+
+        I::enable(apb);
+
+        self.spi.configure::<Word>(
+            clock_divider.into(),
+            FrameFormat::Motorola(mode),
+            true,
+            false,
+            &config,
+            Lines::HalfDuplex,
+        );
+
+        Spi {
+            spi: self.spi,
+            pins: self.pins,
+            _state: HalfDuplex(PhantomData),
+        }
+    }
+}
+
+impl<I, P> Spi<I, P, state::Disabled>
+where
+    I: Instance + Enable,
+    P: ReceiveOnlyPins<I>,
+{
+    /// Initialize the SPI peripheral as a receive-only master
+    pub fn enable_receive_only<Word>(
+        self,
+        apb: &mut <I as RccBus>::Bus,
+        clock_divider: ClockDivider,
+        mode: Mode,
+        config: Config,
+    ) -> Spi<I, P, ReceiveOnly<Word>>
+    where
+        Word: SupportedWordSize,
+    {
+        // There is no NSS pin to pulse
+        assert!(!config.nss_pulse);
+
+        I::enable(apb);
+
+        self.spi.configure::<Word>(
+            clock_divider.into(),
+            FrameFormat::Motorola(mode),
+            true,
+            false,
+            &config,
+            Lines::ReceiveOnly,
+        );
+
+        Spi {
+            spi: self.spi,
+            pins: self.pins,
+            _state: ReceiveOnly(PhantomData),
+        }
+    }
+}
+
+impl<I, P, Word> Spi<I, P, HalfDuplex<Word>>
+where
+    I: Instance,
+    Word: SupportedWordSize + Copy,
+{
+    /// Sends `words`, with the data line as an output
+    pub fn write(&mut self, words: &[Word]) -> Result<(), Error> {
+        self.spi.set_output(true);
+        self.spi.set_enabled(true);
+
+        let result = words
+            .iter()
+            .try_for_each(|&word| block!(self.spi.send(word)));
+        if result.is_ok() {
+            while self.spi.is_busy() {}
+        }
+
+        self.spi.set_enabled(false);
+        result
+    }
+
+    /// Receives enough words to fill `words`, with the data line as an input
+    pub fn read(&mut self, words: &mut [Word]) -> Result<(), Error> {
+        self.spi.set_output(false);
+        receive(&self.spi, words)
+    }
+
+    /// Sends the data in `buffer` using DMA
+    ///
+    /// DMA supports transfers up to 65535 words. Panics if the hardware CRC
+    /// is enabled.
+    pub fn write_all<B, S>(
+        self,
+        buffer: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> SimplexTransfer<I, P, HalfDuplex<Word>, Tx<I>, S, B, dma::Ready>
+    where
+        Tx<I>: dma::Target<S>,
+        S: dma::Stream,
+        B: Deref + 'static,
+        B::Target: AsSlice<Element = Word>,
+    {
+        assert!(!self.spi.is_crc_enabled());
+        self.spi.set_output(true);
+
+        // Safe, because the trait bounds on this method guarantee that
+        // `buffer` can be read from safely. The token is only created while
+        // `self` is moved into the transfer.
+        let transfer = unsafe {
+            dma::Transfer::new(
+                dma,
+                stream,
+                buffer,
+                Tx(PhantomData),
+                self.spi.dr_address(),
+                dma::Direction::MemoryToPeripheral,
+            )
+        };
+
+        SimplexTransfer {
+            target: self,
+            transfer,
+            receive: false,
+        }
+    }
+
+    /// Receives enough words to fill `buffer` using DMA
+    ///
+    /// DMA supports transfers up to 65535 words. The clock only stops once
+    /// the transfer is waited for, so the slave may see more clock cycles than
+    /// needed. Panics if the hardware CRC is enabled.
+    pub fn read_all<B, S>(
+        self,
+        buffer: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> SimplexTransfer<I, P, HalfDuplex<Word>, Rx<I>, S, B, dma::Ready>
+    where
+        Rx<I>: dma::Target<S>,
+        S: dma::Stream,
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = Word>,
+    {
+        self.spi.set_output(false);
+        read_all(self, buffer, dma, stream)
+    }
+}
+
+impl<I, P, Word> Spi<I, P, ReceiveOnly<Word>>
+where
+    I: Instance,
+    Word: SupportedWordSize,
+{
+    /// Receives enough words to fill `words`
+    pub fn read(&mut self, words: &mut [Word]) -> Result<(), Error> {
+        receive(&self.spi, words)
+    }
+
+    /// Receives enough words to fill `buffer` using DMA
+    ///
+    /// DMA supports transfers up to 65535 words. The clock only stops once
+    /// the transfer is waited for, so the slave may see more clock cycles than
+    /// needed. Panics if the hardware CRC is enabled.
+    pub fn read_all<B, S>(
+        self,
+        buffer: Pin<B>,
+        dma: &dma::Handle<S::Instance, state::Enabled>,
+        stream: S,
+    ) -> SimplexTransfer<I, P, ReceiveOnly<Word>, Rx<I>, S, B, dma::Ready>
+    where
+        Rx<I>: dma::Target<S>,
+        S: dma::Stream,
+        B: DerefMut + 'static,
+        B::Target: AsMutSlice<Element = Word>,
+    {
+        read_all(self, buffer, dma, stream)
+    }
+}
+
+/// Receives enough words to fill `words`, stopping the clock during the last
+/// one
+fn receive<I, Word>(spi: &I, words: &mut [Word]) -> Result<(), Error>
+where
+    I: Instance,
+    Word: SupportedWordSize,
+{
+    let n = words.len();
+    if n == 0 {
+        return Ok(());
+    }
+
+    // Enabling the peripheral starts the clock
+    spi.set_enabled(true);
+
+    let mut result = Ok(());
+    for (i, word) in words.iter_mut().enumerate() {
+        if i + 1 == n {
+            spi.set_enabled(false);
+        }
+
+        match block!(spi.read()) {
+            Ok(value) => *word = value,
+            Err(error) => {
+                result = Err(error);
+                break;
+            }
+        }
+    }
+
+    spi.set_enabled(false);
+    while spi.is_busy() {}
+    spi.flush();
+
+    result
+}
+
+fn read_all<I, P, M, Word, B, S>(
+    target: Spi<I, P, M>,
+    buffer: Pin<B>,
+    dma: &dma::Handle<S::Instance, state::Enabled>,
+    stream: S,
+) -> SimplexTransfer<I, P, M, Rx<I>, S, B, dma::Ready>
+where
+    I: Instance,
+    Rx<I>: dma::Target<S>,
+    S: dma::Stream,
+    Word: SupportedWordSize,
+    B: DerefMut + 'static,
+    B::Target: AsMutSlice<Element = Word>,
+{
+    assert!(!target.spi.is_crc_enabled());
+
+    // Safe, because the trait bounds on this function guarantee that `buffer`
+    // can be written to safely. The token is only created while `target` is
+    // moved into the transfer.
+    let transfer = unsafe {
+        dma::Transfer::new(
+            dma,
+            stream,
+            buffer,
+            Rx(PhantomData),
+            target.spi.dr_address(),
+            dma::Direction::PeripheralToMemory,
+        )
+    };
+
+    SimplexTransfer {
+        target,
+        transfer,
+        receive: true,
+    }
+}
+
+/// A DMA transfer of a half-duplex or receive-only SPI master
+///
+/// Wraps the underlying [`dma::Transfer`], and enables the peripheral for the
+/// duration of the transfer.
+pub struct SimplexTransfer<I, P, M, T, S: dma::Stream, B, State> {
+    target: Spi<I, P, M>,
+    transfer: dma::Transfer<T, S, B, State>,
+    receive: bool,
+}
+
+impl<I, P, M, T, S, B> SimplexTransfer<I, P, M, T, S, B, dma::Ready>
+where
+    I: Instance,
+    T: dma::Target<S>,
+    S: dma::Stream,
+    B: 'static,
+{
+    /// Enables the given interrupts for the DMA stream of this transfer
+    pub fn enable_interrupts(
+        &mut self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+        interrupts: dma::Interrupts,
+    ) {
+        self.transfer.enable_interrupts(handle, interrupts);
+    }
+
+    /// Start the transfer
+    ///
+    /// Starts the DMA stream, then enables the peripheral.
+    pub fn start(
+        self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> SimplexTransfer<I, P, M, T, S, B, dma::Started> {
+        let transfer = self.transfer.start(handle);
+        self.target.spi.set_enabled(true);
+
+        SimplexTransfer {
+            target: self.target,
+            transfer,
+            receive: self.receive,
+        }
+    }
+}
+
+impl<I, P, M, T, S, B> SimplexTransfer<I, P, M, T, S, B, dma::Started>
+where
+    I: Instance,
+    S: dma::Stream,
+{
+    /// Checks whether the transfer is still ongoing
+    pub fn is_active(&self, handle: &dma::Handle<S::Instance, state::Enabled>) -> bool {
+        self.transfer.is_active(handle)
+    }
+
+    /// Waits for the transfer to end
+    ///
+    /// This method will block if the transfer is still ongoing. Once the last
+    /// word has been transferred, the peripheral is disabled, and the DMA
+    /// stream, the buffer and the SPI peripheral are returned.
+    pub fn wait(
+        self,
+        handle: &dma::Handle<S::Instance, state::Enabled>,
+    ) -> SimplexWaitResult<I, P, M, S, B> {
+        let (res, error) = match self.transfer.wait(handle) {
+            Ok(res) => (res, None),
+            Err((res, error)) => (res, Some(error)),
+        };
+
+        let spi = &self.target.spi;
+        if self.receive {
+            // Stop the clock, and discard the frames clocked after the last
+            // one
+            spi.set_enabled(false);
+            while spi.is_busy() {}
+            spi.flush();
+        } else {
+            while spi.is_busy() {}
+            spi.set_enabled(false);
+        }
+
+        let res = SimplexResources {
+            stream: res.stream,
+            target: self.target,
+            buffer: res.buffer,
+        };
+
+        match error {
+            Some(error) => Err((res, error)),
+            None => Ok(res),
+        }
+    }
+}
+
+/// Returned by [`SimplexTransfer::wait`]
+pub type SimplexWaitResult<I, P, M, S, B> =
+    Result<SimplexResources<I, P, M, S, B>, (SimplexResources<I, P, M, S, B>, dma::Error)>;
+
+/// The resources that an ongoing [`SimplexTransfer`] needs exclusive access to
+pub struct SimplexResources<I, P, M, S, B> {
+    pub stream: S,
+    pub target: Spi<I, P, M>,
+    pub buffer: Pin<B>,
+}
+
+// As `SimplexResources` is used in the error variant of `Result`, it needs a
+// `Debug` implementation to enable stuff like `unwrap` and `expect`. This can't
+// be derived without putting requirements on the type arguments.
+impl<I, P, M, S, B> fmt::Debug for SimplexResources<I, P, M, S, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SimplexResources {{ .. }}")
+    }
+}
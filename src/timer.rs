@@ -1,4 +1,20 @@
 //! Timers
+//!
+//! [`Timer`] is a periodic count down timer that can also generate single
+//! delays in one-pulse mode, started either by software or, on the timers
+//! with a slave mode controller, by an edge on a trigger input.
+//!
+//! [`Counter`] turns the 32-bit timers TIM2 and TIM5 into a free-running
+//! counter returning [`fugit`] instants, which can back an RTIC `Monotonic`
+//! when the `rtic` feature is enabled.
+//!
+//! ```rust
+//! let mut counter = CounterUs::tim2(dp.TIM2, clocks, &mut rcc.apb1);
+//!
+//! let start = counter.now();
+//! // ...
+//! let elapsed = counter.now() - start;
+//! ```
 
 use crate::hal::timer::{Cancel, CountDown, Periodic};
 use crate::pac::{
@@ -6,7 +22,7 @@ use crate::pac::{
 };
 use crate::rcc::{Clocks, Enable, RccBus, Reset};
 use cast::{u16, u32};
-use fugit::{HertzU32 as Hertz, RateExtU32};
+use fugit::{HertzU32 as Hertz, RateExtU32, TimerInstantU32, TimerInstantU64};
 use nb;
 use void::Void;
 
@@ -24,6 +40,40 @@ pub enum Event {
     TimeOut,
 }
 
+/// Edge of a trigger input starting a one-pulse cycle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerEdge {
+    Rising,
+    Falling,
+}
+
+/// External trigger starting a one-pulse cycle (TS)
+///
+/// The corresponding pin must be configured in the right alternate mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// Filtered timer input 1 (TI1FP1), on the channel 1 pin
+    Ti1(TriggerEdge),
+    /// Filtered timer input 2 (TI2FP2), on the channel 2 pin
+    Ti2(TriggerEdge),
+    /// External trigger input (ETRF), on the ETR pin
+    Etr(TriggerEdge),
+}
+
+impl Trigger {
+    /// Returns the SMCR value selecting this trigger in trigger mode
+    fn smcr_bits(self) -> u32 {
+        // SMS = 110: the counter starts on a rising edge of the trigger
+        let (ts, etp) = match self {
+            Trigger::Ti1(_) => (0b101, false),
+            Trigger::Ti2(_) => (0b110, false),
+            Trigger::Etr(edge) => (0b111, edge == TriggerEdge::Falling),
+        };
+
+        ((etp as u32) << 15) | (ts << 4) | 0b110
+    }
+}
+
 /// Timer errors
 #[derive(Debug, PartialEq)]
 pub enum Error {
@@ -45,25 +95,8 @@ macro_rules! hal {
                     T: Into<Hertz>,
                 {
                     self.disable();
-
-                    self.timeout = timeout.into();
-                    let frequency = self.timeout;
-                    let ticks = self.clock / frequency;
-                    let psc = u16((ticks - 1) / (1 << 16)).unwrap();
-
-                    self.tim.psc.write(|w| unsafe { w.psc().bits(psc) });
-
-                    let arr = u16(ticks / u32(psc + 1)).unwrap();
-
-                    self.tim.arr.write(|w| unsafe { w.bits(u32(arr)) });
-
-                    // Trigger an update event to load the prescaler value to the clock
-                    self.tim.egr.write(|w| w.ug().set_bit());
-                    // The above line raises an update event which will indicate
-                    // that the timer is already finished. Since this is not the case,
-                    // it should be cleared
-                    self.tim.sr.modify(|_, w| w.uif().clear_bit());
-
+                    self.set_one_pulse(false);
+                    self.configure(timeout.into());
                     self.enable();
                 }
 
@@ -113,6 +146,27 @@ macro_rules! hal {
                     timer
                 }
 
+                /// Starts a single count down in one-pulse mode
+                ///
+                /// The counter stops by itself once `timeout` has elapsed, which
+                /// [`CountDown::wait`] and [`Event::TimeOut`] report as usual.
+                /// Calling [`CountDown::start`] goes back to periodic mode.
+                #[allow(unused_unsafe)]
+                pub fn start_one_pulse<T>(&mut self, timeout: T)
+                where
+                    T: Into<Hertz>,
+                {
+                    self.disable();
+                    self.set_one_pulse(true);
+                    self.configure(timeout.into());
+                    self.enable();
+                }
+
+                /// Returns `true` while the counter is running
+                pub fn is_running(&self) -> bool {
+                    self.tim.cr1.read().cen().is_enabled()
+                }
+
                 /// Starts listening for an `event`
                 pub fn listen(&mut self, event: Event) {
                     match event {
@@ -153,6 +207,35 @@ macro_rules! hal {
                     self.tim
                 }
 
+                /// Loads the prescaler and auto-reload values for `timeout`.
+                #[allow(unused_unsafe)]
+                fn configure(&mut self, timeout: Hertz) {
+                    self.timeout = timeout;
+                    let frequency = self.timeout;
+                    let ticks = self.clock / frequency;
+                    let psc = u16((ticks - 1) / (1 << 16)).unwrap();
+
+                    self.tim.psc.write(|w| unsafe { w.psc().bits(psc) });
+
+                    let arr = u16(ticks / u32(psc + 1)).unwrap();
+
+                    self.tim.arr.write(|w| unsafe { w.bits(u32(arr)) });
+
+                    // Trigger an update event to load the prescaler value to the clock
+                    self.tim.egr.write(|w| w.ug().set_bit());
+                    // The above line raises an update event which will indicate
+                    // that the timer is already finished. Since this is not the case,
+                    // it should be cleared
+                    self.tim.sr.modify(|_, w| w.uif().clear_bit());
+                }
+
+                /// Enables or disables one-pulse mode (OPM).
+                fn set_one_pulse(&mut self, one_pulse: bool) {
+                    self.tim.cr1.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(1 << 3)) | ((one_pulse as u32) << 3))
+                    });
+                }
+
                 /// Enables the counter.
                 fn enable(&mut self) {
                     self.tim.cr1.modify(|_, w| w.cen().set_bit());
@@ -184,3 +267,275 @@ hal! {
     TIM10: (tim10, timclk2),
     TIM11: (tim11, timclk2),
 }
+
+macro_rules! one_pulse {
+    ($($TIM:ident,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Arms the timer to count down `timeout` once on every `trigger`
+                ///
+                /// The slave mode controller starts the counter on the trigger
+                /// edge, and the counter stops by itself once `timeout` has
+                /// elapsed, ready for the next trigger. Completion is reported
+                /// by [`CountDown::wait`] and [`Event::TimeOut`].
+                #[allow(unused_unsafe)]
+                pub fn arm_one_pulse<T>(&mut self, timeout: T, trigger: Trigger)
+                where
+                    T: Into<Hertz>,
+                {
+                    self.disable();
+                    self.set_one_pulse(true);
+                    self.configure(timeout.into());
+
+                    // Map IC1 on TI1 and IC2 on TI2 (CCxS = 01), selecting the
+                    // active edge with CCxP
+                    match trigger {
+                        Trigger::Ti1(edge) => {
+                            self.tim.ccmr1_input().modify(|r, w| unsafe { w.bits((r.bits() & !0b11) | 0b01) });
+                            self.tim.ccer.modify(|r, w| unsafe {
+                                w.bits((r.bits() & !0b1010) | (((edge == TriggerEdge::Falling) as u32) << 1))
+                            });
+                        }
+                        Trigger::Ti2(edge) => {
+                            self.tim.ccmr1_input().modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << 8)) | (0b01 << 8)) });
+                            self.tim.ccer.modify(|r, w| unsafe {
+                                w.bits((r.bits() & !0b1010_0000) | (((edge == TriggerEdge::Falling) as u32) << 5))
+                            });
+                        }
+                        Trigger::Etr(_) => {}
+                    }
+
+                    self.tim.smcr.write(|w| unsafe { w.bits(trigger.smcr_bits()) });
+                }
+
+                /// Disarms the trigger set up by [`Self::arm_one_pulse`] and
+                /// stops the counter
+                pub fn disarm_one_pulse(&mut self) {
+                    self.disable();
+                    self.tim.smcr.write(|w| unsafe { w.bits(0) });
+                    self.set_one_pulse(false);
+                }
+            }
+        )+
+    }
+}
+
+one_pulse! {
+    TIM1,
+    TIM2,
+    TIM3,
+    TIM4,
+    TIM5,
+    TIM8,
+}
+
+/// Free-running 32-bit counter ticking at `FREQ` Hz
+///
+/// The counter wraps after 2^32 ticks. [`Counter::now`] returns instants that
+/// compare correctly across a single wrap, while [`Counter::now_u64`] keeps
+/// counting overflows as long as they are noticed at least once per counter
+/// period, either by calling it or [`Counter::handle_overflow`] from the
+/// update interrupt.
+pub struct Counter<TIM, const FREQ: u32> {
+    tim: TIM,
+    overflows: u32,
+}
+
+/// Free-running 32-bit counter ticking every microsecond
+pub type CounterUs<TIM> = Counter<TIM, 1_000_000>;
+
+/// Instant of a [`Counter`] ticking at `FREQ` Hz
+pub type Instant<const FREQ: u32> = TimerInstantU32<FREQ>;
+
+/// Computes the prescaler dividing `clock` down to `freq`
+///
+/// Returns `None` if `clock` isn't a multiple of `freq` or the division is out
+/// of the prescaler range.
+fn exact_psc(clock: Hertz, freq: u32) -> Option<u16> {
+    if freq == 0 || clock.raw() % freq != 0 {
+        return None;
+    }
+
+    u16(clock.raw() / freq - 1).ok()
+}
+
+/// Extends a counter value to 64 bits, accounting for a pending overflow
+///
+/// `value` must be read before `pending`, so a pending overflow that isn't
+/// included in `value` is always noticed. A small value was then read after
+/// the pending overflow and a large one before it.
+fn extend(value: u32, overflows: u32, pending: bool) -> u64 {
+    let overflows = if pending && value < (1 << 31) {
+        u64::from(overflows) + 1
+    } else {
+        u64::from(overflows)
+    };
+
+    (overflows << 32) | u64::from(value)
+}
+
+macro_rules! counter {
+    ($($TIM:ident: ($tim:ident, $timclk:ident),)+) => {
+        $(
+            impl<const FREQ: u32> Counter<$TIM, FREQ> {
+                /// Configures a 32-bit TIM peripheral as a free-running counter
+                ///
+                /// # Panics
+                ///
+                /// Panics if the timer clock can't be divided down to exactly
+                /// `FREQ`.
+                #[allow(unused_unsafe)]
+                pub fn $tim(tim: $TIM, clocks: Clocks, apb: &mut <$TIM as RccBus>::Bus) -> Self {
+                    // enable and reset peripheral to a clean slate state
+                    <$TIM>::enable(apb);
+                    <$TIM>::reset(apb);
+
+                    let psc = exact_psc(clocks.$timclk(), FREQ)
+                        .expect("timer clock is not a multiple of the counter frequency");
+
+                    tim.psc.write(|w| unsafe { w.bits(u32::from(psc)) });
+                    tim.arr.write(|w| unsafe { w.bits(u32::MAX) });
+
+                    // Only counter overflows set the update flag (URS)
+                    tim.cr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << 2)) });
+
+                    // Trigger an update event to load the prescaler value to the clock
+                    tim.egr.write(|w| w.ug().set_bit());
+                    tim.sr.write(|w| unsafe { w.bits(0) });
+                    tim.cr1.modify(|_, w| w.cen().set_bit());
+
+                    Counter { tim, overflows: 0 }
+                }
+
+                /// Returns the current instant
+                pub fn now(&self) -> Instant<FREQ> {
+                    Instant::from_ticks(self.tim.cnt.read().bits())
+                }
+
+                /// Returns the current instant, extended to 64 bits with the
+                /// counter overflows
+                pub fn now_u64(&mut self) -> TimerInstantU64<FREQ> {
+                    // CNT is read first, see `extend`
+                    let value = self.tim.cnt.read().bits();
+                    let pending = self.tim.sr.read().bits() & 1 != 0;
+                    let ticks = extend(value, self.overflows, pending);
+                    if pending {
+                        self.handle_overflow();
+                    }
+
+                    TimerInstantU64::from_ticks(ticks)
+                }
+
+                /// Accounts for a counter overflow
+                ///
+                /// Call this from the timer interrupt handler when listening for
+                /// [`Event::TimeOut`].
+                pub fn handle_overflow(&mut self) {
+                    if self.tim.sr.read().bits() & 1 != 0 {
+                        self.tim.sr.write(|w| unsafe { w.bits(!1) });
+                        self.overflows = self.overflows.wrapping_add(1);
+                    }
+                }
+
+                /// Starts listening for counter overflows
+                pub fn listen(&mut self, event: Event) {
+                    match event {
+                        Event::TimeOut => {
+                            self.tim.dier.modify(|r, w| unsafe { w.bits(r.bits() | 1) });
+                        }
+                    }
+                }
+
+                /// Stops listening for counter overflows
+                pub fn unlisten(&mut self, event: Event) {
+                    match event {
+                        Event::TimeOut => {
+                            self.tim.dier.modify(|r, w| unsafe { w.bits(r.bits() & !1) });
+                        }
+                    }
+                }
+
+                /// Stops the counter and releases the TIM peripheral
+                pub fn free(self) -> $TIM {
+                    self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+
+                    self.tim
+                }
+            }
+
+            #[cfg(feature = "rtic")]
+            impl<const FREQ: u32> rtic_monotonic::Monotonic for Counter<$TIM, FREQ> {
+                type Instant = Instant<FREQ>;
+                type Duration = fugit::TimerDurationU32<FREQ>;
+
+                fn now(&mut self) -> Self::Instant {
+                    <Counter<$TIM, FREQ>>::now(self)
+                }
+
+                fn zero() -> Self::Instant {
+                    Instant::from_ticks(0)
+                }
+
+                unsafe fn reset(&mut self) {
+                    // Overflows and compare matches on channel 1 raise the
+                    // timer interrupt
+                    self.tim.dier.modify(|r, w| w.bits(r.bits() | 0b11));
+                    self.tim.cnt.write(|w| w.bits(0));
+                    self.tim.sr.write(|w| w.bits(0));
+                    self.overflows = 0;
+                }
+
+                #[allow(unused_unsafe)]
+                fn set_compare(&mut self, instant: Self::Instant) {
+                    self.tim.ccr1.write(|w| unsafe { w.bits(instant.ticks()) });
+                }
+
+                fn clear_compare_flag(&mut self) {
+                    self.tim.sr.write(|w| unsafe { w.bits(!(1 << 1)) });
+                }
+
+                fn on_interrupt(&mut self) {
+                    self.handle_overflow();
+                }
+            }
+        )+
+    }
+}
+
+counter! {
+    TIM2: (tim2, timclk1),
+    TIM5: (tim5, timclk1),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_psc() {
+        assert_eq!(exact_psc(Hertz::MHz(216), 1_000_000), Some(215));
+        assert_eq!(exact_psc(Hertz::MHz(216), 216_000_000), Some(0));
+        assert_eq!(exact_psc(Hertz::MHz(216), 7_000_000), None);
+        assert_eq!(exact_psc(Hertz::MHz(216), 1_000), None);
+    }
+
+    #[test]
+    fn test_extend() {
+        assert_eq!(extend(100, 0, false), 100);
+        assert_eq!(extend(100, 2, false), (2 << 32) + 100);
+        assert_eq!(extend(100, 2, true), (3 << 32) + 100);
+        assert_eq!(extend(u32::MAX, 2, true), (2 << 32) + u64::from(u32::MAX));
+
+        // The counter wrapped between reading CNT and SR
+        assert_eq!(extend(0xffff_fff0, 2, true), (2 << 32) + 0xffff_fff0);
+        // The counter wrapped before reading CNT
+        assert_eq!(extend(3, 2, true), (3 << 32) + 3);
+    }
+
+    #[test]
+    fn test_trigger_smcr_bits() {
+        assert_eq!(Trigger::Ti1(TriggerEdge::Rising).smcr_bits(), 0b101_0110);
+        assert_eq!(Trigger::Ti2(TriggerEdge::Falling).smcr_bits(), 0b110_0110);
+        assert_eq!(Trigger::Etr(TriggerEdge::Falling).smcr_bits(), (1 << 15) | 0b111_0110);
+    }
+}